@@ -1,51 +1,244 @@
-use crate::code_queries::{CodeQueries, QueryResults};
-use std::collections::HashMap;
+use crate::code_queries::{CodeQueries, QueryKind, QueryResults};
+use crate::examples;
+use crate::sarif;
+use crate::search::{LanguageBreakdown, SearchOptions, StringMode, FILES_SUFFIX, STRINGS_SUFFIX};
+use std::collections::BTreeSet;
+use std::future::Future;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use tokio::fs::File;
 use tokio::io::{AsyncWriteExt, BufWriter};
 
+/// Collects the results of all repos of a run, and writes them into the out file, with a
+/// column per query and a row per repo.
 #[derive(Debug)]
-pub struct Aggregator<'a> {
-    queries: &'a CodeQueries,
-    results: HashMap<String, HashMap<String, usize>>,
+pub struct Aggregator {
+    columns: Vec<String>,
+    by_language: Option<LanguageBreakdown>,
+    extra_columns: Vec<String>,
+    results: Vec<QueryResults>,
+    /// The repos that weren't searched, with their status, like "timeout".
+    unsearched: Vec<(String, &'static str)>,
 }
 
-impl<'a> Aggregator<'a> {
-    pub fn new(queries: &'a CodeQueries) -> Self {
+impl Aggregator {
+    /// An aggregator with the columns of `queries`, and of what `options` adds to them.
+    pub fn new(queries: &CodeQueries, options: &SearchOptions) -> Self {
+        let mut columns = Vec::new();
+        for query in queries.iter() {
+            let mut query_columns = vec![query.name.clone()];
+
+            if options.strings == StringMode::Separate && matches!(query.kind, QueryKind::Regex(_))
+            {
+                query_columns.push(format!("{}{STRINGS_SUFFIX}", query.name));
+            }
+
+            if options.file_counts {
+                let file_columns: Vec<String> = query_columns
+                    .iter()
+                    .map(|column| format!("{column}{FILES_SUFFIX}"))
+                    .collect();
+                query_columns.extend(file_columns);
+            }
+
+            columns.extend(query_columns);
+        }
+
         Self {
-            queries,
-            results: HashMap::new(),
+            columns,
+            by_language: options.by_language,
+            extra_columns: Vec::new(),
+            results: Vec::new(),
+            unsearched: Vec::new(),
         }
     }
 
     pub fn add(&mut self, results: QueryResults) {
-        let identifier = format!("{}/{}", results.repo_owner, results.repo_name);
-        self.results.insert(identifier, results.inner);
+        for (column, _) in results.extra.iter() {
+            if !self.extra_columns.contains(column) {
+                self.extra_columns.push(column.clone());
+            }
+        }
+
+        self.results.push(results);
+    }
+
+    /// Add a repo that wasn't searched, to be written with its status and without counts.
+    pub fn add_status(&mut self, repo: String, status: &'static str) {
+        self.unsearched.push((repo, status));
+    }
+
+    pub async fn write_examples(&self, path: &Path) -> io::Result<()> {
+        examples::write(path, &self.results).await
+    }
+
+    pub async fn write_sarif(&self, path: &Path) -> io::Result<()> {
+        sarif::write(path, &self.results).await
     }
 
-    pub async fn write(self, path: &Path) -> io::Result<()> {
+    /// All languages that any match was found in, sorted.
+    fn languages(&self) -> BTreeSet<&str> {
+        self.results
+            .iter()
+            .flat_map(|results| results.by_language.keys())
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Write match counts by language in long format, i.e. one row per repo, language, and
+    /// query.
+    pub async fn write_language_breakdown(&self, path: &Path) -> io::Result<()> {
         let f = File::create(path).await?;
         let mut writer = BufWriter::new(f);
 
+        writer.write_all(b"repo,language,query,count\n").await?;
+
+        for results in self.results.iter() {
+            let mut languages: Vec<&String> = results.by_language.keys().collect();
+            languages.sort();
+
+            for language in languages {
+                let matches = &results.by_language[language];
+                for column in self.columns.iter() {
+                    if let Some(count) = matches.get(column) {
+                        let row = format!(
+                            "{}/{},{},{},{}\n",
+                            results.repo_owner, results.repo_name, language, column, count
+                        );
+                        writer.write_all(row.as_bytes()).await?;
+                    }
+                }
+            }
+        }
+
+        writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// The results as a table: the columns after the repo and its status, and a row per
+    /// repo, with the repos that weren't searched last.
+    pub fn table(self) -> (Vec<String>, Vec<Row>) {
+        let languages = match self.by_language {
+            Some(LanguageBreakdown::Columns) => self.languages(),
+            _ => BTreeSet::new(),
+        };
+
+        let mut columns = self.columns.clone();
+        for language in languages.iter() {
+            for column in self.columns.iter() {
+                columns.push(format!("{column} [{language}]"));
+            }
+        }
+        columns.extend(self.extra_columns.iter().cloned());
+
+        let mut rows = Vec::with_capacity(self.results.len() + self.unsearched.len());
+        for results in self.results.iter() {
+            let mut values = Vec::with_capacity(columns.len());
+            for column in self.columns.iter() {
+                let count = results.inner.get(column).unwrap_or(&0);
+                values.push(count.to_string());
+            }
+
+            for language in languages.iter() {
+                let matches = results.by_language.get(*language);
+                for column in self.columns.iter() {
+                    let count = matches.and_then(|m| m.get(column)).unwrap_or(&0);
+                    values.push(count.to_string());
+                }
+            }
+
+            for column in self.extra_columns.iter() {
+                let value = results
+                    .extra
+                    .iter()
+                    .find(|(name, _)| name == column)
+                    .map(|(_, value)| value.clone())
+                    .unwrap_or_default();
+                values.push(value);
+            }
+
+            rows.push(Row {
+                repo: format!("{}/{}", results.repo_owner, results.repo_name),
+                status: "ok",
+                values,
+            });
+        }
+
+        // repos that failed or were skipped, with empty counts
+        for (repo, status) in self.unsearched.iter() {
+            rows.push(Row {
+                repo: repo.clone(),
+                status,
+                values: vec![String::new(); columns.len()],
+            });
+        }
+
+        (columns, rows)
+    }
+
+    /// Write the results into `sink`.
+    pub async fn write(self, sink: &mut dyn OutputSink) -> io::Result<()> {
+        let (columns, rows) = self.table();
+        sink.write(&columns, &rows).await
+    }
+}
+
+/// A repo's row of the results.
+#[derive(Debug, Clone)]
+pub struct Row {
+    /// The repo's owner and name, like "owner/name".
+    pub repo: String,
+    /// "ok" for repos that were searched, or why they weren't, like "timeout".
+    pub status: &'static str,
+    /// A value for each column, empty for the repos that weren't searched.
+    pub values: Vec<String>,
+}
+
+/// Where the results of a run are written to, once all repos are done.
+pub trait OutputSink: Send {
+    /// Write the results: the columns after the repo and its status, and the rows.
+    fn write<'a>(
+        &'a mut self,
+        columns: &'a [String],
+        rows: &'a [Row],
+    ) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send + 'a>>;
+}
+
+/// Writes the results into a CSV file, the out file by default.
+#[derive(Debug, Clone)]
+pub struct CsvSink {
+    path: PathBuf,
+}
+
+impl CsvSink {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    async fn write_csv(&self, columns: &[String], rows: &[Row]) -> io::Result<()> {
+        let f = File::create(&self.path).await?;
+        let mut writer = BufWriter::new(f);
+
         // header
-        writer.write_all("repo".as_bytes()).await?;
-        for query in self.queries.iter() {
+        writer.write_all("repo,status".as_bytes()).await?;
+        for column in columns.iter() {
             writer.write_u8(b',').await?;
-            writer.write_all(query.as_bytes()).await?
+            writer.write_all(column.as_bytes()).await?
         }
-
         writer.write_u8(b'\n').await?;
 
         // per repo results
-        for (repo, results) in self.results.iter() {
+        for row in rows.iter() {
+            let repo = format!("{},{}", row.repo, row.status);
             writer.write_all(repo.as_bytes()).await?;
 
-            for query in self.queries.iter() {
+            for value in row.values.iter() {
                 writer.write_u8(b',').await?;
-
-                let count = results.get(query).unwrap_or(&0);
-                writer.write_all(count.to_string().as_bytes()).await?;
+                writer.write_all(value.as_bytes()).await?;
             }
 
             writer.write_u8(b'\n').await?;
@@ -56,3 +249,13 @@ impl<'a> Aggregator<'a> {
         Ok(())
     }
 }
+
+impl OutputSink for CsvSink {
+    fn write<'a>(
+        &'a mut self,
+        columns: &'a [String],
+        rows: &'a [Row],
+    ) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send + 'a>> {
+        Box::pin(self.write_csv(columns, rows))
+    }
+}
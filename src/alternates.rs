@@ -0,0 +1,178 @@
+use crate::duration::HumanDuration;
+use crate::forge::{remote_url, url_key, CloneProtocol, Repo};
+use crate::git;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use url::Url;
+
+/// Where the object stores are kept, in the target directory.
+pub const STORES_DIR: &str = ".objects";
+
+/// Whether an object store could be cloned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Failed,
+    /// It was cloned by an earlier run.
+    Existing,
+    /// It was cloned by this run.
+    Cloned,
+}
+
+/// A bare clone of a repo that its forks, and the repo itself, are cloned with as a
+/// reference, so that the objects they have in common are stored only once. Clones refer
+/// to it through git's alternates, so it must be kept as long as they are.
+#[derive(Debug, Clone)]
+pub struct ObjectStore {
+    path: PathBuf,
+    upstream: Url,
+    /// Set once cloning the store was tried.
+    state: Arc<OnceCell<State>>,
+}
+
+impl ObjectStore {
+    /// Clone the store, unless an earlier run or another repo did already. A store that
+    /// can't be cloned is logged, and left out.
+    pub async fn prepare(&self, protocol: CloneProtocol, timeout: Option<HumanDuration>) {
+        self.state
+            .get_or_init(|| async {
+                match self.clone_store(protocol, timeout).await {
+                    Ok(state) => state,
+                    Err(e) => {
+                        log::warn!("Cloning without a shared object store: {e}");
+                        State::Failed
+                    }
+                }
+            })
+            .await;
+    }
+
+    async fn clone_store(
+        &self,
+        protocol: CloneProtocol,
+        timeout: Option<HumanDuration>,
+    ) -> Result<State> {
+        if tokio::fs::try_exists(&self.path).await? {
+            return Ok(State::Existing);
+        }
+        log::info!("Cloning {} as a shared object store", self.upstream);
+
+        // cloned next to where it goes, so that an interrupted clone isn't mistaken for it
+        let partial = self.path.with_extension("partial");
+        if tokio::fs::try_exists(&partial).await? {
+            tokio::fs::remove_dir_all(&partial).await?;
+        }
+        let mut command = git::command();
+        // git can't refer to the objects of a shallow clone
+        command.arg("clone").arg("--bare").arg("--quiet");
+        let output = command
+            .arg(remote_url(&self.upstream, protocol))
+            .arg(partial.as_os_str())
+            .stdout(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout.duration(), output)
+                .await
+                .map_err(|_| anyhow!("Cloning {} took longer than {timeout}", self.upstream))??,
+            None => output.await?,
+        };
+
+        if !output.status.success() {
+            let _ = tokio::fs::remove_dir_all(&partial).await;
+            return Err(anyhow!(
+                "Failed to clone {}: {}",
+                self.upstream,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        tokio::fs::rename(&partial, &self.path).await?;
+        Ok(State::Cloned)
+    }
+
+    /// The store to clone with, if it's ready.
+    pub fn path(&self) -> Option<&Path> {
+        let ready = self
+            .state
+            .get()
+            .is_some_and(|state| *state != State::Failed);
+        ready.then_some(self.path.as_path())
+    }
+}
+
+/// The object stores of a run, one per family of repos, i.e. a repo and its forks.
+#[derive(Debug)]
+pub struct ObjectStores {
+    dir: PathBuf,
+    /// By the key of the repo the family is named after.
+    stores: HashMap<String, ObjectStore>,
+}
+
+impl ObjectStores {
+    pub fn new(target_dir: &Path) -> Self {
+        Self {
+            dir: target_dir.join(STORES_DIR),
+            stores: HashMap::new(),
+        }
+    }
+
+    /// Plan which of a page of repos share an object store: those of the same family, if
+    /// there are several of them, or if the family has a store already.
+    pub fn plan(&mut self, repos: &[Repo]) -> Vec<Option<ObjectStore>> {
+        let families: Vec<&Url> = repos
+            .iter()
+            .map(|repo| repo.meta.parent.as_ref().unwrap_or(&repo.clone_url))
+            .collect();
+        let mut sizes: HashMap<String, usize> = HashMap::new();
+        for family in families.iter() {
+            *sizes.entry(url_key(family)).or_default() += 1;
+        }
+
+        let local = |repo: &Repo| repo.clone_url.scheme() == "file" || repo.swh_directory.is_some();
+        repos
+            .iter()
+            .zip(families)
+            .map(|(repo, family)| {
+                let key = url_key(family);
+                if local(repo) || (sizes[&key] < 2 && !self.stores.contains_key(&key)) {
+                    return None;
+                }
+                let store = self
+                    .stores
+                    .entry(key.clone())
+                    .or_insert_with(|| ObjectStore {
+                        path: self.dir.join(format!("{key}.git")),
+                        upstream: family.clone(),
+                        state: Arc::new(OnceCell::new()),
+                    });
+                Some(store.clone())
+            })
+            .collect()
+    }
+
+    /// Remove the stores cloned by this run, once no clones refer to them anymore, and the
+    /// directories they were in, if they are empty. Those of earlier runs may be referred to
+    /// by the clones those kept.
+    pub async fn remove(&mut self) {
+        for (_, store) in self.stores.drain() {
+            if store.state.get() != Some(&State::Cloned) {
+                continue;
+            }
+            log::debug!("Removing {:?}", store.path);
+            if let Err(e) = tokio::fs::remove_dir_all(&store.path).await {
+                log::warn!("Failed to remove {:?}: {e}", store.path);
+            }
+
+            let mut dir = store.path.parent();
+            while let Some(parent) = dir.filter(|dir| dir.starts_with(&self.dir)) {
+                if tokio::fs::remove_dir(parent).await.is_err() {
+                    break;
+                }
+                dir = parent.parent();
+            }
+        }
+    }
+}
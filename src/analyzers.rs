@@ -0,0 +1,206 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::process::Command;
+
+/// A linter that is run in each clone of its language, whose warnings are counted by lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Analyzer {
+    /// cargo clippy, in repos with a Cargo.toml.
+    Clippy,
+    /// ESLint, in repos with a package.json.
+    Eslint,
+}
+
+impl FromStr for Analyzer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clippy" => Ok(Analyzer::Clippy),
+            "eslint" => Ok(Analyzer::Eslint),
+            _ => Err(format!("unknown analyzer: {s}")),
+        }
+    }
+}
+
+impl Analyzer {
+    /// The analyzer's name, which its columns start with.
+    pub fn name(self) -> &'static str {
+        match self {
+            Analyzer::Clippy => "clippy",
+            Analyzer::Eslint => "eslint",
+        }
+    }
+
+    /// The file that marks a repo the analyzer can run in.
+    fn manifest(self) -> &'static str {
+        match self {
+            Analyzer::Clippy => "Cargo.toml",
+            Analyzer::Eslint => "package.json",
+        }
+    }
+}
+
+/// The analyzers to run, from a comma-separated list like "clippy,eslint".
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Analyzers(pub Vec<Analyzer>);
+
+impl FromStr for Analyzers {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut analyzers = Vec::new();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let analyzer = name.parse()?;
+            if !analyzers.contains(&analyzer) {
+                analyzers.push(analyzer);
+            }
+        }
+        Ok(Self(analyzers))
+    }
+}
+
+/// Run the analyzers for the languages of the clone in `path`, and return their columns: the
+/// number of warnings of each analyzer, and those of each lint that warned at all. One that
+/// fails is logged, and its columns are left empty.
+pub async fn run(analyzers: &[Analyzer], path: &Path, repo: &str) -> Vec<(String, String)> {
+    let mut columns = Vec::new();
+    for &analyzer in analyzers {
+        if !tokio::fs::try_exists(path.join(analyzer.manifest()))
+            .await
+            .unwrap_or(false)
+        {
+            continue;
+        }
+
+        let counts = match analyzer {
+            Analyzer::Clippy => clippy(path).await,
+            Analyzer::Eslint => eslint(path).await,
+        };
+        match counts {
+            Ok(counts) => {
+                let total: usize = counts.values().sum();
+                columns.push((format!("{}_warnings", analyzer.name()), total.to_string()));
+                for (lint, count) in counts {
+                    columns.push((format!("{}: {lint}", analyzer.name()), count.to_string()));
+                }
+            }
+            Err(e) => log::warn!(repo; "{} failed for {repo}: {e:#}", analyzer.name()),
+        }
+    }
+    columns
+}
+
+/// A line of cargo's JSON output, of which only the compiler's messages are counted.
+#[derive(Debug, Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<Diagnostic>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Diagnostic {
+    code: Option<DiagnosticCode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiagnosticCode {
+    code: String,
+}
+
+/// The number of clippy's and the compiler's warnings by lint, like "clippy::needless_return"
+/// or "unused_variables".
+async fn clippy(path: &Path) -> Result<BTreeMap<String, usize>> {
+    // built outside of the clone, so that later searches don't search the build
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    let target_dir = std::env::temp_dir().join(format!(
+        "octosurfer-clippy.{}.{}",
+        std::process::id(),
+        BUILDS.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let output = Command::new("cargo")
+        .args(["clippy", "--quiet", "--message-format", "json"])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        // lints that the repo denies only count, rather than fail the build
+        .args(["--", "--cap-lints", "warn"])
+        .current_dir(path)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("Failed to run cargo clippy");
+    let _ = tokio::fs::remove_dir_all(&target_dir).await;
+    let output = output?;
+
+    let mut counts = BTreeMap::new();
+    let mut built = false;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Ok(message) = serde_json::from_str::<CargoMessage>(line) else {
+            continue;
+        };
+        built |= message.reason == "compiler-artifact";
+        if message.reason != "compiler-message" {
+            continue;
+        }
+        if let Some(code) = message.message.and_then(|message| message.code) {
+            *counts.entry(code.code).or_insert(0) += 1;
+        }
+    }
+    // a build that fails before anything is built doesn't get to any lints, while one that
+    // fails later still has those of the crates that were checked
+    if !output.status.success() && !built {
+        return Err(anyhow!(
+            "cargo clippy failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(counts)
+}
+
+/// ESLint's results for a file.
+#[derive(Debug, Deserialize)]
+struct EslintFile {
+    messages: Vec<EslintMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EslintMessage {
+    /// Missing for errors like those parsing the file.
+    rule_id: Option<String>,
+}
+
+/// The number of ESLint's warnings and errors by rule, like "no-unused-vars", with the repo's
+/// own configuration.
+async fn eslint(path: &Path) -> Result<BTreeMap<String, usize>> {
+    let output = Command::new("eslint")
+        .args(["--format", "json", "."])
+        .current_dir(path)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("Failed to run eslint")?;
+    // eslint exits with 1 if it found problems, and with 2 if it failed
+    if !matches!(output.status.code(), Some(0 | 1)) {
+        return Err(anyhow!(
+            "eslint failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let files: Vec<EslintFile> =
+        serde_json::from_slice(&output.stdout).context("Failed to parse the output of eslint")?;
+    let mut counts = BTreeMap::new();
+    for message in files.into_iter().flat_map(|file| file.messages) {
+        let rule = message.rule_id.unwrap_or_else(|| "other".to_owned());
+        *counts.entry(rule).or_insert(0) += 1;
+    }
+    Ok(counts)
+}
@@ -0,0 +1,298 @@
+use crate::git;
+use crate::size::ByteSize;
+use anyhow::{anyhow, Result};
+use http::Uri;
+use hyper_util::client::proxy::matcher::Matcher;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{Duration, Instant};
+use url::Url;
+
+/// The most a request head sent to the proxy may take.
+const MAX_HEAD: usize = 64 * 1024;
+
+/// The bytes read at once when relaying a connection.
+const CHUNK: usize = 16 * 1024;
+
+/// The user that git authenticates to the proxy as, with the secret of the run as password.
+const PROXY_USER: &str = "octosurfer";
+
+/// What the proxy answers requests without the secret of the run with.
+const AUTH_REQUIRED: &[u8] = b"HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic realm=\"octosurfer\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+static LIMIT: OnceLock<Bandwidth> = OnceLock::new();
+
+#[derive(Debug)]
+struct Bucket {
+    /// The bytes that may be transferred right away, or if negative, those transferred
+    /// ahead of the rate.
+    available: f64,
+    updated: Instant,
+}
+
+/// A limit on the bytes per second that all transfers of a run take together.
+#[derive(Debug, Clone)]
+pub struct Bandwidth {
+    rate: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl Bandwidth {
+    fn new(rate: ByteSize) -> Self {
+        Self {
+            rate: rate.bytes() as f64,
+            bucket: Arc::new(Mutex::new(Bucket {
+                available: 0.0,
+                updated: Instant::now(),
+            })),
+        }
+    }
+
+    /// Account for `bytes` transferred, and wait until the rate allows for them.
+    pub async fn take(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let refilled = (now - bucket.updated).as_secs_f64() * self.rate;
+            // at most a second's worth of bytes at once, after idling
+            bucket.available = (bucket.available + refilled).min(self.rate);
+            bucket.updated = now;
+            bucket.available -= bytes as f64;
+            match bucket.available < 0.0 {
+                true => Duration::from_secs_f64(-bucket.available / self.rate),
+                false => Duration::ZERO,
+            }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// The limit of --max-bandwidth, if there is one.
+pub fn limit() -> Option<&'static Bandwidth> {
+    LIMIT.get()
+}
+
+/// Limit the transfers of the run to `rate` bytes per second: git's over HTTP(S), by having
+/// it go through a proxy on the loopback interface that relays connections no faster, and
+/// Software Heritage's downloads, by reading them no faster.
+pub async fn configure(rate: Option<ByteSize>) -> Result<()> {
+    let Some(rate) = rate else {
+        return Ok(());
+    };
+    if rate.bytes() == 0 {
+        return Err(anyhow!("--max-bandwidth must be more than 0"));
+    }
+    let bandwidth = Bandwidth::new(rate);
+
+    // git goes through the proxy of the environment by way of this one
+    let proxies = Arc::new(Matcher::from_env());
+    let proxy = proxies.intercept(&Uri::from_static("https://github.com"));
+    if let Some(proxy) = proxy.filter(|proxy| proxy.uri().scheme_str() != Some("http")) {
+        return Err(anyhow!(
+            "--max-bandwidth only goes through http proxies, not {}",
+            proxy.uri()
+        ));
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    // other users of the machine could reach the proxy, too, and through it the proxy of
+    // the environment with the user's credentials, so only git is let through, by a secret
+    let proxy_url = format!("http://{PROXY_USER}:{}@{addr}", secret());
+    let auth = Matcher::builder()
+        .all(proxy_url.clone())
+        .build()
+        .intercept(&Uri::from_static("https://github.com"))
+        .and_then(|proxy| proxy.basic_auth()?.to_str().ok().map(str::to_owned))
+        .ok_or_else(|| anyhow!("Failed to set up the bandwidth limiting proxy"))?;
+    tokio::spawn(serve(listener, bandwidth.clone(), proxies, Arc::new(auth)));
+    log::debug!("Limiting transfers to {rate}/s through a proxy on {addr}");
+    git::configure("http.proxy", &proxy_url);
+    // sent with the first request, rather than once the proxy asked for it
+    git::configure("http.proxyAuthMethod", "basic");
+
+    LIMIT
+        .set(bandwidth)
+        .map_err(|_| anyhow!("The bandwidth is already limited"))
+}
+
+/// A secret of 128 bits, from the randomness that the OS seeds hash maps with.
+fn secret() -> String {
+    (0..2u8)
+        .map(|i| format!("{:016x}", std::hash::RandomState::new().hash_one(i)))
+        .collect()
+}
+
+async fn serve(
+    listener: TcpListener,
+    bandwidth: Bandwidth,
+    proxies: Arc<Matcher>,
+    auth: Arc<String>,
+) {
+    loop {
+        let client = match listener.accept().await {
+            Ok((client, _)) => client,
+            Err(e) => {
+                log::warn!("The bandwidth limiting proxy failed to accept a connection: {e}");
+                continue;
+            }
+        };
+        let bandwidth = bandwidth.clone();
+        let proxies = proxies.clone();
+        let auth = auth.clone();
+        tokio::spawn(async move {
+            if let Err(e) = relay(client, bandwidth, &proxies, &auth).await {
+                log::debug!("The bandwidth limiting proxy dropped a connection: {e}");
+            }
+        });
+    }
+}
+
+/// Relay a client's connection to where its request goes: a tunnel for HTTPS, or a single
+/// plain request for HTTP, or to the HTTP proxy of the environment for either, as it is.
+/// Requests that don't authenticate with `auth`, the secret of the run, are refused.
+async fn relay(
+    mut client: TcpStream,
+    bandwidth: Bandwidth,
+    proxies: &Matcher,
+    auth: &str,
+) -> Result<()> {
+    let mut head = Vec::new();
+    let mut chunk = [0; CHUNK];
+    let end = loop {
+        if let Some(end) = head.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if head.len() > MAX_HEAD {
+            return Err(anyhow!("The request head is too long"));
+        }
+        let read = client.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&chunk[..read]);
+    };
+
+    // the secret is checked, and then not passed on to anyone
+    let mut line = String::new();
+    let mut authorized = false;
+    for header in String::from_utf8_lossy(&head[..end]).split_inclusive("\r\n") {
+        match header.split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("proxy-authorization") => {
+                authorized |= value.trim() == auth;
+            }
+            _ => line.push_str(header),
+        }
+    }
+    if !authorized {
+        client.write_all(AUTH_REQUIRED).await?;
+        return Ok(());
+    }
+    head.splice(..end, line.bytes());
+    let end = line.len();
+
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(anyhow!(
+            "Invalid request: {}",
+            line.lines().next().unwrap_or("")
+        ));
+    };
+
+    let uri: Uri = match method {
+        "CONNECT" => format!("https://{target}").parse()?,
+        _ => target.parse()?,
+    };
+    let mut upstream = if let Some(proxy) = proxies.intercept(&uri) {
+        if proxy.uri().scheme_str() != Some("http") {
+            return Err(anyhow!(
+                "--max-bandwidth only goes through http proxies, not {}",
+                proxy.uri()
+            ));
+        }
+        let host = proxy.uri().host().unwrap_or_default();
+        let port = proxy.uri().port_u16().unwrap_or(80);
+        let upstream = TcpStream::connect((host, port)).await?;
+        if let Some(auth) = proxy.basic_auth() {
+            let line_end = head
+                .windows(2)
+                .position(|window| window == b"\r\n")
+                .unwrap_or(0)
+                + 2;
+            let mut header = b"Proxy-Authorization: ".to_vec();
+            header.extend_from_slice(auth.as_bytes());
+            header.extend_from_slice(b"\r\n");
+            head.splice(line_end..line_end, header);
+        }
+        upstream
+    } else if method == "CONNECT" {
+        let upstream = TcpStream::connect(target).await?;
+        client
+            .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+            .await?;
+        head.drain(..end);
+        upstream
+    } else {
+        let url = Url::parse(target)?;
+        let host = url.host_str().ok_or_else(|| anyhow!("No host in {url}"))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let upstream = TcpStream::connect((host, port)).await?;
+        head.splice(..end, origin_form(&line, &url));
+        upstream
+    };
+    upstream.write_all(&head).await?;
+
+    let (mut client_read, mut client_write) = client.split();
+    let (mut upstream_read, mut upstream_write) = upstream.split();
+    tokio::try_join!(
+        copy(&mut client_read, &mut upstream_write, &bandwidth),
+        copy(&mut upstream_read, &mut client_write, &bandwidth),
+    )?;
+    Ok(())
+}
+
+/// A request head for a proxy, with the target in the form servers expect, and a single
+/// request per connection, so that each one is relayed to where it goes.
+fn origin_form(head: &str, url: &Url) -> Vec<u8> {
+    let mut lines = head.split("\r\n").filter(|line| !line.is_empty());
+    let mut request = lines.next().unwrap_or_default().split_whitespace();
+    let method = request.next().unwrap_or_default();
+    let version = request.nth(1).unwrap_or("HTTP/1.1");
+    let path = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_owned(),
+    };
+
+    let mut rewritten = format!("{method} {path} {version}\r\n");
+    for line in lines {
+        let name = line.split(':').next().unwrap_or_default().to_lowercase();
+        if name != "connection" && name != "proxy-connection" {
+            rewritten.push_str(line);
+            rewritten.push_str("\r\n");
+        }
+    }
+    rewritten.push_str("Connection: close\r\n\r\n");
+    rewritten.into_bytes()
+}
+
+/// Copy until the end of `from`, taking up the bandwidth in both directions.
+async fn copy<R, W>(from: &mut R, to: &mut W, bandwidth: &Bandwidth) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut chunk = [0; CHUNK];
+    loop {
+        let read = from.read(&mut chunk).await?;
+        if read == 0 {
+            // the other side may have closed the connection already
+            let _ = to.shutdown().await;
+            return Ok(());
+        }
+        bandwidth.take(read).await;
+        to.write_all(&chunk[..read]).await?;
+    }
+}
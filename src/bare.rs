@@ -0,0 +1,80 @@
+use crate::depth_args;
+use crate::duration::HumanDuration;
+use crate::git;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+async fn git(git_dir: &Path, args: &[&str]) -> Result<()> {
+    let output = git::command()
+        .arg("--git-dir")
+        .arg(git_dir.as_os_str())
+        .args(args)
+        .kill_on_drop(true)
+        .output()
+        .await?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "git {} failed in {:?}: {}",
+            args.join(" "),
+            git_dir,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Update what a bare clone's HEAD points to, the default branch or `git_ref`, which is
+/// all that's searched.
+pub async fn fetch(
+    git_dir: &Path,
+    git_ref: Option<&str>,
+    depth: Option<u32>,
+    timeout: Option<HumanDuration>,
+) -> Result<()> {
+    let shallow = tokio::fs::try_exists(git_dir.join("shallow")).await?;
+    let depth = depth_args(depth, shallow);
+    let fetched = async {
+        let mut fetch = vec!["fetch", "--quiet"];
+        fetch.extend(depth.iter().map(String::as_str));
+        fetch.extend(["origin", git_ref.unwrap_or("HEAD")]);
+        git(git_dir, &fetch).await?;
+        // a tag's commit, rather than the tag itself
+        git(git_dir, &["update-ref", "HEAD", "FETCH_HEAD^{commit}"]).await
+    };
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout.duration(), fetched)
+            .await
+            .map_err(|_| anyhow!("Updating {:?} took longer than {timeout}", git_dir))??,
+        None => fetched.await?,
+    }
+
+    log::debug!("Successfully updated {:?}", git_dir);
+    Ok(())
+}
+
+/// Check out a bare clone's default branch into `path`, replacing what an interrupted run
+/// may have left there.
+pub async fn add_worktree(git_dir: &Path, path: &Path) -> Result<()> {
+    if tokio::fs::try_exists(path).await? {
+        tokio::fs::remove_dir_all(path).await?;
+    }
+    git(git_dir, &["worktree", "prune"]).await?;
+
+    let path = path.to_string_lossy();
+    git(
+        git_dir,
+        &[
+            "worktree", "add", "--quiet", "--detach", "--force", &path, "HEAD",
+        ],
+    )
+    .await
+}
+
+/// Remove a worktree from [`add_worktree`], and git's record of it.
+pub async fn remove_worktree(git_dir: &Path, path: &Path) -> Result<()> {
+    log::debug!("Removing {:?}", path);
+    tokio::fs::remove_dir_all(path).await?;
+    git(git_dir, &["worktree", "prune"]).await
+}
@@ -0,0 +1,211 @@
+use crate::forge::{parse_date, Repo, RepoMeta};
+use crate::RunCmd;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use url::Url;
+
+/// The API to use if no URL is given.
+const DEFAULT_URL: &str = "https://api.bitbucket.org/2.0/";
+
+/// Repos per page, the maximum Bitbucket allows.
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct RepoPage {
+    values: Vec<BitbucketRepo>,
+    /// The URL of the next page, if there is one.
+    next: Option<Url>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitbucketRepo {
+    /// "workspace/slug"
+    full_name: String,
+    links: Links,
+    /// The main language, or an empty string.
+    #[serde(default)]
+    language: String,
+    /// In bytes.
+    size: Option<u64>,
+    updated_on: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Links {
+    clone: Vec<CloneLink>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloneLink {
+    /// "https" or "ssh"
+    name: String,
+    /// Not necessarily a URL, e.g. "git@bitbucket.org:workspace/slug.git" for ssh.
+    href: String,
+}
+
+impl TryFrom<BitbucketRepo> for Repo {
+    type Error = anyhow::Error;
+
+    fn try_from(repo: BitbucketRepo) -> Result<Self> {
+        let (owner, name) = repo
+            .full_name
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Repo without an owner: {}", repo.full_name))?;
+        let link = |name: &str| {
+            repo.links
+                .clone
+                .iter()
+                .find(|link| link.name == name)
+                .map(|link| link.href.clone())
+        };
+        let href =
+            link("https").ok_or_else(|| anyhow!("Repo without a clone URL: {}", repo.full_name))?;
+        let ssh_url = link("ssh");
+        let mut clone_url = Url::parse(&href)?;
+        // clone URLs contain the name of the user the API was queried as
+        let _ = clone_url.set_username("");
+
+        Ok(Repo {
+            owner: owner.to_owned(),
+            name: name.to_owned(),
+            clone_url,
+            swh_directory: None,
+            meta: RepoMeta {
+                stars: None,
+                language: Some(repo.language).filter(|language| !language.is_empty()),
+                size: repo.size.map(|size| size / 1024),
+                pushed_at: repo.updated_on.as_deref().and_then(parse_date),
+                topics: Vec::new(),
+                parent: None,
+                ssh_url,
+            },
+        })
+    }
+}
+
+/// Translate GitHub-style date qualifiers like ">2020-01-01" into a filter on the last update.
+fn pushed_filter(spec: &str) -> Result<String> {
+    for op in [">=", "<=", ">", "<"] {
+        if let Some(date) = spec.strip_prefix(op) {
+            return Ok(format!("updated_on {op} {}", date.trim()));
+        }
+    }
+    Err(anyhow!(
+        "--pushed only supports >, >=, <, and <= for Bitbucket: {spec}"
+    ))
+}
+
+/// Quote a string for a Bitbucket query.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Build a Bitbucket query from the repo search options.
+fn to_query(argh: &RunCmd) -> Result<String> {
+    let mut filters = Vec::new();
+
+    let keywords: Vec<String> = argh
+        .keywords
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|keyword| !keyword.is_empty())
+        .map(|keyword| {
+            let keyword = quote(keyword);
+            format!("(name ~ {keyword} OR description ~ {keyword})")
+        })
+        .collect();
+    filters.extend(keywords);
+
+    if let Some(languages) = &argh.languages {
+        let languages: Vec<String> = languages
+            .split(',')
+            .map(|language| format!("language = {}", quote(&language.trim().to_lowercase())))
+            .collect();
+        filters.push(format!("({})", languages.join(" OR ")));
+    }
+
+    if let Some(pushed) = &argh.pushed {
+        for spec in pushed.split(',') {
+            filters.push(pushed_filter(spec.trim())?);
+        }
+    }
+
+    Ok(filters.join(" AND "))
+}
+
+/// A repo search on Bitbucket Cloud.
+pub struct BitbucketSearch {
+    client: reqwest::Client,
+    token: Option<String>,
+    /// The next page to fetch, if any.
+    next: Option<Url>,
+}
+
+impl BitbucketSearch {
+    pub fn from_argh(argh: &RunCmd) -> Result<Self> {
+        for (flag, value) in [
+            ("--stars", &argh.stars),
+            ("--topics", &argh.topics),
+            ("--licenses", &argh.licenses),
+            ("--any-language", &argh.any_language),
+        ] {
+            if value.is_some() {
+                return Err(anyhow!("{flag} is not supported for Bitbucket"));
+            }
+        }
+
+        let mut base = match &argh.forge_url {
+            Some(url) => url.clone(),
+            None => Url::parse(DEFAULT_URL)?,
+        };
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+
+        let mut first = base.join("repositories")?;
+        let query = to_query(argh)?;
+        if !query.is_empty() {
+            first.query_pairs_mut().append_pair("q", &query);
+        }
+        first
+            .query_pairs_mut()
+            .append_pair("sort", "-updated_on")
+            .append_pair("pagelen", &PAGE_SIZE.to_string());
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            token: std::env::var("BITBUCKET_TOKEN").ok(),
+            next: Some(first),
+        })
+    }
+
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Repo>>> {
+        let Some(url) = self.next.take() else {
+            return Ok(None);
+        };
+
+        let mut request = self.client.get(url);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let page: RepoPage = request.send().await?.error_for_status()?.json().await?;
+        self.next = page.next;
+
+        let repos = page
+            .values
+            .into_iter()
+            .filter_map(|repo| match Repo::try_from(repo) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    log::warn!("Skipping repo: {e}");
+                    None
+                }
+            })
+            .collect();
+
+        Ok(Some(repos))
+    }
+}
@@ -0,0 +1,182 @@
+use crate::code_queries::QueryResults;
+use crate::forge::{CloneProtocol, Repo};
+use crate::git;
+use crate::presets::Preset;
+use crate::search::SearchOptions;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The manifest's file name, in the target directory.
+const MANIFEST: &str = ".octosurfer-cache.json";
+
+/// What a repo looked like when it was last searched, and what was found.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// The version of the repo that was searched: when it was last pushed to, as told by
+    /// the forge, or else the commit its default branch pointed to.
+    version: String,
+    /// The fingerprint of the queries and options it was searched with.
+    fingerprint: String,
+    results: Vec<QueryResults>,
+}
+
+/// Whether a repo's results are cached.
+pub enum Lookup {
+    /// The repo hasn't changed since it was searched with the same queries and options.
+    Cached(Vec<QueryResults>),
+    /// The repo needs to be searched, and its results can be stored for this version of it,
+    /// if it's known.
+    Changed(Option<String>),
+}
+
+/// The results of repos searched in earlier runs, by repo, so that repos that haven't
+/// changed since aren't cloned and searched again.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    path: PathBuf,
+    fingerprint: String,
+    /// The branch or tag that is searched instead of the default branch.
+    git_ref: Option<String>,
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+/// A fingerprint of everything that changes the results of searching a repo.
+fn fingerprint(queries: &str, options: &SearchOptions, git_ref: Option<&str>) -> String {
+    let mut options = options.clone();
+    // the order of a set's Debug output changes from run to run
+    let mut extensions: Vec<String> = options
+        .extensions
+        .take()
+        .map(|extensions| extensions.into_iter().collect())
+        .unwrap_or_default();
+    extensions.sort();
+    // doesn't change the results of repos that finish in time
+    options.timeout = None;
+
+    let mut hasher = DefaultHasher::new();
+    queries.hash(&mut hasher);
+    format!("{options:?}").hash(&mut hasher);
+    extensions.hash(&mut hasher);
+    git_ref.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+impl Cache {
+    /// Load the manifest in `target_dir`, if there is one. The results are for the queries
+    /// of `query_file` and `preset`.
+    pub async fn load(
+        target_dir: &Path,
+        query_file: Option<&Path>,
+        preset: Option<Preset>,
+        options: &SearchOptions,
+        git_ref: Option<String>,
+    ) -> Result<Self> {
+        let mut queries = match query_file {
+            Some(query_file) => tokio::fs::read_to_string(query_file)
+                .await
+                .with_context(|| format!("Failed to read {query_file:?}"))?,
+            None => String::new(),
+        };
+        for (name, query) in preset.map(Preset::queries).unwrap_or_default() {
+            queries.push_str(&format!("{name}: {query}\n"));
+        }
+        let path = target_dir.join(MANIFEST);
+
+        let entries = match tokio::fs::read_to_string(&path).await {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("Failed to parse the cache manifest {path:?}"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        log::info!("Loaded {} cached repos from {:?}", entries.len(), path);
+
+        Ok(Self {
+            path,
+            fingerprint: fingerprint(&queries, options, git_ref.as_deref()),
+            git_ref,
+            entries: Arc::new(Mutex::new(entries)),
+        })
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether the results of a repo can be reused, since it hasn't changed since it was
+    /// last searched.
+    pub async fn lookup(&self, repo: &Repo, protocol: CloneProtocol) -> Lookup {
+        let version = match version(repo, protocol, self.git_ref.as_deref()).await {
+            Ok(version) => version,
+            Err(e) => {
+                log::debug!("Not using the cache for {}/{}: {e}", repo.owner, repo.name);
+                return Lookup::Changed(None);
+            }
+        };
+
+        let entries = self.entries();
+        let cached = entries
+            .get(&repo.key())
+            .filter(|entry| entry.version == version && entry.fingerprint == self.fingerprint);
+        match cached {
+            Some(entry) => Lookup::Cached(entry.results.clone()),
+            None => Lookup::Changed(Some(version)),
+        }
+    }
+
+    /// Remember the results of a repo, at a version from `lookup`.
+    pub fn store(&self, repo: &Repo, version: String, results: &[QueryResults]) {
+        let entry = Entry {
+            version,
+            fingerprint: self.fingerprint.clone(),
+            results: results.to_vec(),
+        };
+        self.entries().insert(repo.key(), entry);
+    }
+
+    /// Write the manifest, replacing the one before only once it's complete.
+    pub async fn save(&self) -> Result<()> {
+        let json = serde_json::to_string(&*self.entries())?;
+        let partial = self.path.with_extension("json.partial");
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(&partial, json).await?;
+        tokio::fs::rename(&partial, &self.path).await?;
+        Ok(())
+    }
+}
+
+/// The version of a repo: what `git_ref` points to, if given, or else when it was last
+/// pushed to, if the forge tells, or else the commit its default branch points to.
+async fn version(repo: &Repo, protocol: CloneProtocol, git_ref: Option<&str>) -> Result<String> {
+    if git_ref.is_none() {
+        if let Some(pushed_at) = repo.meta.pushed_at {
+            return Ok(pushed_at.to_rfc3339());
+        }
+    }
+    if let Some(directory) = &repo.swh_directory {
+        return Ok(directory.clone());
+    }
+
+    let output = git::command()
+        .arg("ls-remote")
+        .arg(repo.remote_url(protocol))
+        .arg(git_ref.unwrap_or("HEAD"))
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.split_whitespace().next() {
+        Some(head) if output.status.success() => Ok(head.to_owned()),
+        _ => Err(anyhow!(
+            "Failed to look up {} of {}/{}",
+            git_ref.unwrap_or("the HEAD"),
+            repo.owner,
+            repo.name
+        )),
+    }
+}
@@ -0,0 +1,99 @@
+use crate::code_queries::{CodeQuery, Engine, QueryKind};
+use crate::search;
+use crate::structural;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Validate a query file without touching the network.
+///
+/// Every query is compiled on its own so that errors can be attributed to a line. Problems
+/// are printed to stdout, and an error is returned if there were any.
+pub async fn check_queries(path: &Path, engine: Engine) -> Result<()> {
+    let f = File::open(path).await?;
+    let reader = BufReader::new(f);
+    let mut lines = reader.lines();
+
+    let builder = search::matcher_builder();
+    let pcre2_builder = search::pcre2_matcher_builder();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut problems = 0;
+    let mut count = 0;
+    let mut lineno = 0;
+
+    while let Some(line) = lines.next_line().await? {
+        lineno += 1;
+        let query = line.trim();
+
+        if query.is_empty() {
+            continue;
+        }
+
+        count += 1;
+
+        if let Some(first) = seen.get(query) {
+            println!("line {lineno}: duplicate of line {first}: {query}");
+            problems += 1;
+            continue;
+        }
+        seen.insert(query.to_owned(), lineno);
+
+        let parsed = match CodeQuery::parse(query, engine) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                println!("line {lineno}: {e}");
+                problems += 1;
+                continue;
+            }
+        };
+
+        let compiled = match parsed.kind {
+            QueryKind::Regex(Engine::Rust) => builder
+                .build(&parsed.pattern)
+                .map(drop)
+                .map_err(|e| e.to_string()),
+            QueryKind::Regex(Engine::Pcre2) => pcre2_builder
+                .build(&parsed.pattern)
+                .map(drop)
+                .map_err(|e| e.to_string()),
+            QueryKind::Structural(lang) => structural::compile(lang, &parsed.pattern)
+                .map(drop)
+                .map_err(|e| e.to_string()),
+        };
+
+        if let Err(e) = compiled {
+            println!("line {lineno}: invalid pattern {query}");
+            for err_line in e.lines() {
+                println!("    {err_line}");
+            }
+            problems += 1;
+        }
+    }
+
+    println!();
+    println!("Matcher configuration:");
+    match engine {
+        Engine::Rust => println!("  engine:          rust regex (pcre2: prefix for PCRE2)"),
+        Engine::Pcre2 => println!("  engine:          PCRE2"),
+    }
+    println!("  whole words:     yes");
+    println!("  case sensitive:  yes");
+    println!("  multi-line:      no");
+    println!("  binary files:    skipped on first NUL byte");
+    println!("  hidden files:    skipped unless --hidden is given");
+    println!("  encoding:        detected, unless --encoding is given");
+    println!(
+        "  tree-sitter:     {}",
+        structural::Lang::ALL.map(|lang| lang.name()).join(", ")
+    );
+    println!();
+
+    if problems == 0 {
+        println!("{count} queries OK");
+        Ok(())
+    } else {
+        Err(anyhow!("{problems} problem(s) in {count} queries"))
+    }
+}
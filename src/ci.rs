@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Prefix of the columns with the uses of each GitHub action.
+const COLUMN_PREFIX: &str = "ci: ";
+
+/// CI systems, by the files that configure them, relative to the repo. A directory stands for
+/// the YAML files in it.
+const SYSTEMS: &[(&str, &[&str])] = &[
+    ("appveyor", &["appveyor.yml", ".appveyor.yml"]),
+    (
+        "azure_pipelines",
+        &["azure-pipelines.yml", ".azure-pipelines.yml"],
+    ),
+    ("bitbucket_pipelines", &["bitbucket-pipelines.yml"]),
+    ("buildkite", &[".buildkite/pipeline.yml"]),
+    ("circleci", &[".circleci/config.yml"]),
+    ("cirrus", &[".cirrus.yml"]),
+    ("drone", &[".drone.yml"]),
+    ("github_actions", &[".github/workflows"]),
+    ("gitlab_ci", &[".gitlab-ci.yml"]),
+    ("jenkins", &["Jenkinsfile"]),
+    ("travis", &[".travis.yml"]),
+    ("woodpecker", &[".woodpecker.yml", ".woodpecker"]),
+];
+
+/// Top-level keys of a .gitlab-ci.yml that aren't jobs.
+const GITLAB_KEYWORDS: &[&str] = &[
+    "after_script",
+    "before_script",
+    "cache",
+    "default",
+    "image",
+    "include",
+    "services",
+    "stages",
+    "variables",
+    "workflow",
+];
+
+/// What a repo's CI configuration uses.
+#[derive(Debug, Default)]
+struct Usage {
+    systems: Vec<&'static str>,
+    jobs: usize,
+    run_steps: usize,
+    /// Steps by the action they use, like "actions/checkout", of any version.
+    actions: BTreeMap<String, usize>,
+}
+
+/// Look at the CI configuration of the clone in `path`, hidden as it may be, and return its
+/// columns: the CI systems it's for, its number of jobs, its number of GitHub Actions steps
+/// that run commands, and those of each action.
+pub async fn run(path: &Path, repo: &str) -> Vec<(String, String)> {
+    let usage = tokio::task::spawn_blocking({
+        let path = path.to_owned();
+        move || usage(&path)
+    })
+    .await;
+    let usage = match usage {
+        Ok(usage) => usage,
+        Err(e) => {
+            log::warn!(repo; "Failed to read the CI configuration of {repo}: {e}");
+            return Vec::new();
+        }
+    };
+
+    let mut columns = vec![
+        ("ci_systems".to_owned(), usage.systems.join("/")),
+        ("ci_jobs".to_owned(), usage.jobs.to_string()),
+        ("ci_run_steps".to_owned(), usage.run_steps.to_string()),
+    ];
+    for (action, count) in usage.actions {
+        columns.push((format!("{COLUMN_PREFIX}{action}"), count.to_string()));
+    }
+    columns
+}
+
+fn usage(path: &Path) -> Usage {
+    let mut usage = Usage::default();
+    for (system, files) in SYSTEMS {
+        let texts: Vec<String> = files
+            .iter()
+            .flat_map(|file| read_config(&path.join(file)))
+            .collect();
+        if texts.is_empty() {
+            continue;
+        }
+        usage.systems.push(*system);
+
+        for text in texts {
+            match *system {
+                "github_actions" => github_workflow(&text, &mut usage),
+                "gitlab_ci" => usage.jobs += gitlab_jobs(&text),
+                _ => {}
+            }
+        }
+    }
+    usage
+}
+
+/// The text of a configuration file, or of the YAML files of a directory.
+fn read_config(path: &Path) -> Vec<String> {
+    let read = |path: &Path| {
+        std::fs::read(path)
+            .ok()
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+    };
+    if !path.is_dir() {
+        return read(path).into_iter().collect();
+    }
+
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "yml" || extension == "yaml")
+        })
+        .collect();
+    paths.sort();
+    paths.iter().filter_map(|path| read(path)).collect()
+}
+
+/// Count the jobs, steps, and actions of a GitHub Actions workflow. Workflows are YAML, but
+/// what's counted is on lines of its own, like `uses: actions/checkout@v4`, or a job's name
+/// at the first indentation under `jobs:`.
+fn github_workflow(text: &str, usage: &mut Usage) {
+    let mut in_jobs = false;
+    let mut job_indent = None;
+    for line in text.lines() {
+        let content = line.trim_start();
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - content.len();
+
+        if indent == 0 {
+            in_jobs = key(content) == Some("jobs");
+            job_indent = None;
+            continue;
+        }
+        if in_jobs && *job_indent.get_or_insert(indent) == indent && key(content).is_some() {
+            usage.jobs += 1;
+        }
+
+        let content = content.strip_prefix("- ").unwrap_or(content).trim_start();
+        if let Some(action) = content.strip_prefix("uses:") {
+            let action = unquote(action);
+            // a reusable workflow is a job of its own, not a step
+            if !action.is_empty() && !action.contains(".github/workflows/") {
+                let action = action.split('@').next().unwrap_or(action);
+                *usage.actions.entry(action.to_owned()).or_insert(0) += 1;
+            }
+        } else if content.starts_with("run:") {
+            usage.run_steps += 1;
+        }
+    }
+}
+
+/// The number of jobs in a .gitlab-ci.yml, i.e. of its top-level keys other than keywords and
+/// hidden ones like `.template:`.
+fn gitlab_jobs(text: &str) -> usize {
+    text.lines()
+        .filter(|line| !line.starts_with([' ', '\t', '#', '-', '.']))
+        .filter_map(key)
+        .filter(|key| !GITLAB_KEYWORDS.contains(key))
+        .count()
+}
+
+/// The key of a line that starts a mapping, like `build:` or `"deploy job":  # comment`.
+fn key(line: &str) -> Option<&str> {
+    let key = unquote(line).strip_suffix(':')?;
+    let key = key.trim_matches(|c| c == '"' || c == '\'');
+    (!key.is_empty()).then_some(key)
+}
+
+/// A YAML scalar without its quotes and a trailing comment.
+fn unquote(value: &str) -> &str {
+    let value = value.split(" #").next().unwrap_or_default().trim();
+    value.trim_matches(|c| c == '"' || c == '\'')
+}
@@ -0,0 +1,195 @@
+use crate::alternates::STORES_DIR;
+use crate::disk::dir_size;
+use crate::forge::{RepoList, RepoSource};
+use crate::lockfile::{self, LockfileDependencies};
+use crate::size::ByteSize;
+use crate::{failures, metadata};
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Whether a directory is a clone: a checkout, a worktree of a bare clone, or a bare clone.
+async fn is_clone(path: &Path) -> Result<bool> {
+    if tokio::fs::try_exists(path.join(".git")).await? {
+        return Ok(true);
+    }
+    let head = tokio::fs::metadata(path.join("HEAD")).await;
+    let objects = tokio::fs::metadata(path.join("objects")).await;
+    Ok(head.is_ok_and(|head| head.is_file()) && objects.is_ok_and(|objects| objects.is_dir()))
+}
+
+/// The clones of repos in a target directory, as `<owner>/<name>` or `<owner>/<name>.git`.
+async fn clones(target_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut clones = Vec::new();
+    let mut owners = tokio::fs::read_dir(target_dir).await?;
+    while let Some(owner) = owners.next_entry().await? {
+        if !owner.file_type().await?.is_dir() || owner.file_name() == STORES_DIR {
+            continue;
+        }
+
+        let mut repos = tokio::fs::read_dir(owner.path()).await?;
+        while let Some(repo) = repos.next_entry().await? {
+            // symbolic links are never followed, so nothing outside is removed
+            if repo.file_type().await?.is_dir() && is_clone(&repo.path()).await? {
+                clones.push(repo.path());
+            }
+        }
+    }
+
+    clones.sort();
+    Ok(clones)
+}
+
+/// Remove the owners' directories in a target directory that are empty.
+async fn remove_empty_owners(target_dir: &Path) -> Result<()> {
+    let mut owners = tokio::fs::read_dir(target_dir).await?;
+    while let Some(owner) = owners.next_entry().await? {
+        if !owner.file_type().await?.is_dir() || owner.file_name() == STORES_DIR {
+            continue;
+        }
+        // other files are left alone, and with them their owner's directory
+        if tokio::fs::remove_dir(owner.path()).await.is_err() {
+            log::debug!("Keeping {}, which isn't empty", owner.path().display());
+        }
+    }
+    Ok(())
+}
+
+/// Remove the clones in a target directory, as `<owner>/<name>` or `<owner>/<name>.git`, and
+/// the shared object stores, and then the owners' directories, if nothing else is in them.
+/// Directories that aren't clones are left alone. With `dry_run`, only list what would be
+/// removed.
+pub async fn clean(target_dir: &Path, dry_run: bool) -> Result<()> {
+    if !tokio::fs::try_exists(target_dir).await? {
+        return Err(anyhow!("{target_dir:?} doesn't exist"));
+    }
+    let mut clones = clones(target_dir).await?;
+    let stores = target_dir.join(STORES_DIR);
+    if tokio::fs::try_exists(&stores).await? {
+        clones.insert(0, stores);
+    }
+
+    if dry_run {
+        let mut total_size = 0;
+        for clone in clones.iter() {
+            let size = dir_size(clone.clone()).await?;
+            total_size += size;
+            println!("{}\t{}", clone.display(), ByteSize(size));
+        }
+        println!("{} clones, {} in total", clones.len(), ByteSize(total_size));
+        return Ok(());
+    }
+
+    for clone in clones.iter() {
+        log::debug!("Removing {}", clone.display());
+        tokio::fs::remove_dir_all(clone).await?;
+    }
+
+    remove_empty_owners(target_dir).await?;
+
+    log::info!("Removed {} clones from {:?}", clones.len(), target_dir);
+    Ok(())
+}
+
+/// Remove the clones in a target directory of repos that aren't in `corpus`, i.e. that a run
+/// didn't find anymore, by their lowercase `<owner>/<name>`, or only list them in a dry run.
+/// A run that found no repos at all prunes nothing, since that's much more likely a search
+/// that failed to find them than repos that are all gone.
+pub async fn prune(target_dir: &Path, corpus: &HashSet<String>, dry_run: bool) -> Result<()> {
+    if !tokio::fs::try_exists(target_dir).await? {
+        return Ok(());
+    }
+    if corpus.is_empty() {
+        log::warn!("Not pruning {:?}, since no repos were found", target_dir);
+        return Ok(());
+    }
+
+    let mut stale = Vec::new();
+    for clone in clones(target_dir).await? {
+        let (Some(owner), Some(name)) =
+            (clone.parent().and_then(Path::file_name), clone.file_name())
+        else {
+            continue;
+        };
+        let name = name.to_string_lossy();
+        let name = name.strip_suffix(".git").unwrap_or(&name);
+        let full_name = format!("{}/{name}", owner.to_string_lossy()).to_lowercase();
+        if !corpus.contains(&full_name) {
+            stale.push(clone);
+        }
+    }
+
+    if dry_run {
+        for clone in stale.iter() {
+            println!("{}", clone.display());
+        }
+        log::info!(
+            "Would prune {} clones from {:?}, which weren't found anymore",
+            stale.len(),
+            target_dir
+        );
+        return Ok(());
+    }
+
+    log::info!(
+        "Pruning {} clones from {:?}, which weren't found anymore",
+        stale.len(),
+        target_dir
+    );
+    for clone in stale.iter() {
+        log::info!("Pruning {}", clone.display());
+        tokio::fs::remove_dir_all(clone).await?;
+    }
+    remove_empty_owners(target_dir).await?;
+
+    log::info!("Pruned {} clones from {:?}", stale.len(), target_dir);
+    Ok(())
+}
+
+/// Remove the clones in a target directory of repos that aren't in the file in `list`, or
+/// only list them in a dry run, like [`prune`] does after a run, but without running again.
+/// The file is a results file written by run or analyze, a repo list like `--repo-list`
+/// takes, or a lockfile or SBOM like `--from-lockfile` takes, whose dependencies are looked
+/// up in their registries.
+pub async fn prune_against(target_dir: &Path, list: &Path, dry_run: bool) -> Result<()> {
+    if !tokio::fs::try_exists(target_dir).await? {
+        return Err(anyhow!("{target_dir:?} doesn't exist"));
+    }
+    let corpus = listed_repos(list).await?;
+    prune(target_dir, &corpus, dry_run).await
+}
+
+/// The lowercase `<owner>/<name>` of the repos in a results file, repo list, or lockfile.
+async fn listed_repos(list: &Path) -> Result<HashSet<String>> {
+    let text = tokio::fs::read_to_string(list)
+        .await
+        .with_context(|| format!("Failed to read {list:?}"))?;
+
+    let header = text.lines().next().unwrap_or_default();
+    let repo_list = header == metadata::HEADER || header == failures::HEADER;
+    if !repo_list && header.starts_with("repo,") {
+        let repos = text.lines().skip(1).filter_map(|row| row.split(',').next());
+        return Ok(repos
+            .filter(|repo| !repo.is_empty())
+            .map(str::to_lowercase)
+            .collect());
+    }
+
+    let mut source: Box<dyn RepoSource> = match lockfile::is_lockfile(list, &text) {
+        true => Box::new(LockfileDependencies::from_path(list)?),
+        false => Box::new(RepoList::from_path(list)?),
+    };
+    let mut corpus = HashSet::new();
+    while let Some(repos) = source.next_page().await? {
+        for repo in repos {
+            corpus.insert(format!("{}/{}", repo.owner, repo.name).to_lowercase());
+        }
+    }
+    // the clones of the dependencies that couldn't be looked up would be pruned
+    if !source.complete() {
+        return Err(anyhow!(
+            "Not pruning, since some of the repos in {list:?} couldn't be looked up"
+        ));
+    }
+    Ok(corpus)
+}
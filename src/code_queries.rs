@@ -1,51 +1,290 @@
+use crate::error::{Error, QueryError};
+use crate::examples::Example;
+use crate::presets::Preset;
+use crate::structural::Lang;
+use anyhow::{anyhow, Context};
+use regex_syntax::hir::{Hir, HirKind};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::io;
 use std::path::Path;
-use tokio::fs::File;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::str::FromStr;
 
+/// Prefix marking a tree-sitter query, followed by the language and another colon, e.g.
+/// `ts:rust:(unsafe_block) @u`.
+const STRUCTURAL_PREFIX: &str = "ts:";
+
+/// Prefix selecting the PCRE2 engine for a single regex query, e.g. `pcre2:foo(?=bar)`.
+const PCRE2_PREFIX: &str = "pcre2:";
+
+/// Prefix making a query case-insensitive, e.g. `ci:malloc`.
+const CASE_INSENSITIVE_PREFIX: &str = "ci:";
+
+/// Prefix marking the rest of the line as a regex. This is the default, but allows writing
+/// regexes that would otherwise look like they start with a prefix.
+const REGEX_PREFIX: &str = "re:";
+
+/// Prefix marking the rest of the line as a literal string, e.g. `lit:std::vector<int>`.
+const LITERAL_PREFIX: &str = "lit:";
+
+/// Prefix only counting matches that look random enough, followed by the minimum entropy in
+/// bits per character and another colon, e.g. `entropy:3.5:[A-Za-z0-9/+]{40}`.
+const ENTROPY_PREFIX: &str = "entropy:";
+
+/// The regex engine a query is matched with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// Rust's regex crate, as used by ripgrep by default.
+    #[default]
+    Rust,
+    /// PCRE2, which supports look-around and backreferences.
+    Pcre2,
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rust" => Ok(Engine::Rust),
+            "pcre2" => Ok(Engine::Pcre2),
+            _ => Err(format!("unknown regex engine: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum QueryKind {
+    Regex(Engine),
+    Structural(Lang),
+}
+
+#[derive(Debug, Clone)]
+pub struct CodeQuery {
+    /// The query as written in the query file, or the name of a preset's query; used as its
+    /// column name.
+    pub name: String,
+    /// The pattern handed to the matcher.
+    pub pattern: String,
+    pub kind: QueryKind,
+    /// Only count matches whose longest word has at least this entropy, in bits per
+    /// character.
+    pub min_entropy: Option<f64>,
+}
+
+impl CodeQuery {
+    /// Parse a line of a query file. Regex queries without an engine prefix use `engine`.
+    pub fn parse(line: &str, engine: Engine) -> Result<Self, String> {
+        let name = line.to_owned();
+
+        if let Some(rest) = line.strip_prefix(STRUCTURAL_PREFIX) {
+            let (lang, pattern) = rest
+                .split_once(':')
+                .ok_or_else(|| format!("missing language in structural query: {line}"))?;
+            let lang = lang.parse()?;
+
+            return Ok(Self {
+                name,
+                pattern: pattern.to_owned(),
+                kind: QueryKind::Structural(lang),
+                min_entropy: None,
+            });
+        }
+
+        let mut engine = engine;
+        let mut case_insensitive = false;
+        let mut literal = false;
+        let mut min_entropy = None;
+        let mut pattern = line;
+
+        // `ci:`, `pcre2:`, and `entropy:` may be combined in any order, `re:` and `lit:` end
+        // the prefixes
+        loop {
+            if let Some(rest) = pattern.strip_prefix(ENTROPY_PREFIX) {
+                let (bits, rest) = rest
+                    .split_once(':')
+                    .and_then(|(bits, rest)| Some((bits.parse::<f64>().ok()?, rest)))
+                    .ok_or_else(|| format!("invalid entropy in query: {line}"))?;
+                min_entropy = Some(bits);
+                pattern = rest;
+            } else if let Some(rest) = pattern.strip_prefix(PCRE2_PREFIX) {
+                engine = Engine::Pcre2;
+                pattern = rest;
+            } else if let Some(rest) = pattern.strip_prefix(CASE_INSENSITIVE_PREFIX) {
+                case_insensitive = true;
+                pattern = rest;
+            } else if let Some(rest) = pattern.strip_prefix(REGEX_PREFIX) {
+                pattern = rest;
+                break;
+            } else if let Some(rest) = pattern.strip_prefix(LITERAL_PREFIX) {
+                literal = true;
+                pattern = rest;
+                break;
+            } else {
+                break;
+            }
+        }
+
+        if pattern.is_empty() {
+            return Err(format!("empty pattern: {line}"));
+        }
+
+        let mut pattern = match literal {
+            true => regex_syntax::escape(pattern),
+            false => pattern.to_owned(),
+        };
+
+        if case_insensitive {
+            pattern = format!("(?i:{pattern})");
+        }
+
+        Ok(Self {
+            name,
+            pattern,
+            kind: QueryKind::Regex(engine),
+            min_entropy,
+        })
+    }
+
+    /// The shortest term worth handing to a code search.
+    const MIN_TERM_LEN: usize = 3;
+
+    /// A literal that every match of this query contains, to look for with a code search.
+    /// Code search ignores case, so case-insensitive queries have terms as well.
+    pub fn search_term(&self) -> Option<String> {
+        let QueryKind::Regex(_) = self.kind else {
+            return None;
+        };
+        let pattern = self
+            .pattern
+            .strip_prefix("(?i:")
+            .and_then(|pattern| pattern.strip_suffix(')'))
+            .unwrap_or(&self.pattern);
+
+        let hir = regex_syntax::parse(pattern).ok()?;
+        longest_literal(&hir).filter(|term| term.chars().count() >= Self::MIN_TERM_LEN)
+    }
+}
+
+/// The longest literal that every match of `hir` contains.
+fn longest_literal(hir: &Hir) -> Option<String> {
+    match hir.kind() {
+        HirKind::Literal(literal) => String::from_utf8(literal.0.to_vec()).ok(),
+        HirKind::Capture(capture) => longest_literal(&capture.sub),
+        HirKind::Concat(subs) => {
+            let mut candidates = Vec::new();
+            let mut run = String::new();
+            for sub in subs {
+                match sub.kind() {
+                    HirKind::Literal(literal) => match std::str::from_utf8(&literal.0) {
+                        Ok(literal) => run.push_str(literal),
+                        Err(_) => candidates.push(std::mem::take(&mut run)),
+                    },
+                    _ => {
+                        candidates.push(std::mem::take(&mut run));
+                        candidates.extend(longest_literal(sub));
+                    }
+                }
+            }
+            candidates.push(run);
+            candidates
+                .into_iter()
+                .filter(|candidate| !candidate.is_empty())
+                .max_by_key(|candidate| candidate.len())
+        }
+        _ => None,
+    }
+}
+
+/// The queries that repos are searched with, one per line of a query file.
 #[derive(Debug, Default, Clone)]
 pub struct CodeQueries {
-    inner: Vec<String>,
+    inner: Vec<CodeQuery>,
 }
 
 impl CodeQueries {
-    pub async fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
-        let f = File::open(path).await?;
-        let reader = BufReader::new(f);
-        let mut lines = reader.lines();
+    /// Read and parse the queries of a query file, with `engine` for those without an
+    /// engine prefix.
+    pub async fn from_file(path: impl AsRef<Path>, engine: Engine) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read {path:?}"))?;
+        Self::parse(&contents, engine)
+    }
+
+    /// The queries of a preset, like the secrets of `--preset secrets`, named like
+    /// "secrets: github_token" rather than by their patterns.
+    pub fn from_preset(preset: Preset, engine: Engine) -> Result<Self, Error> {
+        let mut this = Self::default();
+        for (name, line) in preset.queries() {
+            let mut query = CodeQuery::parse(line, engine).map_err(|e| anyhow!(e))?;
+            query.name = format!("{}: {name}", preset.name());
+            this.push(query);
+        }
+        Ok(this)
+    }
 
+    /// Parse queries, one per line like in a query file.
+    fn parse(queries: &str, engine: Engine) -> Result<Self, Error> {
         let mut this = Self::default();
-        while let Some(line) = lines.next_line().await? {
-            this.push(line.trim().to_owned());
+        for (i, line) in queries.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let query = CodeQuery::parse(line, engine).map_err(|message| {
+                Error::Query(QueryError {
+                    line: line_number,
+                    message,
+                })
+            })?;
+            this.push(query);
         }
 
         if this.inner.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "Empty query file!",
-            ));
+            return Err(Error::Other(anyhow!("Empty query file!")));
         }
 
         Ok(this)
     }
 
-    fn push(&mut self, s: String) {
-        self.inner.push(s)
+    fn push(&mut self, query: CodeQuery) {
+        self.inner.push(query)
+    }
+
+    /// Add the queries of `other`, except for those there are already.
+    pub fn extend(&mut self, other: CodeQueries) {
+        for query in other.inner {
+            if !self.inner.iter().any(|q| q.name == query.name) {
+                self.push(query);
+            }
+        }
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &String> {
+    pub fn iter(&self) -> impl Iterator<Item = &CodeQuery> {
         self.inner.iter()
     }
 
-    pub fn as_slice(&self) -> &[String] {
-        self.inner.as_slice()
+    /// All regex queries that are matched with the given engine.
+    pub fn regex_queries(&self, engine: Engine) -> Vec<&CodeQuery> {
+        self.inner
+            .iter()
+            .filter(|q| matches!(q.kind, QueryKind::Regex(e) if e == engine))
+            .collect()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryResults {
     pub repo_name: String,
     pub repo_owner: String,
     pub inner: HashMap<String, usize>,
+    /// Additional per-repo columns, written after the query columns.
+    pub extra: Vec<(String, String)>,
+    /// Sample matches, if requested.
+    pub examples: Vec<Example>,
+    /// Match counts keyed by language, if requested.
+    pub by_language: HashMap<String, HashMap<String, usize>>,
 }
@@ -0,0 +1,65 @@
+use crate::forge::ForgeKind;
+use crate::{git, gitea, RunCmd};
+use anyhow::{anyhow, Result};
+use url::Url;
+
+/// A credential helper that answers git's requests with `username` and `password`, which
+/// may refer to environment variables, so that tokens are never part of a command line, a
+/// URL, or a log line.
+fn helper(username: &str, password: &str) -> String {
+    format!(
+        "!f() {{ test \"$1\" = get && printf 'username=%s\\npassword=%s\\n' \"{username}\" \"{password}\"; }}; f"
+    )
+}
+
+/// Have git use the tokens of the forges whose environment variables are set to clone and
+/// update repos from their hosts, and only from those, with --clone-with-token.
+pub fn configure(argh: &RunCmd) -> Result<()> {
+    if !argh.clone_with_token {
+        return Ok(());
+    }
+
+    let gitea_url = match (&argh.forge_url, argh.forge) {
+        (Some(url), ForgeKind::Gitea) => url.clone(),
+        _ => Url::parse(gitea::DEFAULT_URL)?,
+    };
+    let forges = [
+        (
+            "GITHUB_TOKEN",
+            "https://github.com".to_owned(),
+            "x-access-token",
+            "$GITHUB_TOKEN",
+        ),
+        // Gitea takes a token as the username, with this password
+        (
+            "GITEA_TOKEN",
+            gitea_url.origin().ascii_serialization(),
+            "$GITEA_TOKEN",
+            "x-oauth-basic",
+        ),
+        (
+            "BITBUCKET_TOKEN",
+            "https://bitbucket.org".to_owned(),
+            "x-token-auth",
+            "$BITBUCKET_TOKEN",
+        ),
+    ];
+    let credentials: Vec<_> = forges
+        .into_iter()
+        .filter(|(var, ..)| std::env::var(var).is_ok_and(|token| !token.is_empty()))
+        .collect();
+    if credentials.is_empty() {
+        return Err(anyhow!(
+            "--clone-with-token needs GITHUB_TOKEN, GITEA_TOKEN, or BITBUCKET_TOKEN to be set"
+        ));
+    }
+
+    for (_, host, username, password) in credentials {
+        log::debug!("Cloning from {host} with a token");
+        let key = format!("credential.{host}.helper");
+        // an empty helper drops the user's helpers, so that none of them stores the token
+        git::configure(&key, "");
+        git::configure(&key, &helper(username, password));
+    }
+    Ok(())
+}
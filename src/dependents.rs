@@ -0,0 +1,86 @@
+use crate::registry::USER_AGENT;
+use anyhow::Result;
+use regex::Regex;
+use std::time::Duration;
+use url::Url;
+
+/// How long to wait between pages, since github.com limits scraping more strictly than
+/// the API.
+const PAGE_DELAY: Duration = Duration::from_secs(2);
+
+/// The repos depending on a GitHub repo, as listed on its "Used by" page. The API has no
+/// way of listing them, so the pages are scraped.
+pub struct Dependents {
+    client: reqwest::Client,
+    /// The owner and name of the repo whose dependents these are.
+    of: (String, String),
+    /// The next page to scrape, if any.
+    next: Option<Url>,
+    first: bool,
+}
+
+impl Dependents {
+    pub fn new(owner: &str, name: &str) -> Result<Self> {
+        let next = Url::parse(&format!(
+            "https://github.com/{owner}/{name}/network/dependents?dependent_type=REPOSITORY"
+        ))?;
+
+        Ok(Self {
+            client: reqwest::Client::builder().user_agent(USER_AGENT).build()?,
+            of: (owner.to_owned(), name.to_owned()),
+            next: Some(next),
+            first: true,
+        })
+    }
+
+    /// The owners and names of the next page of dependents, or `None` after the last page.
+    pub async fn next_page(&mut self) -> Result<Option<Vec<(String, String)>>> {
+        let Some(url) = self.next.take() else {
+            return Ok(None);
+        };
+        if !self.first {
+            tokio::time::sleep(PAGE_DELAY).await;
+        }
+        self.first = false;
+
+        log::debug!("Scraping {url}");
+        let html = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let (repos, next) = parse_page(&html);
+        self.next = next;
+
+        let (owner, name) = &self.of;
+        let repos = repos
+            .into_iter()
+            .filter(|(o, n)| !(o.eq_ignore_ascii_case(owner) && n.eq_ignore_ascii_case(name)))
+            .collect();
+        Ok(Some(repos))
+    }
+}
+
+/// The repos on a page of dependents, and the URL of the next page.
+fn parse_page(html: &str) -> (Vec<(String, String)>, Option<Url>) {
+    let repo = Regex::new(
+        r#"data-hovercard-type="repository"[^>]*href="/([A-Za-z0-9-]+)/([A-Za-z0-9._-]+)""#,
+    )
+    .expect("valid regex");
+    let next = Regex::new(r#"href="(https://github\.com/[^"]*dependents_after=[^"]*)""#)
+        .expect("valid regex");
+
+    let repos = repo
+        .captures_iter(html)
+        .map(|captures| (captures[1].to_owned(), captures[2].to_owned()))
+        .collect();
+    let next = next
+        .captures(html)
+        .and_then(|captures| Url::parse(&captures[1].replace("&amp;", "&")).ok());
+
+    (repos, next)
+}
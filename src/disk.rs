@@ -0,0 +1,195 @@
+use crate::size::ByteSize;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use walkdir::WalkDir;
+
+/// The number of repos named in the summary of the disk usage, those whose clones take the
+/// most space.
+const LARGEST_CLONES: usize = 5;
+
+/// The error of a repo that was skipped, since its clone wouldn't fit on the disk or into
+/// `--max-disk`.
+#[derive(Debug)]
+pub struct OutOfSpace {
+    full_name: String,
+    size: u64,
+}
+
+impl fmt::Display for OutOfSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Skipping {}, since its clone of about {} doesn't fit",
+            self.full_name,
+            ByteSize(self.size)
+        )
+    }
+}
+
+impl std::error::Error for OutOfSpace {}
+
+#[derive(Debug)]
+struct BudgetState {
+    limit: u64,
+    /// The bytes the clones of this run take, or are expected to take while cloning.
+    used: Mutex<u64>,
+    /// Notified whenever a clone is removed.
+    released: Notify,
+    /// Whether clones are removed after searching them, so that waiting frees up space.
+    wait: bool,
+}
+
+/// A limit on the disk space the clones of a run take, shared by all of its repos.
+#[derive(Debug, Clone)]
+pub struct DiskBudget(Arc<BudgetState>);
+
+impl DiskBudget {
+    /// A budget of `limit`. If `wait`, repos wait for other repos' clones to be removed when
+    /// theirs wouldn't fit, rather than being skipped.
+    pub fn new(limit: ByteSize, wait: bool) -> Self {
+        Self(Arc::new(BudgetState {
+            limit: limit.bytes(),
+            used: Mutex::new(0),
+            released: Notify::new(),
+            wait,
+        }))
+    }
+
+    fn used(&self) -> std::sync::MutexGuard<'_, u64> {
+        self.0.used.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Reserve `size` bytes for a clone into `target_dir`, once both the budget and the disk
+    /// have room for it.
+    pub async fn reserve(
+        &self,
+        target_dir: &Path,
+        full_name: &str,
+        size: u64,
+    ) -> Result<Reservation> {
+        let out_of_space = || OutOfSpace {
+            full_name: full_name.to_owned(),
+            size,
+        };
+        if size > self.0.limit {
+            return Err(out_of_space().into());
+        }
+
+        let mut waiting = false;
+        loop {
+            // registered before checking, so that a release in between isn't missed
+            let released = self.0.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            let available = available_space(target_dir)?;
+            {
+                let mut used = self.used();
+                if *used + size <= self.0.limit && size <= available {
+                    *used += size;
+                    return Ok(Reservation {
+                        budget: self.clone(),
+                        size,
+                    });
+                }
+                // without clones to be removed, there's nothing to wait for
+                if !self.0.wait || *used == 0 {
+                    return Err(out_of_space().into());
+                }
+            }
+
+            if !waiting {
+                log::info!("Waiting for disk space to clone {full_name}");
+                waiting = true;
+            }
+            released.await;
+        }
+    }
+}
+
+/// Disk space reserved for a clone, which is released once it's dropped, for when the
+/// clone is removed.
+#[derive(Debug)]
+pub struct Reservation {
+    budget: DiskBudget,
+    size: u64,
+}
+
+impl Reservation {
+    /// Account for the actual size of a clone, rather than the expected one.
+    pub fn resize(&mut self, size: u64) {
+        let mut used = self.budget.used();
+        *used = *used - self.size + size;
+        self.size = size;
+    }
+
+    /// Keep the space reserved for the rest of the run, for a clone that isn't removed.
+    pub fn keep(mut self) {
+        self.size = 0;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        *self.budget.used() -= self.size;
+        self.budget.0.released.notify_waiters();
+    }
+}
+
+/// The free space on the disk that `path` is on, or would be on once created.
+fn available_space(path: &Path) -> Result<u64> {
+    let mut existing = path;
+    while !existing.exists() {
+        match existing.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => existing = parent,
+            _ => {
+                existing = Path::new(".");
+                break;
+            }
+        }
+    }
+    fs4::available_space(existing)
+        .with_context(|| format!("Failed to get the free space of {existing:?}"))
+}
+
+/// The bytes the files in a directory take.
+pub async fn dir_size(path: PathBuf) -> Result<u64> {
+    let size = tokio::task::spawn_blocking(move || {
+        WalkDir::new(path)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum()
+    })
+    .await?;
+    Ok(size)
+}
+
+/// Log the disk space the clones of a run take, by full name, in total, and which take the
+/// most.
+pub fn log_usage(usage: &HashMap<String, u64>) {
+    if usage.is_empty() {
+        return;
+    }
+    let total: u64 = usage.values().sum();
+    let mut largest: Vec<(&String, &u64)> = usage.iter().collect();
+    largest.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let largest: Vec<String> = largest
+        .into_iter()
+        .take(LARGEST_CLONES)
+        .map(|(full_name, size)| format!("{full_name} ({})", ByteSize(*size)))
+        .collect();
+
+    log::info!(
+        "The clones of {} repos take {} on disk, the largest being {}",
+        usage.len(),
+        ByteSize(total),
+        largest.join(", ")
+    );
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A duration, parsed from strings like `90`, `90s`, `10m`, or `2h`.
+///
+/// Plain numbers are seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HumanDuration(pub Duration);
+
+impl HumanDuration {
+    pub fn duration(self) -> Duration {
+        self.0
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (digits, multiplier) = match s.char_indices().last() {
+            Some((idx, 's')) => (&s[..idx], 1),
+            Some((idx, 'm')) => (&s[..idx], 60),
+            Some((idx, 'h')) => (&s[..idx], 60 * 60),
+            Some((idx, 'd')) => (&s[..idx], 24 * 60 * 60),
+            _ => (s, 1),
+        };
+
+        let n: u64 = digits
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid duration: {s}"))?;
+
+        n.checked_mul(multiplier)
+            .map(|secs| HumanDuration(Duration::from_secs(secs)))
+            .ok_or_else(|| format!("duration too long: {s}"))
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [(&str, u64); 3] = [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60)];
+
+        let secs = self.0.as_secs();
+        for (unit, len) in UNITS {
+            if secs >= len && secs.is_multiple_of(len) {
+                return write!(f, "{}{unit}", secs / len);
+            }
+        }
+
+        write!(f, "{secs}s")
+    }
+}
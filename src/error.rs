@@ -0,0 +1,108 @@
+use crate::failures::RepoFailure;
+use crate::progress::Phase;
+use http::StatusCode;
+use std::fmt;
+
+/// What failed, for those embedding octosurfer to tell apart, e.g. to try again once a rate
+/// limit is reset. Everything else uses [`anyhow`], and is sorted into these at the API's
+/// boundary.
+#[derive(Debug)]
+pub enum Error {
+    /// A call of a forge's or a registry's API failed.
+    Api(anyhow::Error),
+    /// A rate limit of GitHub's API was exceeded, even after waiting for it.
+    RateLimited(anyhow::Error),
+    /// A repo couldn't be cloned, updated, or downloaded.
+    Clone { repo: String, error: anyhow::Error },
+    /// A repo couldn't be searched.
+    Search { repo: String, error: anyhow::Error },
+    /// A query of the query file couldn't be parsed.
+    Query(QueryError),
+    /// The run was interrupted with Ctrl-C, so its results are incomplete.
+    Interrupted,
+    /// Anything else, like options that don't go together, or files that can't be read or
+    /// written.
+    Other(anyhow::Error),
+}
+
+/// A query that couldn't be parsed, and why.
+#[derive(Debug, Clone)]
+pub struct QueryError {
+    /// The line of the query file, counting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Api(error) | Error::RateLimited(error) | Error::Other(error) => {
+                write!(f, "{error}")
+            }
+            Error::Clone { repo, error } | Error::Search { repo, error } => {
+                write!(f, "{repo}: {error}")
+            }
+            Error::Query(error) => {
+                write!(f, "Invalid query on line {}: {}", error.line, error.message)
+            }
+            Error::Interrupted => write!(f, "Interrupted, so the results are incomplete"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Api(error)
+            | Error::RateLimited(error)
+            | Error::Other(error)
+            | Error::Clone { error, .. }
+            | Error::Search { error, .. } => error.source(),
+            Error::Query(_) | Error::Interrupted => None,
+        }
+    }
+}
+
+impl From<RepoFailure> for Error {
+    fn from(failure: RepoFailure) -> Self {
+        let repo = format!("{}/{}", failure.repo.owner, failure.repo.name);
+        let error = failure.error;
+        match failure.phase {
+            Some(Phase::Search) => Error::Search { repo, error },
+            _ => Error::Clone { repo, error },
+        }
+    }
+}
+
+/// Whether GitHub refused a call for a rate limit, its primary or a secondary one.
+fn rate_limited(error: &octocrab::Error) -> bool {
+    match error {
+        octocrab::Error::GitHub { source, .. } => {
+            matches!(
+                source.status_code,
+                StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+            ) && source.message.to_lowercase().contains("rate limit")
+        }
+        _ => false,
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Error>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<RepoFailure>() {
+            Ok(failure) => return failure.into(),
+            Err(error) => error,
+        };
+
+        match error.downcast_ref::<octocrab::Error>() {
+            Some(octocrab) if rate_limited(octocrab) => Error::RateLimited(error),
+            Some(_) => Error::Api(error),
+            None if error.is::<reqwest::Error>() => Error::Api(error),
+            None => Error::Other(error),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::forge::Repo;
+use crate::size::ByteSize;
+use std::fmt;
+use std::time::Duration;
+
+/// Assumed download speed of clones, in bytes per second.
+const DOWNLOAD_SPEED: u64 = 10 << 20;
+/// Assumed speed of searching a clone, in bytes per second.
+const SEARCH_SPEED: u64 = 100 << 20;
+/// A checkout takes about as much space as the packed history it came from.
+pub const DISK_FACTOR: u64 = 2;
+/// GitHub allows this many code searches per minute.
+const CODE_SEARCHES_PER_MINUTE: u64 = 10;
+
+/// A rough estimate of a run, from the metadata of the repos found.
+#[derive(Debug)]
+pub struct Estimate {
+    repos: usize,
+    unknown_size: usize,
+    download: u64,
+    pages: usize,
+    code_searches: usize,
+}
+
+impl Estimate {
+    /// An estimate for `repos`, found in `pages` requests, with up to `code_searches_per_repo`
+    /// code searches for each to prefilter them.
+    pub fn new(repos: &[Repo], pages: usize, code_searches_per_repo: usize) -> Self {
+        let download = repos
+            .iter()
+            .filter_map(|repo| repo.meta.size)
+            .map(|size| size * 1024)
+            .sum();
+        let unknown_size = repos.iter().filter(|repo| repo.meta.size.is_none()).count();
+        Self {
+            repos: repos.len(),
+            unknown_size,
+            download,
+            pages,
+            code_searches: repos.len() * code_searches_per_repo,
+        }
+    }
+
+    fn runtime(&self) -> Duration {
+        let secs = self.download / DOWNLOAD_SPEED
+            + self.download * DISK_FACTOR / SEARCH_SPEED
+            + self.code_searches as u64 * 60 / CODE_SEARCHES_PER_MINUTE;
+        Duration::from_secs(secs)
+    }
+}
+
+/// A duration rounded to its two largest units, like "about 3h 20m".
+fn rough_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    match (days, hours, mins) {
+        (0, 0, 0) => "under a minute".to_owned(),
+        (0, 0, m) => format!("about {m}m"),
+        (0, h, m) => format!("about {h}h {m}m"),
+        (d, h, _) => format!("about {d}d {h}h"),
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Repos:         {}", self.repos)?;
+        match self.unknown_size {
+            0 => writeln!(f)?,
+            n => writeln!(f, ", {n} of unknown size, which aren't counted below")?,
+        }
+        writeln!(f, "Download:      up to {}", ByteSize(self.download))?;
+        writeln!(
+            f,
+            "Disk:          about {}, freed after searching with --rm",
+            ByteSize(self.download * DISK_FACTOR)
+        )?;
+        write!(f, "API requests:  {} to find the repos", self.pages)?;
+        match self.code_searches {
+            0 => writeln!(f)?,
+            n => writeln!(f, ", and up to {n} code searches for --prefilter")?,
+        }
+        writeln!(f, "Runtime:       {}", rough_duration(self.runtime()))
+    }
+}
@@ -0,0 +1,104 @@
+use crate::code_queries::QueryResults;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+/// Lines of context before and after each example.
+const CONTEXT: u64 = 2;
+
+/// Where a match was found: a file, and a 1-based line number.
+pub type Location = (PathBuf, u64);
+
+/// A sample match, with a few lines of surrounding context.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Example {
+    /// The column the match was counted in.
+    pub column: String,
+    /// The file, relative to the repo.
+    pub path: PathBuf,
+    pub line: u64,
+    /// Line numbers and lines around the match, including the matching line.
+    pub context: Vec<(u64, String)>,
+}
+
+/// Read the context of every location, at most `limit` per column.
+pub fn collect(
+    root: &Path,
+    mut locations: HashMap<String, Vec<Location>>,
+    limit: usize,
+) -> Vec<Example> {
+    let mut files: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    let mut examples = Vec::new();
+
+    let mut columns: Vec<String> = locations.keys().cloned().collect();
+    columns.sort();
+
+    for column in columns {
+        let mut found = locations.remove(&column).unwrap_or_default();
+        found.sort();
+        found.truncate(limit);
+
+        for (path, line) in found {
+            let lines = files.entry(path.clone()).or_insert_with(|| {
+                std::fs::read(&path)
+                    .map(|bytes| {
+                        String::from_utf8_lossy(&bytes)
+                            .lines()
+                            .map(ToOwned::to_owned)
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            });
+
+            let first = line.saturating_sub(CONTEXT).max(1);
+            let last = (line + CONTEXT).min(lines.len() as u64);
+            let context = (first..=last)
+                .map(|n| (n, lines[(n - 1) as usize].clone()))
+                .collect();
+
+            examples.push(Example {
+                column: column.clone(),
+                path: path.strip_prefix(root).unwrap_or(&path).to_owned(),
+                line,
+                context,
+            });
+        }
+    }
+
+    examples
+}
+
+/// Write the examples of all repos into a plain text file, grep style.
+pub async fn write(path: &Path, results: &[QueryResults]) -> io::Result<()> {
+    let f = File::create(path).await?;
+    let mut writer = BufWriter::new(f);
+
+    for result in results {
+        for example in result.examples.iter() {
+            let header = format!(
+                "{}/{}: {}\n{}:{}\n",
+                result.repo_owner,
+                result.repo_name,
+                example.column,
+                example.path.display(),
+                example.line
+            );
+            writer.write_all(header.as_bytes()).await?;
+
+            for (n, line) in example.context.iter() {
+                let sep = if *n == example.line { ':' } else { '-' };
+                let line = format!("{n:>6}{sep} {line}\n");
+                writer.write_all(line.as_bytes()).await?;
+            }
+
+            writer.write_all(b"--\n").await?;
+        }
+    }
+
+    writer.flush().await?;
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use crate::disk::OutOfSpace;
+use crate::forge::{Repo, RepoMeta};
+use crate::progress::Phase;
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use url::Url;
+
+/// The header of the CSV that `write` writes.
+pub const HEADER: &str = "repo,clone_url,phase,error";
+
+/// A repo that couldn't be analyzed, and the phase it failed in, if it got to one.
+#[derive(Debug)]
+pub struct RepoFailure {
+    pub repo: Repo,
+    pub phase: Option<Phase>,
+    pub timed_out: bool,
+    pub error: anyhow::Error,
+}
+
+impl RepoFailure {
+    /// The status of the repo in the results: clone_failed, search_failed, timeout, or
+    /// skipped.
+    pub fn status(&self) -> &'static str {
+        match self.phase {
+            _ if self.error.is::<OutOfSpace>() => "skipped",
+            _ if self.timed_out => "timeout",
+            Some(Phase::Search) => "search_failed",
+            _ => "clone_failed",
+        }
+    }
+}
+
+impl fmt::Display for RepoFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for RepoFailure {}
+
+/// The failed repo of a row of the CSV that `write` writes.
+pub fn parse_row(row: &str) -> Result<Repo> {
+    let columns: Vec<&str> = row.splitn(4, ',').collect();
+    let [full_name, clone_url, _, _] = columns[..] else {
+        return Err(anyhow!("Expected the columns {HEADER}"));
+    };
+
+    // repos searched where they were, with --local-only, are searched there again
+    if clone_url.starts_with("file:") {
+        let (owner, name) = full_name
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Not a repo: {full_name}"))?;
+        return Ok(Repo {
+            owner: owner.to_owned(),
+            name: name.to_owned(),
+            clone_url: Url::parse(clone_url)?,
+            swh_directory: None,
+            meta: RepoMeta::default(),
+        });
+    }
+    Repo::from_url(clone_url)
+}
+
+/// Where the failures of a run that writes its results to `out_file` go.
+pub fn path(out_file: &Path) -> PathBuf {
+    out_file.with_extension("failures.csv")
+}
+
+/// Write the failed repos as CSV, one row per repo. The error is the last column, and may
+/// contain commas, but no newlines.
+pub async fn write(path: &Path, failures: &[RepoFailure]) -> io::Result<()> {
+    let f = File::create(path).await?;
+    let mut writer = BufWriter::new(f);
+
+    writer.write_all(HEADER.as_bytes()).await?;
+    writer.write_u8(b'\n').await?;
+
+    for failure in failures {
+        let repo = &failure.repo;
+        let error = format!("{:#}", failure.error).replace(['\r', '\n'], " ");
+        let row = format!(
+            "{}/{},{},{},{}\n",
+            repo.owner,
+            repo.name,
+            repo.clone_url,
+            failure.phase.map(Phase::name).unwrap_or_default(),
+            error,
+        );
+        writer.write_all(row.as_bytes()).await?;
+    }
+
+    writer.flush().await?;
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use crate::forge::Repo;
+use crate::size::ByteSize;
+use crate::RunCmd;
+use anyhow::{anyhow, Context, Result};
+use regex::RegexSet;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Why repos larger than `--max-repo-size` are skipped.
+pub const TOO_LARGE: &str = "larger than --max-repo-size";
+
+/// Translate a glob like "owner/*-mirror" into a regex matching whole full names, where
+/// `*` and `?` don't match the slash between owner and name.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("(?i)^");
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex_syntax::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Read `owner/name` patterns from a file, one per line, skipping empty lines and
+/// comments.
+fn read_patterns(path: &Path) -> Result<RegexSet> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+
+    let mut patterns = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !line.contains('/') {
+            return Err(anyhow!("Expected owner/name in {path:?}, got {line}"));
+        }
+        patterns.push(glob_to_regex(line));
+    }
+
+    log::info!("Excluding {} patterns from {:?}", patterns.len(), path);
+    Ok(RegexSet::new(patterns)?)
+}
+
+/// Lowercase owners from a comma-separated list.
+fn split_owners(owners: &Option<String>) -> Option<HashSet<String>> {
+    owners.as_ref().map(|owners| {
+        owners
+            .split(',')
+            .map(|owner| owner.trim().to_lowercase())
+            .filter(|owner| !owner.is_empty())
+            .collect()
+    })
+}
+
+/// Which repos to skip, wherever they were found.
+#[derive(Debug, Default)]
+pub struct RepoFilter {
+    exclude: Option<RegexSet>,
+    /// Lowercase owners, one of which a repo must belong to.
+    only_owners: Option<HashSet<String>>,
+    /// Lowercase owners whose repos are skipped.
+    skip_owners: Option<HashSet<String>>,
+    max_size: Option<ByteSize>,
+}
+
+impl RepoFilter {
+    pub fn from_argh(argh: &RunCmd) -> Result<Self> {
+        let exclude = match &argh.exclude_file {
+            Some(path) => Some(read_patterns(path)?),
+            None => None,
+        };
+
+        Ok(Self {
+            exclude,
+            only_owners: split_owners(&argh.only_owners),
+            skip_owners: split_owners(&argh.skip_owners),
+            max_size: argh.max_repo_size,
+        })
+    }
+
+    /// Why to skip a repo, if it should be skipped.
+    pub fn skip_reason(&self, repo: &Repo) -> Option<&'static str> {
+        let full_name = format!("{}/{}", repo.owner, repo.name);
+        if self
+            .exclude
+            .as_ref()
+            .is_some_and(|exclude| exclude.is_match(&full_name))
+        {
+            return Some("excluded");
+        }
+
+        let owner = repo.owner.to_lowercase();
+        if self
+            .only_owners
+            .as_ref()
+            .is_some_and(|owners| !owners.contains(&owner))
+        {
+            return Some("not owned by any of --only-owners");
+        }
+        if self
+            .skip_owners
+            .as_ref()
+            .is_some_and(|owners| owners.contains(&owner))
+        {
+            return Some("owned by one of --skip-owners");
+        }
+        if let (Some(max_size), Some(size)) = (self.max_size, repo.meta.size) {
+            // forges tell sizes in kilobytes
+            if size * 1024 > max_size.bytes() {
+                return Some(TOO_LARGE);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,570 @@
+use crate::bitbucket::BitbucketSearch;
+use crate::code_queries::CodeQueries;
+use crate::gitea::GiteaSearch;
+use crate::github::GithubRepos;
+use crate::lockfile::LockfileDependencies;
+use crate::registry::{self, CratesIoSearch, NpmSearch, PypiSearch, RegistryKind};
+use crate::swh::SwhOrigins;
+use crate::RunCmd;
+use crate::{failures, metadata};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use octocrab::models::Repository;
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use url::Url;
+
+/// A repo to clone and search, wherever it is hosted.
+#[derive(Debug, Clone)]
+pub struct Repo {
+    pub owner: String,
+    pub name: String,
+    pub clone_url: Url,
+    /// The Software Heritage directory to download instead of cloning, for repos that may
+    /// no longer exist where they were hosted.
+    pub swh_directory: Option<String>,
+    pub meta: RepoMeta,
+}
+
+/// What the forge a repo was found on tells about it, if anything.
+#[derive(Debug, Clone, Default)]
+pub struct RepoMeta {
+    pub stars: Option<u64>,
+    /// The main language.
+    pub language: Option<String>,
+    /// In kilobytes.
+    pub size: Option<u64>,
+    /// When the repo was last pushed to, or updated for forges that don't tell.
+    pub pushed_at: Option<DateTime<Utc>>,
+    pub topics: Vec<String>,
+    /// The clone URL of the repo this one is a fork of.
+    pub parent: Option<Url>,
+    /// The URL to clone the repo over SSH, e.g. "git@github.com:owner/name.git".
+    pub ssh_url: Option<String>,
+}
+
+/// Parse a date like "2024-01-31T12:00:00Z", as forges' APIs return them.
+pub fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+impl TryFrom<Repository> for Repo {
+    type Error = anyhow::Error;
+
+    fn try_from(repo: Repository) -> Result<Self> {
+        let owner = repo
+            .owner
+            .ok_or_else(|| anyhow!("Repo without an owner!"))?
+            .login;
+        let clone_url = repo
+            .clone_url
+            .ok_or_else(|| anyhow!("Repo without a clone URL!"))?;
+        let meta = RepoMeta {
+            stars: repo.stargazers_count.map(u64::from),
+            language: repo
+                .language
+                .as_ref()
+                .and_then(|language| language.as_str())
+                .map(ToOwned::to_owned),
+            size: repo.size.map(u64::from),
+            pushed_at: repo.pushed_at,
+            topics: repo.topics.unwrap_or_default(),
+            parent: repo.parent.and_then(|parent| parent.clone_url),
+            ssh_url: repo.ssh_url,
+        };
+
+        Ok(Self {
+            owner,
+            name: repo.name,
+            clone_url,
+            swh_directory: None,
+            meta,
+        })
+    }
+}
+
+impl Repo {
+    /// Find a repo by its web URL, like "https://github.com/owner/name", or its clone URL.
+    ///
+    /// Anything after the name, like "/tree/main/some/crate", is ignored.
+    pub fn from_url(url: &str) -> Result<Self> {
+        let url = url.trim();
+        // e.g. "git+https://github.com/owner/name.git", as found in package.json files
+        let url = url.strip_prefix("git+").unwrap_or(url);
+        // scp-like ssh URLs, e.g. "git@github.com:owner/name.git"
+        let url = match url.split_once(':') {
+            Some((user_host, path)) if user_host.contains('@') && !path.starts_with("//") => {
+                Url::parse(&format!("ssh://{user_host}/{path}"))?
+            }
+            _ => Url::parse(url)?,
+        };
+
+        let mut segments = url
+            .path_segments()
+            .ok_or_else(|| anyhow!("Not a repo URL: {url}"))?
+            .filter(|segment| !segment.is_empty());
+        let (Some(owner), Some(name)) = (segments.next(), segments.next()) else {
+            return Err(anyhow!("Not a repo URL: {url}"));
+        };
+        let owner = owner.to_owned();
+        let name = name.strip_suffix(".git").unwrap_or(name).to_owned();
+
+        // always clone via https, even if the URL is for git:// or a browser
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("Not a repo URL: {url}"))?;
+        let port = url
+            .port()
+            .map(|port| format!(":{port}"))
+            .unwrap_or_default();
+        let clone_url = Url::parse(&format!("https://{host}{port}/{owner}/{name}"))?;
+
+        Ok(Self {
+            owner,
+            name,
+            clone_url,
+            swh_directory: None,
+            meta: RepoMeta::default(),
+        })
+    }
+
+    /// The URL to clone the repo from with `protocol`.
+    pub fn remote_url(&self, protocol: CloneProtocol) -> String {
+        match &self.meta.ssh_url {
+            Some(ssh_url) if protocol == CloneProtocol::Ssh => ssh_url.clone(),
+            _ => remote_url(&self.clone_url, protocol),
+        }
+    }
+
+    /// Identifies a repo however its URL is written, e.g. "github.com/owner/name".
+    pub fn key(&self) -> String {
+        url_key(&self.clone_url)
+    }
+
+    /// The repo that this repo's URL redirects to, e.g. after it was renamed or
+    /// transferred, or the repo itself.
+    pub async fn resolve_redirects(&self, client: &reqwest::Client) -> Result<Self> {
+        let scheme = self.clone_url.scheme();
+        if self.swh_directory.is_some() || !(scheme == "https" || scheme == "http") {
+            return Ok(self.clone());
+        }
+
+        let mut url = self.clone_url.clone();
+        let path = url.path().to_owned();
+        url.set_path(path.strip_suffix(".git").unwrap_or(&path));
+
+        let response = client.head(url.clone()).send().await?.error_for_status()?;
+        let target = response.url();
+        if target.host_str() != url.host_str() || target.path() == url.path() {
+            return Ok(self.clone());
+        }
+
+        let mut repo = Repo::from_url(target.as_str())?;
+        repo.meta = self.meta.clone();
+        log::debug!(
+            "{}/{} moved to {}/{}",
+            self.owner,
+            self.name,
+            repo.owner,
+            repo.name
+        );
+        Ok(repo)
+    }
+}
+
+/// The URL to clone the repo at an HTTPS `url` from with `protocol`, assuming that its
+/// host takes SSH clones as the user "git", like GitHub, Gitea, and Bitbucket do.
+pub fn remote_url(url: &Url, protocol: CloneProtocol) -> String {
+    let https = url.scheme() == "https" || url.scheme() == "http";
+    match url.host_str() {
+        Some(host) if https && protocol == CloneProtocol::Ssh => {
+            format!("ssh://git@{host}{}", url.path())
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// Identifies the repo at a URL, see [`Repo::key`].
+pub fn url_key(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default();
+    let path = url.path().trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    format!("{host}{path}").to_lowercase()
+}
+
+/// The repos already cloned into a directory, as `<owner>/<name>`, or with `--bare`, as
+/// `<owner>/<name>.git`.
+pub struct LocalRepos {
+    repos: Option<Vec<Repo>>,
+}
+
+impl LocalRepos {
+    pub fn from_argh(argh: &RunCmd) -> Result<Self> {
+        if argh.rm {
+            return Err(anyhow!("--rm can't be combined with --local-only"));
+        }
+        if argh.forge != ForgeKind::Github || argh.registry.is_some() {
+            return Err(anyhow!(
+                "--local-only can't be combined with --forge or --registry"
+            ));
+        }
+
+        let target_dir = std::fs::canonicalize(&argh.target_dir)?;
+        let mut repos = Vec::new();
+        for owner in sorted_dirs(&target_dir)? {
+            for path in sorted_dirs(&owner)? {
+                let (Some(owner), Some(name)) = (owner.file_name(), path.file_name()) else {
+                    continue;
+                };
+                let name = name.to_string_lossy();
+                let name = match argh.bare {
+                    true => match name.strip_suffix(".git") {
+                        Some(name) => name.to_owned(),
+                        // left behind by an interrupted search
+                        None => continue,
+                    },
+                    false => name.into_owned(),
+                };
+                let clone_url = Url::from_directory_path(&path)
+                    .map_err(|_| anyhow!("Not a directory: {path:?}"))?;
+
+                repos.push(Repo {
+                    owner: owner.to_string_lossy().into_owned(),
+                    name,
+                    clone_url,
+                    swh_directory: None,
+                    meta: RepoMeta::default(),
+                });
+            }
+        }
+
+        log::info!("Found {} repos in {:?}", repos.len(), target_dir);
+        Ok(Self { repos: Some(repos) })
+    }
+
+    pub fn next_page(&mut self) -> Option<Vec<Repo>> {
+        self.repos.take()
+    }
+}
+
+/// Repos listed one per line, as `owner/name` on GitHub or as URLs, or in the CSV that
+/// `search` writes, or in the CSV of the repos that failed in a run.
+pub struct RepoList {
+    repos: Option<Vec<Repo>>,
+}
+
+impl RepoList {
+    pub fn from_argh(argh: &RunCmd) -> Result<Self> {
+        let Some(path) = argh.repo_list.as_ref().or(argh.retry_from.as_ref()) else {
+            return Err(anyhow!("No repo list given"));
+        };
+        Self::from_path(path)
+    }
+
+    /// The repos listed in the file in `path`, or on stdin for `-`.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let text = match path.as_os_str() == "-" {
+            true => std::io::read_to_string(std::io::stdin()).context("Failed to read stdin")?,
+            false => {
+                std::fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?
+            }
+        };
+
+        let mut seen = HashSet::new();
+        let mut repos = Vec::new();
+        let mut lines = text.lines().peekable();
+        let csv = lines.next_if_eq(&metadata::HEADER).is_some();
+        let failures = !csv && lines.next_if_eq(&failures::HEADER).is_some();
+        for line in lines {
+            // e.g. `gh repo list` prints the description and more after the name
+            let Some(entry) = line.split_whitespace().next() else {
+                continue;
+            };
+            if entry.starts_with('#') {
+                continue;
+            }
+
+            let repo = if csv {
+                metadata::parse_row(line)
+            } else if failures {
+                failures::parse_row(line)
+            } else if entry.contains("://") || entry.starts_with("git@") {
+                Repo::from_url(entry)
+            } else {
+                Repo::from_url(&format!("https://github.com/{entry}"))
+            };
+            match repo {
+                Ok(repo) => {
+                    if seen.insert(repo.clone_url.clone()) {
+                        repos.push(repo);
+                    }
+                }
+                Err(e) => log::warn!("Skipping {entry}: {e}"),
+            }
+        }
+
+        log::info!("Found {} repos in {:?}", repos.len(), path);
+        Ok(Self { repos: Some(repos) })
+    }
+
+    pub fn next_page(&mut self) -> Option<Vec<Repo>> {
+        self.repos.take()
+    }
+}
+
+/// The directories in a directory, except for hidden ones, sorted by name.
+fn sorted_dirs(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if entry.file_type()?.is_dir() && !hidden {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// How to clone repos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CloneProtocol {
+    #[default]
+    Https,
+    /// With the user's SSH keys.
+    Ssh,
+}
+
+impl FromStr for CloneProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "https" => Ok(CloneProtocol::Https),
+            "ssh" => Ok(CloneProtocol::Ssh),
+            _ => Err(format!("unknown clone protocol: {s}")),
+        }
+    }
+}
+
+/// The kind of hosting to search for repos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForgeKind {
+    #[default]
+    Github,
+    /// Gitea, or a compatible forge like Forgejo.
+    Gitea,
+    /// Bitbucket Cloud.
+    Bitbucket,
+}
+
+impl FromStr for ForgeKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "github" => Ok(ForgeKind::Github),
+            "gitea" | "forgejo" => Ok(ForgeKind::Gitea),
+            "bitbucket" => Ok(ForgeKind::Bitbucket),
+            _ => Err(format!("unknown forge: {s}")),
+        }
+    }
+}
+
+/// The next page of repos of a [`RepoSource`], or `None` after the last page.
+pub type PageFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<Vec<Repo>>>> + Send + 'a>>;
+
+/// A rate limit of a source's API, as far as it's tracked.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    /// The calls that use it up, like "search".
+    pub resource: &'static str,
+    pub remaining: usize,
+    pub limit: usize,
+    pub reset: DateTime<Utc>,
+}
+
+/// Where the repos of a run come from, a page at a time: a search or listing on a forge or a
+/// package registry, a list, or the target directory. Repos come with the URLs to clone them
+/// from, and with what the source tells about them. The [`Runner`](crate::Runner) only
+/// ever takes repos from a source, so that new forges, or mock ones, can be added as
+/// sources of their own.
+pub trait RepoSource: Send {
+    /// The query string sent to the forge, if repos are searched for.
+    fn query(&self) -> Option<&str> {
+        None
+    }
+
+    /// The next page of repos, or `None` after the last page.
+    fn next_page(&mut self) -> PageFuture<'_>;
+
+    /// Whether the pages were all the repos that the source found, once they were taken,
+    /// rather than only the first ones that a search returns.
+    fn complete(&self) -> bool {
+        true
+    }
+
+    /// The rate limits of the source's API, as far as they're tracked.
+    fn rate_limits(&self) -> Vec<RateLimit> {
+        Vec::new()
+    }
+}
+
+/// The source of repos that the options of a run name.
+pub fn from_argh(argh: &RunCmd, queries: &CodeQueries) -> Result<Box<dyn RepoSource>> {
+    if argh.local_only && argh.prefilter {
+        return Err(anyhow!("--prefilter can't be combined with --local-only"));
+    }
+    // the repos that failed are retried wherever they are
+    if argh.local_only && argh.retry_from.is_none() {
+        return Ok(Box::new(LocalRepos::from_argh(argh)?));
+    }
+
+    let github = argh.forge == ForgeKind::Github
+        && argh.registry.is_none()
+        && argh.repo_list.is_none()
+        && argh.retry_from.is_none()
+        && argh.from_swh.is_none()
+        && argh.from_lockfile.is_none();
+    if !github && argh.from_markdown.is_some() {
+        return Err(anyhow!("--from-markdown only supports GitHub"));
+    }
+    if !github && argh.forks_of.is_some() {
+        return Err(anyhow!("--forks-of only supports GitHub"));
+    }
+    if !github && argh.dependents_of.is_some() {
+        return Err(anyhow!("--dependents-of only supports GitHub"));
+    }
+    if !github && argh.prefilter {
+        return Err(anyhow!("--prefilter only supports GitHub"));
+    }
+    if !github && argh.graphql {
+        return Err(anyhow!("--graphql only supports GitHub"));
+    }
+    if !github && (argh.per_page.is_some() || argh.max_pages.is_some()) {
+        return Err(anyhow!("--per-page and --max-pages only support GitHub"));
+    }
+    if !github && argh.api_cache.is_some() {
+        return Err(anyhow!("--api-cache only supports GitHub"));
+    }
+
+    if argh.retry_from.is_some() {
+        registry::check_unsupported(argh, "--retry-from")?;
+        if argh.forge != ForgeKind::Github || argh.registry.is_some() || argh.repo_list.is_some() {
+            return Err(anyhow!(
+                "--retry-from can't be combined with --forge, --registry, or --repo-list"
+            ));
+        }
+        return Ok(Box::new(RepoList::from_argh(argh)?));
+    }
+
+    if argh.repo_list.is_some() {
+        registry::check_unsupported(argh, "--repo-list")?;
+        if argh.forge != ForgeKind::Github || argh.registry.is_some() {
+            return Err(anyhow!(
+                "--repo-list can't be combined with --forge or --registry"
+            ));
+        }
+        return Ok(Box::new(RepoList::from_argh(argh)?));
+    }
+
+    if argh.from_lockfile.is_some() {
+        if argh.forge != ForgeKind::Github || argh.registry.is_some() || argh.from_swh.is_some() {
+            return Err(anyhow!(
+                "--from-lockfile can't be combined with --forge, --registry, or --from-swh"
+            ));
+        }
+        return Ok(Box::new(LockfileDependencies::from_argh(argh)?));
+    }
+
+    if argh.from_swh.is_some() {
+        if argh.forge != ForgeKind::Github || argh.registry.is_some() {
+            return Err(anyhow!(
+                "--from-swh can't be combined with --forge or --registry"
+            ));
+        }
+        return Ok(Box::new(SwhOrigins::from_argh(argh)?));
+    }
+
+    if let Some(registry) = argh.registry {
+        if argh.forge != ForgeKind::Github {
+            return Err(anyhow!("--registry can't be combined with --forge"));
+        }
+        return match registry {
+            RegistryKind::CratesIo => Ok(Box::new(CratesIoSearch::from_argh(argh)?)),
+            RegistryKind::Npm => Ok(Box::new(NpmSearch::from_argh(argh)?)),
+            RegistryKind::Pypi => Ok(Box::new(PypiSearch::from_argh(argh)?)),
+        };
+    }
+
+    match argh.forge {
+        ForgeKind::Github => Ok(Box::new(GithubRepos::from_argh(argh, queries)?)),
+        ForgeKind::Gitea => Ok(Box::new(GiteaSearch::from_argh(argh)?)),
+        ForgeKind::Bitbucket => Ok(Box::new(BitbucketSearch::from_argh(argh)?)),
+    }
+}
+
+impl RepoSource for GiteaSearch {
+    fn next_page(&mut self) -> PageFuture<'_> {
+        Box::pin(self.next_page())
+    }
+}
+
+impl RepoSource for BitbucketSearch {
+    fn next_page(&mut self) -> PageFuture<'_> {
+        Box::pin(self.next_page())
+    }
+}
+
+impl RepoSource for CratesIoSearch {
+    fn next_page(&mut self) -> PageFuture<'_> {
+        Box::pin(self.next_page())
+    }
+}
+
+impl RepoSource for NpmSearch {
+    fn next_page(&mut self) -> PageFuture<'_> {
+        Box::pin(self.next_page())
+    }
+}
+
+impl RepoSource for PypiSearch {
+    fn next_page(&mut self) -> PageFuture<'_> {
+        Box::pin(self.next_page())
+    }
+}
+
+impl RepoSource for RepoList {
+    fn next_page(&mut self) -> PageFuture<'_> {
+        let page = self.next_page();
+        Box::pin(async move { Ok(page) })
+    }
+}
+
+impl RepoSource for LockfileDependencies {
+    fn next_page(&mut self) -> PageFuture<'_> {
+        Box::pin(self.next_page())
+    }
+
+    fn complete(&self) -> bool {
+        self.complete()
+    }
+}
+
+impl RepoSource for SwhOrigins {
+    fn next_page(&mut self) -> PageFuture<'_> {
+        Box::pin(self.next_page())
+    }
+}
+
+impl RepoSource for LocalRepos {
+    fn next_page(&mut self) -> PageFuture<'_> {
+        let page = self.next_page();
+        Box::pin(async move { Ok(page) })
+    }
+}
@@ -0,0 +1,35 @@
+use std::sync::Mutex;
+use tokio::process::Command;
+
+/// Configuration that every git command of the run is given, as keys and values.
+static CONFIG: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Give every git command of the run `key = value`, like `git -c`, but without putting it on
+/// the command line.
+pub fn configure(key: &str, value: &str) {
+    let mut config = CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+    config.push((key.to_owned(), value.to_owned()));
+}
+
+/// A git command, with the configuration of [`configure`].
+pub fn command() -> Command {
+    let mut command = Command::new("git");
+    let config = CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+    if config.is_empty() {
+        return command;
+    }
+
+    // added to any configuration given through the environment already
+    let mut count: usize = std::env::var("GIT_CONFIG_COUNT")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0);
+    for (key, value) in config.iter() {
+        command
+            .env(format!("GIT_CONFIG_KEY_{count}"), key)
+            .env(format!("GIT_CONFIG_VALUE_{count}"), value);
+        count += 1;
+    }
+    command.env("GIT_CONFIG_COUNT", count.to_string());
+    command
+}
@@ -0,0 +1,155 @@
+use crate::forge::{parse_date, Repo, RepoMeta};
+use crate::RunCmd;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use url::Url;
+
+/// The forge to search if no URL is given.
+pub const DEFAULT_URL: &str = "https://codeberg.org";
+
+/// Repos per page, the maximum Gitea allows by default.
+const PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Deserialize)]
+struct SearchResults {
+    data: Vec<GiteaRepo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GiteaRepo {
+    name: String,
+    owner: GiteaUser,
+    clone_url: Url,
+    /// The primary language, or an empty string.
+    #[serde(default)]
+    language: String,
+    stars_count: Option<u64>,
+    /// In kilobytes.
+    size: Option<u64>,
+    updated_at: Option<String>,
+    #[serde(default)]
+    topics: Vec<String>,
+    /// The repo this one is a fork of.
+    parent: Option<GiteaParent>,
+    ssh_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GiteaParent {
+    clone_url: Url,
+}
+
+#[derive(Debug, Deserialize)]
+struct GiteaUser {
+    login: String,
+}
+
+/// A repo search on a Gitea instance, or a compatible forge like Forgejo.
+pub struct GiteaSearch {
+    client: reqwest::Client,
+    search_url: Url,
+    token: Option<String>,
+    keywords: String,
+    /// Only keep repos with one of these primary languages (lowercase), since Gitea can't
+    /// filter by language itself.
+    languages: Option<Vec<String>>,
+    /// The next page to fetch, if any.
+    page: Option<usize>,
+}
+
+impl GiteaSearch {
+    pub fn from_argh(argh: &RunCmd) -> Result<Self> {
+        for (flag, value) in [
+            ("--pushed", &argh.pushed),
+            ("--stars", &argh.stars),
+            ("--topics", &argh.topics),
+            ("--org", &argh.org),
+            ("--user", &argh.user),
+            ("--starred-by", &argh.starred_by),
+            ("--licenses", &argh.licenses),
+            ("--any-language", &argh.any_language),
+        ] {
+            if value.is_some() {
+                return Err(anyhow!("{flag} is not supported for Gitea"));
+            }
+        }
+
+        let mut base = match &argh.forge_url {
+            Some(url) => url.clone(),
+            None => Url::parse(DEFAULT_URL)?,
+        };
+        // so that joining doesn't replace the last path segment of e.g. example.org/gitea
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        let search_url = base.join("api/v1/repos/search")?;
+
+        let languages = argh.languages.as_ref().map(|languages| {
+            languages
+                .split(',')
+                .map(|language| language.trim().to_lowercase())
+                .collect()
+        });
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            search_url,
+            token: std::env::var("GITEA_TOKEN").ok(),
+            keywords: argh.keywords.clone().unwrap_or_default(),
+            languages,
+            page: Some(1),
+        })
+    }
+
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Repo>>> {
+        let Some(page) = self.page else {
+            return Ok(None);
+        };
+
+        // Gitea matches any of several comma-separated keywords
+        let mut request = self.client.get(self.search_url.clone()).query(&[
+            ("q", self.keywords.as_str()),
+            ("includeDesc", "true"),
+            ("sort", "updated"),
+            ("order", "desc"),
+            ("page", &page.to_string()),
+            ("limit", &PAGE_SIZE.to_string()),
+        ]);
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("token {token}"));
+        }
+
+        let results: SearchResults = request.send().await?.error_for_status()?.json().await?;
+
+        self.page = match results.data.len() < PAGE_SIZE {
+            true => None,
+            false => Some(page + 1),
+        };
+
+        let repos = results
+            .data
+            .into_iter()
+            .filter(|repo| match &self.languages {
+                Some(languages) => languages.contains(&repo.language.to_lowercase()),
+                None => true,
+            })
+            .map(|repo| Repo {
+                owner: repo.owner.login,
+                name: repo.name,
+                clone_url: repo.clone_url,
+                swh_directory: None,
+                meta: RepoMeta {
+                    stars: repo.stars_count,
+                    language: Some(repo.language).filter(|language| !language.is_empty()),
+                    size: repo.size,
+                    pushed_at: repo.updated_at.as_deref().and_then(parse_date),
+                    topics: repo.topics,
+                    parent: repo.parent.map(|parent| parent.clone_url),
+                    ssh_url: repo.ssh_url,
+                },
+            })
+            .collect();
+
+        Ok(Some(repos))
+    }
+}
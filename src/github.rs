@@ -0,0 +1,720 @@
+use crate::code_queries::CodeQueries;
+use crate::dependents::Dependents;
+use crate::duration::HumanDuration;
+use crate::forge::{PageFuture, RateLimit, Repo, RepoSource};
+use crate::github_cache::ApiCache;
+use crate::github_graphql::{self, GraphqlRepo};
+use crate::github_query::GithubQuery;
+use crate::github_rate_limit::{request_eta, RateLimiter, Resource};
+use crate::markdown;
+use crate::proxy;
+use crate::RunCmd;
+use anyhow::{anyhow, Context, Result};
+use chrono::DateTime;
+use http::Uri;
+use octocrab::models::Repository;
+use octocrab::{Octocrab, Page};
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::Duration;
+
+/// How often an API call is retried after a transient error, before giving up.
+const API_RETRIES: u32 = 5;
+/// The wait before retrying an API call the first time, doubled for every retry after.
+const API_BACKOFF: Duration = Duration::from_secs(2);
+/// The most repos the API returns per request.
+const MAX_PER_PAGE: u32 = 100;
+/// The most results a search returns, however many it found.
+const MAX_SEARCH_RESULTS: u64 = 1000;
+
+/// Whether an API error may go away by itself, like a server or network error.
+fn is_transient(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<octocrab::Error>() {
+        Some(octocrab::Error::GitHub { source, .. }) => source.status_code.is_server_error(),
+        Some(octocrab::Error::Hyper { .. } | octocrab::Error::Service { .. }) => true,
+        _ => false,
+    }
+}
+
+/// Make an API call, and retry it with exponential backoff after transient errors, so that
+/// a single hiccup doesn't end a long run.
+pub async fn with_retries<T, E, Fut>(what: &str, mut call: impl FnMut() -> Fut) -> Result<T>
+where
+    E: Into<anyhow::Error>,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut backoff = API_BACKOFF;
+    let mut retries = 0;
+    loop {
+        match call().await.map_err(Into::into) {
+            Err(e) if retries < API_RETRIES && is_transient(&e) => {
+                retries += 1;
+                log::warn!("{what} failed, retrying in {backoff:?} ({retries}/{API_RETRIES}): {e}");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Where a search is in the pages of its results.
+enum Cursor {
+    Start,
+    Next(Uri),
+    /// The end cursor of the last page of a GraphQL search.
+    After(String),
+    Done,
+}
+
+/// Where to get repos from.
+enum Source {
+    /// The results of a repo search with this query string.
+    Search(String),
+    /// The results of a GraphQL search with this query string.
+    GraphqlSearch(String),
+    /// All repos of an organization.
+    Org(String),
+    /// All public repos of a user.
+    User(String),
+    /// The repos a user starred.
+    StarredBy(String),
+    /// Links to repos, by owner and name, which may be outdated if a repo was renamed or
+    /// transferred.
+    Links(Vec<(String, String)>),
+    /// A repo, by owner and name, and all of its forks.
+    ForksOf(String, String),
+    /// The repos depending on a repo.
+    DependentsOf(Dependents),
+}
+
+impl Source {
+    fn from_argh(argh: &RunCmd) -> Result<Self> {
+        if argh.org.is_some() && argh.user.is_some() {
+            return Err(anyhow!("--org and --user can't be combined"));
+        }
+
+        let sources = [
+            ("--all-repos", argh.all_repos),
+            ("--starred-by", argh.starred_by.is_some()),
+            ("--from-markdown", argh.from_markdown.is_some()),
+            ("--forks-of", argh.forks_of.is_some()),
+            ("--dependents-of", argh.dependents_of.is_some()),
+        ];
+        let given: Vec<&str> = sources
+            .iter()
+            .filter(|(_, given)| *given)
+            .map(|(flag, _)| *flag)
+            .collect();
+        if given.len() > 1 {
+            return Err(anyhow!("{} can't be combined", given.join(" and ")));
+        }
+        if argh.graphql && (argh.all_repos || !given.is_empty()) {
+            let source = given.first().copied().unwrap_or("--all-repos");
+            return Err(anyhow!("--graphql only applies to searches, not {source}"));
+        }
+        if !argh.all_repos && !given.is_empty() && (argh.org.is_some() || argh.user.is_some()) {
+            return Err(anyhow!(
+                "--org and --user can't be combined with {}",
+                given[0]
+            ));
+        }
+
+        if let Some(user) = &argh.starred_by {
+            return Ok(Source::StarredBy(user.clone()));
+        }
+
+        if let Some(path) = &argh.from_markdown {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {path:?}"))?;
+            let mut links = markdown::github_repos(&text);
+            log::info!("Found {} repo links in {:?}", links.len(), path);
+            // taken from the back
+            links.reverse();
+            return Ok(Source::Links(links));
+        }
+
+        if let Some(full_name) = &argh.forks_of {
+            let (owner, name) = full_name
+                .split_once('/')
+                .ok_or_else(|| anyhow!("--forks-of needs owner/name, got {full_name}"))?;
+            return Ok(Source::ForksOf(owner.to_owned(), name.to_owned()));
+        }
+
+        if let Some(full_name) = &argh.dependents_of {
+            let (owner, name) = full_name
+                .split_once('/')
+                .ok_or_else(|| anyhow!("--dependents-of needs owner/name, got {full_name}"))?;
+            return Ok(Source::DependentsOf(Dependents::new(owner, name)?));
+        }
+
+        match (&argh.org, &argh.user, argh.all_repos) {
+            (Some(org), _, true) => Ok(Source::Org(org.clone())),
+            (_, Some(user), true) => Ok(Source::User(user.clone())),
+            (None, None, true) => Err(anyhow!("--all-repos needs --org or --user")),
+            (_, _, false) => {
+                let query = GithubQuery::from_argh(argh).to_query_string()?;
+                match argh.graphql {
+                    // GraphQL searches can't be sorted other than with a qualifier
+                    true => Ok(Source::GraphqlSearch(format!("{query} sort:updated-desc"))),
+                    false => Ok(Source::Search(query)),
+                }
+            }
+        }
+    }
+}
+
+/// Which listed repos to skip, since only searches can filter on GitHub's side, and which
+/// found repos to skip by what searches can't filter on.
+#[derive(Debug, Default)]
+struct ListFilter {
+    /// Lowercase names of which a repo's main language must be one.
+    languages: Option<Vec<String>>,
+    /// Lowercase names of which any language of a repo must be one.
+    any_languages: Option<Vec<String>>,
+    /// Lowercase SPDX ids of which a repo's license must be one.
+    licenses: Option<Vec<String>>,
+    skip_archived: bool,
+    skip_forks: bool,
+}
+
+fn split_lowercase(s: &Option<String>) -> Option<Vec<String>> {
+    s.as_ref().map(|s| {
+        s.split(',')
+            .map(|item| item.trim().to_lowercase())
+            .collect()
+    })
+}
+
+fn contains_lowercase(list: &Option<Vec<String>>, item: Option<&str>) -> bool {
+    match list {
+        Some(list) => item.is_some_and(|item| list.contains(&item.to_lowercase())),
+        None => true,
+    }
+}
+
+impl ListFilter {
+    fn from_argh(argh: &RunCmd) -> Self {
+        Self {
+            languages: split_lowercase(&argh.languages),
+            any_languages: split_lowercase(&argh.any_language),
+            licenses: split_lowercase(&argh.licenses),
+            skip_archived: argh.skip_archived,
+            skip_forks: argh.skip_forks,
+        }
+    }
+
+    fn keep(&self, repo: &Repository) -> bool {
+        if self.skip_archived && repo.archived == Some(true) {
+            return false;
+        }
+        if self.skip_forks && repo.fork == Some(true) {
+            return false;
+        }
+
+        let language = repo
+            .language
+            .as_ref()
+            .and_then(|language| language.as_str());
+        let license = repo
+            .license
+            .as_ref()
+            .map(|license| license.spdx_id.as_str());
+        contains_lowercase(&self.languages, language) && contains_lowercase(&self.licenses, license)
+    }
+
+    fn keep_graphql(&self, repo: &GraphqlRepo) -> bool {
+        if self.skip_archived && repo.is_archived {
+            return false;
+        }
+        if self.skip_forks && repo.is_fork {
+            return false;
+        }
+        if self.any_languages.is_some()
+            && !repo
+                .languages()
+                .any(|language| contains_lowercase(&self.any_languages, Some(language)))
+        {
+            return false;
+        }
+
+        contains_lowercase(&self.languages, repo.primary_language())
+            && contains_lowercase(&self.licenses, repo.license())
+    }
+}
+
+/// The terms to look for with code search before cloning a repo, one per query.
+pub fn prefilter_terms(queries: &CodeQueries) -> Result<Vec<String>> {
+    if queries.iter().next().is_none() {
+        return Err(anyhow!("--prefilter needs a --query-file"));
+    }
+    let mut terms = Vec::new();
+    for query in queries.iter() {
+        let term = query.search_term().ok_or_else(|| {
+            anyhow!(
+                "--prefilter needs a literal of at least 3 characters in every query, but {} has none",
+                query.name
+            )
+        })?;
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    log::info!("Prefiltering repos on {terms:?}");
+    Ok(terms)
+}
+
+/// Repos from GitHub, found by a search or listed by the API.
+pub struct GithubRepos {
+    octocrab: Octocrab,
+    /// Waits for the rate limit of every call.
+    limiter: RateLimiter,
+    source: Source,
+    filter: ListFilter,
+    cursor: Cursor,
+    /// Lowercase full names of the repos so far, to skip links to the same repo under
+    /// different names.
+    seen: HashSet<String>,
+    /// Terms of which a repo must contain at least one, according to code search, to be
+    /// cloned.
+    prefilter: Option<Vec<String>>,
+    /// Repos per request.
+    per_page: u32,
+    /// The pages to stop after, if any, and those so far.
+    max_pages: Option<u32>,
+    pages: u32,
+    /// The number of repos a search found, as of its first page, of which only the first
+    /// [`MAX_SEARCH_RESULTS`] are returned.
+    total: Option<u64>,
+}
+
+impl GithubRepos {
+    pub fn from_argh(argh: &RunCmd, queries: &CodeQueries) -> Result<Self> {
+        let gh_token = std::env::var("GITHUB_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+        if gh_token.is_none() {
+            // code search and GraphQL aren't available at all without a token
+            if argh.prefilter {
+                return Err(anyhow!("--prefilter needs a GitHub token"));
+            }
+            if argh.graphql {
+                return Err(anyhow!("--graphql needs a GitHub token"));
+            }
+            log::warn!(
+                "No GitHub token, so searching at 10 requests per minute, and listing at 60 per \
+                 hour; set GITHUB_TOKEN, pass --token-file, or log in with gh for more"
+            );
+        }
+        let api_cache = argh.api_cache.as_deref().map(ApiCache::new).transpose()?;
+        let octocrab = proxy::github_client(gh_token, api_cache)?;
+
+        let source = Source::from_argh(argh)?;
+        if argh.any_language.is_some() && !argh.graphql {
+            return Err(anyhow!("--any-language needs --graphql"));
+        }
+        let per_page = match (argh.per_page, &source) {
+            (Some(per_page), _) if !(1..=MAX_PER_PAGE).contains(&per_page) => {
+                return Err(anyhow!("--per-page must be from 1 to {MAX_PER_PAGE}"));
+            }
+            (Some(per_page), _) => per_page,
+            (None, Source::GraphqlSearch(_)) => github_graphql::PAGE_SIZE,
+            (None, _) => MAX_PER_PAGE,
+        };
+        if argh.max_pages == Some(0) {
+            return Err(anyhow!("--max-pages must be at least 1"));
+        }
+
+        let filter = match source {
+            Source::Search(_) => ListFilter::default(),
+            _ => ListFilter::from_argh(argh),
+        };
+
+        // an estimate only counts the code searches
+        let prefilter = match argh.prefilter && !argh.estimate {
+            true => Some(prefilter_terms(queries)?),
+            false => None,
+        };
+
+        Ok(Self {
+            limiter: RateLimiter::new(octocrab.clone()),
+            octocrab,
+            source,
+            filter,
+            cursor: Cursor::Start,
+            seen: HashSet::new(),
+            prefilter,
+            per_page,
+            max_pages: argh.max_pages,
+            pages: 0,
+            total: None,
+        })
+    }
+
+    /// The query string of a search.
+    pub fn query(&self) -> Option<&str> {
+        match &self.source {
+            Source::Search(query) | Source::GraphqlSearch(query) => Some(query),
+            _ => None,
+        }
+    }
+
+    /// The first page of repos.
+    async fn first_page(&self) -> Result<Page<Repository>> {
+        let per_page = self.per_page.to_string();
+        let page = match &self.source {
+            Source::Search(query_string) => {
+                let page = self
+                    .limiter
+                    .call(Resource::Search, "Searching repos", || {
+                        self.octocrab
+                            .search()
+                            .repositories(query_string)
+                            .sort("updated")
+                            .order("desc")
+                            .per_page(self.per_page as u8)
+                            .send()
+                    })
+                    .await?;
+                self.log_eta(Resource::Search, page.total_count.unwrap_or_default());
+                page
+            }
+            Source::Org(org) => {
+                let params = [("per_page", per_page.as_str()), ("sort", "pushed")];
+                self.limiter
+                    .call(Resource::Core, "Listing repos", || {
+                        self.octocrab
+                            .get(format!("/orgs/{org}/repos"), Some(&params))
+                    })
+                    .await?
+            }
+            Source::User(user) => {
+                let params = [
+                    ("per_page", per_page.as_str()),
+                    ("sort", "pushed"),
+                    ("type", "owner"),
+                ];
+                self.limiter
+                    .call(Resource::Core, "Listing repos", || {
+                        self.octocrab
+                            .get(format!("/users/{user}/repos"), Some(&params))
+                    })
+                    .await?
+            }
+            Source::ForksOf(owner, name) => {
+                let params = [("per_page", per_page.as_str()), ("sort", "newest")];
+                let mut page: Page<Repository> = self
+                    .limiter
+                    .call(Resource::Core, "Listing forks", || {
+                        self.octocrab
+                            .get(format!("/repos/{owner}/{name}/forks"), Some(&params))
+                    })
+                    .await?;
+                // the repo itself, to compare its forks to
+                let repo = self
+                    .limiter
+                    .call(Resource::Core, "Looking up repo", || async {
+                        self.octocrab.repos(owner, name).get().await
+                    })
+                    .await?;
+                for fork in page.items.iter_mut() {
+                    fork.parent = Some(Box::new(repo.clone()));
+                }
+                page.items.insert(0, repo);
+                page
+            }
+            // looked up one by one in `next_links` and `next_dependents`, and searched in
+            // `next_graphql`, instead
+            Source::Links(_) | Source::DependentsOf(_) | Source::GraphqlSearch(_) => {
+                unreachable!()
+            }
+            Source::StarredBy(user) => {
+                let params = [("per_page", per_page.as_str())];
+                self.limiter
+                    .call(Resource::Core, "Listing starred repos", || {
+                        self.octocrab
+                            .get(format!("/users/{user}/starred"), Some(&params))
+                    })
+                    .await?
+            }
+        };
+
+        Ok(page)
+    }
+
+    /// Log how many pages of repos a search takes, and about how long with the rate limit of
+    /// `resource`, as of the first page.
+    fn log_eta(&self, resource: Resource, total: u64) {
+        let Some(rate) = self.limiter.tracked(resource) else {
+            return;
+        };
+        let resource_name = resource.name();
+        let results = total.min(MAX_SEARCH_RESULTS);
+        let mut pages = results.div_ceil(self.per_page.into());
+        if let Some(max_pages) = self.max_pages {
+            pages = pages.min(max_pages.into());
+        }
+        let eta = HumanDuration(request_eta(resource, pages.saturating_sub(1), &rate));
+        match total > results {
+            true => log::info!(
+                "Found {total} repos, of which the first {results} are returned on {pages} pages, taking about {eta} at the {resource_name} rate limit"
+            ),
+            false => log::info!(
+                "Found {total} repos on {pages} pages, taking about {eta} at the {resource_name} rate limit"
+            ),
+        }
+    }
+
+    /// Whether code search finds any of `terms` in a repo. Repos code search fails for are
+    /// kept, since they may still match.
+    async fn contains_any(&self, repo: &Repo, terms: &[String]) -> Result<bool> {
+        for term in terms {
+            let query = format!(
+                "\"{}\" repo:{}/{}",
+                term.replace('"', ""),
+                repo.owner,
+                repo.name
+            );
+            let search = || self.octocrab.search().code(&query).per_page(1).send();
+            match self
+                .limiter
+                .call(Resource::CodeSearch, "Code search", search)
+                .await
+            {
+                Ok(page) if page.total_count.unwrap_or(0) > 0 => return Ok(true),
+                Ok(_) => {}
+                Err(e) => {
+                    log::warn!("Code search failed for {}/{}: {e}", repo.owner, repo.name);
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Drop the repos code search finds none of the prefilter terms in.
+    async fn apply_prefilter(&self, repos: Vec<Repo>) -> Result<Vec<Repo>> {
+        let Some(terms) = &self.prefilter else {
+            return Ok(repos);
+        };
+
+        let mut kept = Vec::new();
+        for repo in repos {
+            if self.contains_any(&repo, terms).await? {
+                kept.push(repo);
+            } else {
+                log::info!(
+                    "Skipping {}/{}: no query terms found",
+                    repo.owner,
+                    repo.name
+                );
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Links looked up per page.
+    const LINKS_PER_PAGE: usize = 50;
+
+    /// Look up the next page of links, following renames and transfers.
+    async fn next_links(&mut self) -> Result<Option<Vec<Repo>>> {
+        let Source::Links(links) = &mut self.source else {
+            return Ok(None);
+        };
+        if links.is_empty() {
+            return Ok(None);
+        }
+        let page = links.split_off(links.len().saturating_sub(Self::LINKS_PER_PAGE));
+
+        let repos = self.look_up(page.into_iter().rev()).await;
+        Ok(Some(self.apply_prefilter(repos).await?))
+    }
+
+    /// Scrape the next page of dependents, and look them up.
+    async fn next_dependents(&mut self) -> Result<Option<Vec<Repo>>> {
+        let Source::DependentsOf(dependents) = &mut self.source else {
+            return Ok(None);
+        };
+        let Some(page) = dependents.next_page().await? else {
+            return Ok(None);
+        };
+
+        let repos = self.look_up(page.into_iter()).await;
+        Ok(Some(self.apply_prefilter(repos).await?))
+    }
+
+    /// Look up repos by owner and name, following renames and transfers, and skipping
+    /// those seen before.
+    async fn look_up(&mut self, links: impl Iterator<Item = (String, String)>) -> Vec<Repo> {
+        let mut repos = Vec::new();
+        for (owner, name) in links {
+            let get = || async { self.octocrab.repos(&owner, &name).get().await };
+            let repo = match self
+                .limiter
+                .call(Resource::Core, "Looking up repo", get)
+                .await
+            {
+                Ok(repo) => repo,
+                Err(e) => {
+                    log::warn!("Skipping {owner}/{name}: {e}");
+                    continue;
+                }
+            };
+
+            let full_name = repo.full_name.clone().unwrap_or_default().to_lowercase();
+            if !self.filter.keep(&repo) || !self.seen.insert(full_name) {
+                continue;
+            }
+
+            match Repo::try_from(repo) {
+                Ok(repo) => repos.push(repo),
+                Err(e) => log::warn!("Skipping {owner}/{name}: {e}"),
+            }
+        }
+        repos
+    }
+
+    /// Search the next page with GraphQL.
+    async fn next_graphql(&mut self) -> Result<Option<Vec<Repo>>> {
+        let Source::GraphqlSearch(query) = &self.source else {
+            return Ok(None);
+        };
+        let after = match &self.cursor {
+            Cursor::Done => return Ok(None),
+            Cursor::Start => None,
+            Cursor::After(after) => Some(after.as_str()),
+            Cursor::Next(_) => unreachable!(),
+        };
+
+        let page = self
+            .limiter
+            .call(Resource::Graphql, "Searching repos", || {
+                github_graphql::search(&self.octocrab, query, self.per_page, after)
+            })
+            .await?;
+        if after.is_none() {
+            self.log_eta(Resource::Graphql, page.total);
+            self.total = Some(page.total);
+        }
+
+        self.cursor = match page.next {
+            Some(after) => Cursor::After(after),
+            None => Cursor::Done,
+        };
+
+        let mut repos = Vec::new();
+        for repo in page.repos {
+            let full_name = repo.full_name();
+            log::debug!(
+                "{full_name}: {} stars, {} KB, license {}, languages {:?}",
+                repo.stargazer_count,
+                repo.disk_usage.unwrap_or_default(),
+                repo.license().unwrap_or("none"),
+                repo.languages().collect::<Vec<_>>(),
+            );
+            if !self.filter.keep_graphql(&repo) {
+                continue;
+            }
+
+            match repo.to_repo() {
+                Ok(repo) => repos.push(repo),
+                Err(e) => log::warn!("Skipping {full_name}: {e}"),
+            }
+        }
+
+        Ok(Some(self.apply_prefilter(repos).await?))
+    }
+
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Repo>>> {
+        if self.max_pages == Some(self.pages) {
+            log::info!("Stopping after {} pages of repos (--max-pages)", self.pages);
+            return Ok(None);
+        }
+        self.pages += 1;
+
+        match self.source {
+            Source::Links(_) => return self.next_links().await,
+            Source::DependentsOf(_) => return self.next_dependents().await,
+            Source::GraphqlSearch(_) => return self.next_graphql().await,
+            _ => {}
+        }
+
+        let page: Page<Repository> = match &self.cursor {
+            Cursor::Done => return Ok(None),
+            Cursor::Start => {
+                let page = self.first_page().await?;
+                if let Source::Search(_) = self.source {
+                    self.total = page.total_count;
+                }
+                page
+            }
+            // only GraphQL searches continue after an end cursor
+            Cursor::After(_) => unreachable!(),
+            Cursor::Next(next) => {
+                let resource = match self.source {
+                    Source::Search(_) => Resource::Search,
+                    _ => Resource::Core,
+                };
+                let next = Some(next.clone());
+                let get = || self.octocrab.get_page(&next);
+                match self
+                    .limiter
+                    .call(resource, "Getting the next page", get)
+                    .await?
+                {
+                    Some(page) => page,
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        self.cursor = match page.next {
+            Some(next) => Cursor::Next(next),
+            None => Cursor::Done,
+        };
+
+        let repos = page
+            .items
+            .into_iter()
+            .filter(|repo| self.filter.keep(repo))
+            .filter_map(|repo| match Repo::try_from(repo) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    log::warn!("Skipping repo: {e}");
+                    None
+                }
+            })
+            .collect();
+
+        Ok(Some(self.apply_prefilter(repos).await?))
+    }
+}
+
+impl RepoSource for GithubRepos {
+    fn query(&self) -> Option<&str> {
+        self.query()
+    }
+
+    fn next_page(&mut self) -> PageFuture<'_> {
+        Box::pin(self.next_page())
+    }
+
+    fn complete(&self) -> bool {
+        !matches!(self.total, Some(total) if total > MAX_SEARCH_RESULTS)
+    }
+
+    fn rate_limits(&self) -> Vec<RateLimit> {
+        Resource::ALL
+            .into_iter()
+            .filter_map(|resource| {
+                let rate = self.limiter.tracked(resource)?;
+                Some(RateLimit {
+                    resource: resource.name(),
+                    remaining: rate.remaining,
+                    limit: rate.limit,
+                    reset: DateTime::from_timestamp(rate.reset as i64, 0)?,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use http::header::{ACCEPT, AUTHORIZATION, CONTENT_LENGTH, ETAG, IF_NONE_MATCH, TRANSFER_ENCODING};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+use tower::{BoxError, Layer, Service};
+
+/// A response of GitHub's API as it's cached, with the ETag to ask whether it changed.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    etag: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Entry {
+    /// The cached response, with the headers of the one that said it's unchanged, like the
+    /// rate limit's, instead of the cached ones.
+    fn into_response(self, fresh: HeaderMap) -> Response<BoxBody<Bytes, BoxError>> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        for name in fresh.keys() {
+            headers.remove(name);
+        }
+        for (name, value) in &fresh {
+            if name != CONTENT_LENGTH && name != TRANSFER_ENCODING {
+                headers.append(name, value.clone());
+            }
+        }
+
+        let mut response = Response::new(full(Bytes::from(self.body)));
+        *response.headers_mut() = headers;
+        response
+    }
+}
+
+fn full(bytes: Bytes) -> BoxBody<Bytes, BoxError> {
+    Full::new(bytes).map_err(|never| match never {}).boxed()
+}
+
+/// GitHub's API responses with an ETag, on disk, by URL. Their requests are sent again
+/// with the ETag, and if the response hasn't changed, GitHub says so with a 304 that doesn't
+/// count against the rate limit, and the cached one is used.
+#[derive(Debug, Clone)]
+pub struct ApiCache {
+    dir: PathBuf,
+}
+
+impl ApiCache {
+    pub fn new(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create the API cache {dir:?}"))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Where the response to a request is cached, if it can be: only GET requests are, by
+    /// URL, and by token, so that private repos aren't shared between tokens.
+    fn path<B>(&self, request: &Request<B>) -> Option<PathBuf> {
+        if request.method() != Method::GET {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        request.uri().to_string().hash(&mut hasher);
+        for name in [ACCEPT, AUTHORIZATION] {
+            request
+                .headers()
+                .get(name)
+                .map(|v| v.as_bytes())
+                .hash(&mut hasher);
+        }
+        Some(self.dir.join(format!("{:016x}.json", hasher.finish())))
+    }
+
+    async fn read(path: &Path) -> Option<Entry> {
+        let json = tokio::fs::read_to_string(path).await.ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    /// Cache a response, replacing the one before only once it's complete.
+    async fn write(
+        path: &Path,
+        url: String,
+        etag: &HeaderValue,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<()> {
+        let entry = Entry {
+            url,
+            etag: etag.to_str()?.to_owned(),
+            headers: headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_owned()))
+                })
+                .collect(),
+            body: std::str::from_utf8(body)?.to_owned(),
+        };
+        let partial = path.with_extension("json.partial");
+        tokio::fs::write(&partial, serde_json::to_string(&entry)?).await?;
+        tokio::fs::rename(&partial, path).await?;
+        Ok(())
+    }
+}
+
+/// Puts the [`ApiCache`], if there is one, in front of a service.
+#[derive(Debug, Clone)]
+pub struct CacheLayer(pub Option<ApiCache>);
+
+impl<S> Layer<S> for CacheLayer {
+    type Service = Cached<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Cached {
+            cache: self.0.clone(),
+            inner,
+        }
+    }
+}
+
+/// A service that answers from the [`ApiCache`] whatever hasn't changed.
+#[derive(Debug, Clone)]
+pub struct Cached<S> {
+    cache: Option<ApiCache>,
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Cached<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+    ReqBody: Send + 'static,
+    ResBody: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<BoxBody<Bytes, BoxError>>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), BoxError>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        // the one that's ready is called, and a clone of it is left for the next call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let path = self.cache.as_ref().and_then(|cache| cache.path(&request));
+
+        Box::pin(async move {
+            let url = request.uri().to_string();
+            let cached = match &path {
+                Some(path) => ApiCache::read(path).await,
+                None => None,
+            };
+            if let Some(etag) = cached
+                .as_ref()
+                .and_then(|c| HeaderValue::from_str(&c.etag).ok())
+            {
+                request.headers_mut().insert(IF_NONE_MATCH, etag);
+            }
+
+            let (parts, body) = inner.call(request).await.map_err(Into::into)?.into_parts();
+            match (path, cached) {
+                (Some(_), Some(cached)) if parts.status == StatusCode::NOT_MODIFIED => {
+                    log::trace!("{url} hasn't changed, using the cached response");
+                    Ok(cached.into_response(parts.headers))
+                }
+                (Some(path), _) if parts.status == StatusCode::OK => {
+                    let Some(etag) = parts.headers.get(ETAG) else {
+                        return Ok(Response::from_parts(
+                            parts,
+                            body.map_err(Into::into).boxed(),
+                        ));
+                    };
+                    let body = body.collect().await.map_err(Into::into)?.to_bytes();
+                    if let Err(e) = ApiCache::write(&path, url, etag, &parts.headers, &body).await {
+                        log::debug!("Failed to cache a response in {path:?}: {e}");
+                    }
+                    Ok(Response::from_parts(parts, full(body)))
+                }
+                _ => Ok(Response::from_parts(
+                    parts,
+                    body.map_err(Into::into).boxed(),
+                )),
+            }
+        })
+    }
+}
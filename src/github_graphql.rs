@@ -0,0 +1,238 @@
+use crate::forge::{parse_date, Repo, RepoMeta};
+use anyhow::{anyhow, Result};
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+
+/// Repos fetched per request; GraphQL allows up to 100, but large pages with all their
+/// languages tend to time out.
+pub const PAGE_SIZE: u32 = 50;
+
+/// The search, with everything about a repo that filters or logs may want, so that no
+/// further requests are needed per repo.
+const SEARCH_QUERY: &str = r#"
+query($query: String!, $first: Int!, $after: String) {
+  search(query: $query, type: REPOSITORY, first: $first, after: $after) {
+    repositoryCount
+    pageInfo { hasNextPage endCursor }
+    nodes {
+      ... on Repository {
+        name
+        url
+        sshUrl
+        owner { login }
+        isArchived
+        isFork
+        parent { url }
+        stargazerCount
+        diskUsage
+        pushedAt
+        repositoryTopics(first: 20) { nodes { topic { name } } }
+        licenseInfo { spdxId }
+        primaryLanguage { name }
+        languages(first: 20, orderBy: { field: SIZE, direction: DESC }) { nodes { name } }
+      }
+    }
+  }
+}
+"#;
+
+#[derive(Debug, Serialize)]
+struct Request<'a> {
+    query: &'static str,
+    variables: Variables<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct Variables<'a> {
+    query: &'a str,
+    first: u32,
+    after: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    data: Option<Data>,
+    errors: Option<Vec<Error>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Error {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Data {
+    search: Search,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Search {
+    repository_count: u64,
+    page_info: PageInfo,
+    nodes: Vec<Option<GraphqlRepo>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Name {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Login {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct License {
+    spdx_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Languages {
+    nodes: Vec<Name>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Topics {
+    nodes: Vec<Topic>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Topic {
+    topic: Name,
+}
+
+#[derive(Debug, Deserialize)]
+struct Parent {
+    url: String,
+}
+
+/// A repo as found by a GraphQL search.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphqlRepo {
+    name: String,
+    url: String,
+    ssh_url: String,
+    owner: Login,
+    pub is_archived: bool,
+    pub is_fork: bool,
+    parent: Option<Parent>,
+    pub stargazer_count: u64,
+    /// In kilobytes.
+    pub disk_usage: Option<u64>,
+    pushed_at: Option<String>,
+    repository_topics: Topics,
+    license_info: Option<License>,
+    primary_language: Option<Name>,
+    languages: Languages,
+}
+
+impl GraphqlRepo {
+    pub fn full_name(&self) -> String {
+        format!("{}/{}", self.owner.login, self.name)
+    }
+
+    pub fn license(&self) -> Option<&str> {
+        self.license_info.as_ref()?.spdx_id.as_deref()
+    }
+
+    pub fn primary_language(&self) -> Option<&str> {
+        self.primary_language
+            .as_ref()
+            .map(|language| language.name.as_str())
+    }
+
+    /// All languages of the repo, the one with the most code first.
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.languages
+            .nodes
+            .iter()
+            .map(|language| language.name.as_str())
+    }
+
+    pub fn to_repo(&self) -> Result<Repo> {
+        let mut repo = Repo::from_url(&self.url)?;
+        repo.meta = RepoMeta {
+            stars: Some(self.stargazer_count),
+            language: self.primary_language().map(ToOwned::to_owned),
+            size: self.disk_usage,
+            pushed_at: self.pushed_at.as_deref().and_then(parse_date),
+            topics: self
+                .repository_topics
+                .nodes
+                .iter()
+                .map(|topic| topic.topic.name.clone())
+                .collect(),
+            parent: self
+                .parent
+                .as_ref()
+                .and_then(|parent| Repo::from_url(&parent.url).ok())
+                .map(|parent| parent.clone_url),
+            ssh_url: Some(self.ssh_url.clone()),
+        };
+        Ok(repo)
+    }
+}
+
+/// A page of search results.
+pub struct SearchPage {
+    pub repos: Vec<GraphqlRepo>,
+    /// The total number of repos found.
+    pub total: u64,
+    /// The cursor to continue after, unless this was the last page.
+    pub next: Option<String>,
+}
+
+/// Search repos, `first` per page, continuing after `after` unless this is the first page.
+pub async fn search(
+    octocrab: &Octocrab,
+    query: &str,
+    first: u32,
+    after: Option<&str>,
+) -> Result<SearchPage> {
+    let request = Request {
+        query: SEARCH_QUERY,
+        variables: Variables {
+            query,
+            first,
+            after,
+        },
+    };
+    let response: Response = octocrab.graphql(&request).await?;
+
+    let messages: Vec<String> = response
+        .errors
+        .unwrap_or_default()
+        .into_iter()
+        .map(|e| e.message)
+        .collect();
+    let Some(data) = response.data else {
+        return Err(anyhow!("GraphQL search failed: {}", messages.join("; ")));
+    };
+    // errors next to data mean some repos couldn't be loaded, and are null
+    for message in messages {
+        log::warn!("GraphQL search: {message}");
+    }
+    let search = data.search;
+
+    let next = match search.page_info.has_next_page {
+        true => search.page_info.end_cursor,
+        false => None,
+    };
+
+    Ok(SearchPage {
+        repos: search.nodes.into_iter().flatten().collect(),
+        total: search.repository_count,
+        next,
+    })
+}
@@ -1,4 +1,4 @@
-use crate::OctoSurfer;
+use crate::RunCmd;
 use anyhow::{anyhow, Result};
 
 fn split_opt_str(s: &Option<String>) -> Option<Vec<String>> {
@@ -13,15 +13,19 @@ pub struct GithubQuery {
     pushed: Option<Vec<String>>,
     stars: Option<Vec<String>>,
     topics: Option<Vec<String>>,
+    licenses: Option<Vec<String>>,
+    org: Option<String>,
+    user: Option<String>,
 }
 
 impl GithubQuery {
-    pub fn from_argh(argh: &OctoSurfer) -> Self {
-        let keywords = argh.keywords.split(',').map(ToOwned::to_owned).collect();
+    pub fn from_argh(argh: &RunCmd) -> Self {
+        let keywords = split_opt_str(&argh.keywords).unwrap_or_default();
         let languages = split_opt_str(&argh.languages);
         let pushed = split_opt_str(&argh.pushed);
         let stars = split_opt_str(&argh.stars);
         let topics = split_opt_str(&argh.topics);
+        let licenses = split_opt_str(&argh.licenses);
 
         Self {
             keywords,
@@ -29,6 +33,9 @@ impl GithubQuery {
             pushed,
             stars,
             topics,
+            licenses,
+            org: argh.org.clone(),
+            user: argh.user.clone(),
         }
     }
 
@@ -63,6 +70,28 @@ impl GithubQuery {
             }
         }
 
+        if let Some(licenses) = &self.licenses {
+            for license in licenses {
+                s.push_str(" license:");
+                s.push_str(license);
+            }
+        }
+
+        if let Some(org) = &self.org {
+            s.push_str(" org:");
+            s.push_str(org);
+        }
+
+        if let Some(user) = &self.user {
+            s.push_str(" user:");
+            s.push_str(user);
+        }
+
+        let s = s.trim().to_owned();
+        if s.is_empty() {
+            return Err(anyhow!("Must give keywords or filters to search for!"));
+        }
+
         if s.chars().count() > 256 {
             Err(anyhow!("Query string exceeded 256 characters!"))
         } else {
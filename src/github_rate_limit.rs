@@ -0,0 +1,242 @@
+use crate::duration::HumanDuration;
+use anyhow::{anyhow, Result};
+use octocrab::models::Rate;
+use octocrab::Octocrab;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How long the rate limits are trusted to be tracked right before they're asked for anew,
+/// since other clients with the same token use them up too.
+const REFRESH_AFTER: Duration = Duration::from_secs(60);
+
+/// The rate limits of the API, keyed by resource. octocrab's own model lacks some
+/// resources, like `code_search`.
+#[derive(Debug, Deserialize)]
+struct RateLimits {
+    resources: HashMap<String, Rate>,
+}
+
+/// The API resources with a rate limit of their own, which calls use up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    /// Most REST calls, like listing or looking up repos.
+    Core,
+    Search,
+    CodeSearch,
+    Graphql,
+}
+
+impl Resource {
+    pub const ALL: [Resource; 4] = [
+        Resource::Core,
+        Resource::Search,
+        Resource::CodeSearch,
+        Resource::Graphql,
+    ];
+
+    /// The resource's name in the API, like "code_search".
+    pub fn name(self) -> &'static str {
+        match self {
+            Resource::Core => "core",
+            Resource::Search => "search",
+            Resource::CodeSearch => "code_search",
+            Resource::Graphql => "graphql",
+        }
+    }
+
+    /// How long the rate limit allows for its requests, before it's reset.
+    fn window(self) -> Duration {
+        match self {
+            Resource::Search | Resource::CodeSearch => Duration::from_secs(60),
+            Resource::Core | Resource::Graphql => Duration::from_secs(60 * 60),
+        }
+    }
+
+    /// Whether the requests are spread evenly over the window, rather than made as fast as
+    /// possible until none are left. Core calls are many, small, and plenty.
+    fn paced(self) -> bool {
+        self != Resource::Core
+    }
+}
+
+/// The time until a rate limit is reset.
+fn until_reset(rate: &Rate) -> Duration {
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    Duration::from_secs(rate.reset.saturating_sub(now))
+}
+
+/// A resource's rate limit, as far as it's tracked.
+#[derive(Debug)]
+struct Tracked {
+    rate: Rate,
+    checked: Instant,
+    /// When the last request was made, or is due.
+    last_request: Option<Instant>,
+}
+
+/// Tracks the rate limits of all resources, and waits until the next call of a resource is
+/// due: once its rate limit is reset, if none are left, or spread evenly over what's left of
+/// it, for the resources that are paced.
+pub struct RateLimiter {
+    octocrab: Octocrab,
+    tracked: Mutex<HashMap<Resource, Tracked>>,
+}
+
+impl RateLimiter {
+    pub fn new(octocrab: Octocrab) -> Self {
+        Self {
+            octocrab,
+            tracked: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Ask for the rate limits of all resources anew.
+    async fn refresh(&self) -> Result<()> {
+        let limits: RateLimits = crate::github::with_retries("Checking the rate limit", || {
+            self.octocrab.get("/rate_limit", None::<&()>)
+        })
+        .await?;
+
+        let mut tracked = self.tracked.lock().unwrap_or_else(|e| e.into_inner());
+        for resource in Resource::ALL {
+            let Some(rate) = limits.resources.get(resource.name()) else {
+                continue;
+            };
+            let name = resource.name();
+            log::trace!(
+                "Remaining {name} requests: {}/{}",
+                rate.remaining,
+                rate.limit
+            );
+            #[cfg(feature = "tui")]
+            crate::tui::record_rate_limit(name, rate.remaining, rate.limit, rate.reset);
+
+            let last_request = tracked.get(&resource).and_then(|t| t.last_request);
+            let rate = rate.clone();
+            let checked = Instant::now();
+            tracked.insert(
+                resource,
+                Tracked {
+                    rate,
+                    checked,
+                    last_request,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// The rate limit of a resource, asked for anew if it isn't tracked, or may be out of
+    /// date.
+    async fn rate(&self, resource: Resource) -> Result<Rate> {
+        let current = {
+            let tracked = self.tracked.lock().unwrap_or_else(|e| e.into_inner());
+            tracked
+                .get(&resource)
+                .filter(|t| t.checked.elapsed() < REFRESH_AFTER && !until_reset(&t.rate).is_zero())
+                .filter(|t| t.rate.remaining > 0)
+                .map(|t| t.rate.clone())
+        };
+        if let Some(rate) = current {
+            return Ok(rate);
+        }
+
+        self.refresh().await?;
+        let tracked = self.tracked.lock().unwrap_or_else(|e| e.into_inner());
+        tracked
+            .get(&resource)
+            .map(|t| t.rate.clone())
+            .ok_or_else(|| anyhow!("No rate limit for {}", resource.name()))
+    }
+
+    /// Wait until a call of `resource` is due, and count it.
+    async fn wait(&self, resource: Resource) -> Result<()> {
+        let name = resource.name();
+        let rate = self.rate(resource).await?;
+        if rate.remaining == 0 {
+            let reset = until_reset(&rate);
+            log::warn!(
+                "Rate limit for {name} exhausted! Sleeping for {}",
+                HumanDuration(Duration::from_secs(reset.as_secs() + 1))
+            );
+            // a second later, so that it's reset for sure
+            tokio::time::sleep(reset + Duration::from_secs(1)).await;
+            self.refresh().await?;
+        }
+
+        let due = {
+            let mut tracked = self.tracked.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(tracked) = tracked.get_mut(&resource) else {
+                return Ok(());
+            };
+            let now = Instant::now();
+            let due = match (resource.paced(), tracked.last_request) {
+                (true, Some(last)) => {
+                    let spacing = until_reset(&tracked.rate) / tracked.rate.remaining.max(1) as u32;
+                    (last + spacing).max(now)
+                }
+                _ => now,
+            };
+            // reserved right away, so that concurrent calls are spread out too
+            tracked.last_request = Some(due);
+            tracked.rate.remaining = tracked.rate.remaining.saturating_sub(1);
+            due
+        };
+        if due > Instant::now() {
+            log::trace!("Pacing {name} requests, waiting {:?}", due - Instant::now());
+            tokio::time::sleep_until(due).await;
+        }
+        Ok(())
+    }
+
+    /// The rate limit of a resource as it's tracked, with the calls so far counted.
+    pub fn tracked(&self, resource: Resource) -> Option<Rate> {
+        let tracked = self.tracked.lock().unwrap_or_else(|e| e.into_inner());
+        tracked.get(&resource).map(|t| t.rate.clone())
+    }
+
+    /// Make a call of `resource` once it's due, and retry it like
+    /// [`with_retries`](crate::github::with_retries), waiting again for every try.
+    pub async fn call<T, E, Fut>(
+        &self,
+        resource: Resource,
+        what: &str,
+        mut call: impl FnMut() -> Fut,
+    ) -> Result<T>
+    where
+        E: Into<anyhow::Error>,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        crate::github::with_retries(what, || {
+            let due = self.wait(resource);
+            let called = call();
+            async move {
+                due.await?;
+                called.await.map_err(Into::into)
+            }
+        })
+        .await
+    }
+}
+
+/// About how long `requests` of `resource` take when paced, rounded to minutes if it's
+/// longer than one: those left in the current window are spread over it, and the rest over
+/// the windows after.
+pub fn request_eta(resource: Resource, requests: u64, rate: &Rate) -> Duration {
+    let remaining = rate.remaining as u64;
+    let eta = match requests <= remaining {
+        true => until_reset(rate) * requests as u32 / remaining.max(1) as u32,
+        false => {
+            let per_request = resource.window() / rate.limit.max(1) as u32;
+            until_reset(rate) + per_request * (requests - remaining) as u32
+        }
+    };
+    match eta.as_secs() {
+        secs if secs >= 60 => Duration::from_secs((secs + 30) / 60 * 60),
+        secs => Duration::from_secs(secs),
+    }
+}
@@ -0,0 +1,262 @@
+use crate::code_queries::{CodeQueries, QueryResults};
+use crate::git;
+use crate::search::{self, SearchOptions};
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::str::FromStr;
+use tokio_util::sync::CancellationToken;
+
+/// Which revisions of a repo to search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryMode {
+    /// Every tag, oldest first.
+    Tags,
+    /// Every commit on the default branch, following first parents only.
+    Commits,
+    /// This many commits, evenly spread over the default branch's history.
+    Sample(usize),
+}
+
+impl FromStr for HistoryMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tags" => Ok(HistoryMode::Tags),
+            "commits" => Ok(HistoryMode::Commits),
+            _ => match s.parse() {
+                Ok(0) => Err("must sample at least one commit".to_owned()),
+                Ok(n) => Ok(HistoryMode::Sample(n)),
+                Err(_) => Err(format!("unknown history mode: {s}")),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Revision {
+    /// What to check out.
+    rev: String,
+    /// What to write into the revision column, i.e. a tag name or a commit SHA.
+    label: String,
+    /// The committer date, in ISO 8601 format.
+    date: String,
+}
+
+async fn git(path: &Path, args: &[&str]) -> Result<String> {
+    let output = git::command()
+        .arg("-C")
+        .arg(path.as_os_str())
+        .args(args)
+        .kill_on_drop(true)
+        .output()
+        .await?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(anyhow!(
+            "git {} failed in {:?}: {}",
+            args.join(" "),
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+async fn commits(path: &Path) -> Result<Vec<Revision>> {
+    let log = git(
+        path,
+        &[
+            "log",
+            "--first-parent",
+            "--reverse",
+            "--format=%H %cI",
+            "HEAD",
+        ],
+    )
+    .await?;
+
+    Ok(log
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(sha, date)| Revision {
+            rev: sha.to_owned(),
+            label: sha.to_owned(),
+            date: date.to_owned(),
+        })
+        .collect())
+}
+
+async fn tags(path: &Path) -> Result<Vec<Revision>> {
+    let refs = git(
+        path,
+        &[
+            "for-each-ref",
+            "--sort=creatordate",
+            "--format=%(refname:short) %(creatordate:iso-strict)",
+            "refs/tags",
+        ],
+    )
+    .await?;
+
+    Ok(refs
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(tag, date)| Revision {
+            rev: format!("refs/tags/{tag}"),
+            label: tag.to_owned(),
+            date: date.to_owned(),
+        })
+        .collect())
+}
+
+/// Pick `n` revisions, evenly spread and always including the first and the last.
+fn sample(revisions: Vec<Revision>, n: usize) -> Vec<Revision> {
+    let len = revisions.len();
+    if n >= len {
+        return revisions;
+    }
+    if n == 1 {
+        return revisions.into_iter().last().into_iter().collect();
+    }
+
+    let picks: Vec<usize> = (0..n).map(|i| i * (len - 1) / (n - 1)).collect();
+    revisions
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| picks.contains(idx))
+        .map(|(_, rev)| rev)
+        .collect()
+}
+
+async fn revisions(path: &Path, mode: HistoryMode) -> Result<Vec<Revision>> {
+    match mode {
+        HistoryMode::Tags => tags(path).await,
+        HistoryMode::Commits => commits(path).await,
+        HistoryMode::Sample(n) => Ok(sample(commits(path).await?, n)),
+    }
+}
+
+/// Make sure the full history and all tags are available, even if the repo was cloned
+/// shallowly by an earlier run.
+async fn unshallow(path: &Path) -> Result<()> {
+    // in a worktree, .git is a file, so git has to tell
+    let shallow = git(path, &["rev-parse", "--is-shallow-repository"]).await?;
+    if shallow.trim() == "true" {
+        log::debug!("Fetching full history of {:?}", path);
+        git(path, &["fetch", "--quiet", "--unshallow", "--tags"]).await?;
+    }
+    Ok(())
+}
+
+/// Search a repo at several points in its history.
+///
+/// Every revision is checked out in place and searched, yielding one set of results per
+/// revision, with the revision and its date as extra columns. The original branch is
+/// checked out again afterwards, however the search ends.
+pub async fn search_history(
+    path: &Path,
+    repo_owner: &str,
+    repo_name: &str,
+    queries: &CodeQueries,
+    options: &SearchOptions,
+    mode: HistoryMode,
+    cancel: &CancellationToken,
+) -> Result<Vec<QueryResults>> {
+    unshallow(path).await?;
+
+    // the branch to return to, or the commit if there is no branch checked out
+    let mut original = git(path, &["rev-parse", "--abbrev-ref", "HEAD"]).await?;
+    if original.trim() == "HEAD" {
+        original = git(path, &["rev-parse", "HEAD"]).await?;
+    }
+    let original = original.trim();
+
+    let revisions = revisions(path, mode).await?;
+    log::info!(
+        "Searching {} revisions of {}/{}",
+        revisions.len(),
+        repo_owner,
+        repo_name
+    );
+
+    let mut restore = Restore {
+        path,
+        original: original.to_owned(),
+        restored: false,
+    };
+    let mut all_results = Vec::with_capacity(revisions.len());
+    for revision in revisions {
+        git(
+            path,
+            &["checkout", "--quiet", "--force", "--detach", &revision.rev],
+        )
+        .await?;
+
+        let mut results = search::search_repo(
+            path,
+            repo_owner.to_owned(),
+            repo_name.to_owned(),
+            queries,
+            options,
+            cancel,
+        )
+        .await?;
+        results
+            .extra
+            .insert(0, ("revision".to_owned(), revision.label));
+        results.extra.insert(1, ("date".to_owned(), revision.date));
+        all_results.push(results);
+    }
+
+    restore.restore().await?;
+    Ok(all_results)
+}
+
+/// Checks out the original branch of a clone again once its history was searched, or when
+/// the search fails or is dropped, e.g. on Ctrl-C or --repo-timeout, so that the clone isn't
+/// left at an old revision for later runs.
+struct Restore<'a> {
+    path: &'a Path,
+    original: String,
+    restored: bool,
+}
+
+impl Restore<'_> {
+    async fn restore(&mut self) -> Result<()> {
+        self.restored = true;
+        git(
+            self.path,
+            &["checkout", "--quiet", "--force", &self.original],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+impl Drop for Restore<'_> {
+    fn drop(&mut self) {
+        if self.restored {
+            return;
+        }
+        // blocking, since a dropped search can't await anything anymore, and the runtime
+        // may be shutting down after Ctrl-C
+        let mut command = git::command();
+        let status = command
+            .as_std_mut()
+            .arg("-C")
+            .arg(self.path)
+            .args(["checkout", "--quiet", "--force", &self.original])
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+        if !status.is_ok_and(|status| status.success()) {
+            log::warn!(
+                "Failed to check out {} in {:?} again",
+                self.original,
+                self.path
+            );
+        }
+    }
+}
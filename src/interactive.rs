@@ -0,0 +1,130 @@
+use crate::forge::Repo;
+use crate::size::ByteSize;
+use anyhow::{anyhow, Result};
+use std::io::{IsTerminal, Write};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+const HELP: &str = "Enter numbers or ranges like 3,5-7 to deselect or reselect repos, \
+                    'all' or 'none' to select all or none, 'list' to list them again, \
+                    'q' to abort, or nothing to start cloning the selected repos.";
+
+/// Parse a selection like "1,3-5" of numbers from 1 to `len` into indices.
+fn parse_selection(s: &str, len: usize) -> Result<Vec<usize>, String> {
+    let number = |n: &str| match n.trim().parse::<usize>() {
+        Ok(n) if (1..=len).contains(&n) => Ok(n),
+        _ => Err(format!("expected a number from 1 to {len}, got {n}")),
+    };
+
+    let mut indices = Vec::new();
+    for part in s.split(',').filter(|part| !part.trim().is_empty()) {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (number(first)?, number(last)?),
+            None => (number(part)?, number(part)?),
+        };
+        if last < first {
+            return Err(format!("empty range: {part}"));
+        }
+        indices.extend(first - 1..last);
+    }
+    Ok(indices)
+}
+
+/// The total size of the selected repos, and how many of them are of unknown size.
+fn total_size(repos: &[Repo], selected: &[bool]) -> (ByteSize, usize) {
+    let mut total = 0;
+    let mut unknown = 0;
+    for (repo, _) in repos
+        .iter()
+        .zip(selected)
+        .filter(|(_, selected)| **selected)
+    {
+        match repo.meta.size {
+            Some(size) => total += size * 1024,
+            None => unknown += 1,
+        }
+    }
+    (ByteSize(total), unknown)
+}
+
+fn list(repos: &[Repo], selected: &[bool]) {
+    let width = repos.len().to_string().len();
+    for (idx, (repo, selected)) in repos.iter().zip(selected).enumerate() {
+        let mark = if *selected { 'x' } else { ' ' };
+        let size = match repo.meta.size {
+            Some(size) => ByteSize(size * 1024).to_string(),
+            None => "?".to_owned(),
+        };
+        eprintln!(
+            "{:>width$} [{mark}] {}/{} ({size})",
+            idx + 1,
+            repo.owner,
+            repo.name
+        );
+    }
+}
+
+fn summary(repos: &[Repo], selected: &[bool]) {
+    let count = selected.iter().filter(|selected| **selected).count();
+    let (size, unknown) = total_size(repos, selected);
+    eprint!("{count} of {} repos selected, {size} in total", repos.len());
+    match unknown {
+        0 => eprintln!(),
+        n => eprintln!(", not counting {n} repos of unknown size"),
+    }
+}
+
+/// Show the repos found, and let the user deselect some of them, or abort, before any are
+/// cloned.
+pub async fn confirm(repos: Vec<Repo>) -> Result<Vec<Repo>> {
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!("--interactive needs a terminal to read from"));
+    }
+    if repos.is_empty() {
+        return Ok(repos);
+    }
+
+    let mut selected = vec![true; repos.len()];
+    list(&repos, &selected);
+    summary(&repos, &selected);
+    eprintln!("{HELP}");
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        eprint!("> ");
+        std::io::stderr().flush()?;
+
+        let Some(line) = lines.next_line().await? else {
+            return Err(anyhow!("Aborted, since the input ended"));
+        };
+        match line.trim() {
+            "" => break,
+            "q" | "quit" => return Err(anyhow!("Aborted before cloning any repos")),
+            "all" => selected.fill(true),
+            "none" => selected.fill(false),
+            "list" => list(&repos, &selected),
+            "?" | "help" => {
+                eprintln!("{HELP}");
+                continue;
+            }
+            line => match parse_selection(line, repos.len()) {
+                Ok(indices) => {
+                    for idx in indices {
+                        selected[idx] = !selected[idx];
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    continue;
+                }
+            },
+        }
+        summary(&repos, &selected);
+    }
+
+    Ok(repos
+        .into_iter()
+        .zip(selected)
+        .filter(|(_, selected)| *selected)
+        .map(|(repo, _)| repo)
+        .collect())
+}
@@ -0,0 +1,65 @@
+use std::path::Path;
+
+/// Common languages and their file extensions.
+pub const LANGUAGES: &[(&str, &[&str])] = &[
+    ("c", &["c", "h"]),
+    ("c#", &["cs"]),
+    (
+        "c++",
+        &["cc", "cpp", "cxx", "c++", "hh", "hpp", "hxx", "h++"],
+    ),
+    ("css", &["css", "scss", "sass", "less"]),
+    ("fortran", &["f", "for", "f77", "f90", "f95", "f03", "f08"]),
+    ("go", &["go"]),
+    ("haskell", &["hs", "lhs"]),
+    ("html", &["html", "htm"]),
+    ("java", &["java"]),
+    ("javascript", &["js", "mjs", "cjs", "jsx"]),
+    ("julia", &["jl"]),
+    ("kotlin", &["kt", "kts"]),
+    ("lua", &["lua"]),
+    ("markdown", &["md", "markdown"]),
+    ("objective-c", &["m", "mm"]),
+    ("perl", &["pl", "pm"]),
+    ("php", &["php"]),
+    ("python", &["py", "pyi"]),
+    ("r", &["r"]),
+    ("ruby", &["rb"]),
+    ("rust", &["rs"]),
+    ("scala", &["scala", "sc"]),
+    ("shell", &["sh", "bash", "zsh"]),
+    ("swift", &["swift"]),
+    ("typescript", &["ts", "tsx", "mts", "cts"]),
+    ("zig", &["zig"]),
+];
+
+/// The extensions of a language, matched case-insensitively by name.
+pub fn extensions(language: &str) -> Option<&'static [&'static str]> {
+    let language = language.to_lowercase();
+    let language = match language.as_str() {
+        "cpp" => "c++",
+        "csharp" => "c#",
+        other => other,
+    };
+
+    LANGUAGES
+        .iter()
+        .find(|(name, _)| *name == language)
+        .map(|(_, exts)| *exts)
+}
+
+/// The lowercase extension of a path, if it has one.
+pub fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+}
+
+/// The language of a file, by its extension.
+pub fn language(path: &Path) -> Option<&'static str> {
+    let ext = extension(path)?;
+    LANGUAGES
+        .iter()
+        .find(|(_, exts)| exts.contains(&ext.as_str()))
+        .map(|(name, _)| *name)
+}
@@ -0,0 +1,1594 @@
+//! Find repos on GitHub and other forges, clone them, and count the matches of code queries
+//! in them.
+//!
+//! The `octosurfer` binary is a thin wrapper around this library. A run is set up from a
+//! [`RunCmd`], parsed from the same arguments as the run subcommand with
+//! [`argh::FromArgs::from_args`], and then [`run`], or driven step by step with a
+//! [`Runner`], which can take its repos from a [`RepoSource`] of one's own, like a forge
+//! that octosurfer doesn't know, and write the results into an [`OutputSink`] of one's own.
+//! Its [`events`](Runner::events) tell what happens to each repo, to show the progress.
+//! Its parts can be used on their own, too: [`CodeQueries`] reads the queries,
+//! [`search_repo`](search::search_repo) searches a repo on disk with them, and an
+//! [`Aggregator`](aggregate::Aggregator) writes the results of many repos.
+//!
+//! Errors are an [`Error`], which tells apart what failed, e.g. a rate limit
+//! that was exceeded from a query that couldn't be parsed.
+
+pub mod aggregate;
+mod alternates;
+mod analyzers;
+mod bandwidth;
+mod bare;
+mod bitbucket;
+mod cache;
+pub mod check;
+mod ci;
+pub mod clean;
+pub mod code_queries;
+mod credentials;
+mod dependents;
+mod disk;
+mod duration;
+pub mod error;
+mod estimate;
+mod examples;
+mod failures;
+mod filter;
+pub mod forge;
+mod git;
+mod gitea;
+mod github;
+mod github_cache;
+mod github_graphql;
+mod github_query;
+mod github_rate_limit;
+mod history;
+mod interactive;
+mod languages;
+mod license;
+mod lockfile;
+pub mod logging;
+pub mod login;
+mod manifests;
+mod markdown;
+mod metadata;
+#[macro_use]
+mod options;
+mod pipeline;
+mod plugins;
+mod presets;
+pub mod progress;
+pub mod proxy;
+mod registry;
+pub mod report;
+mod sample;
+mod sarif;
+pub mod search;
+mod semgrep;
+mod size;
+mod structural;
+mod submodules;
+mod swh;
+pub mod token;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+use crate::aggregate::{CsvSink, OutputSink};
+use crate::alternates::{ObjectStore, ObjectStores};
+use crate::analyzers::Analyzers;
+use crate::cache::{Cache, Lookup};
+use crate::code_queries::{CodeQueries, Engine, QueryResults};
+use crate::disk::DiskBudget;
+use crate::duration::HumanDuration;
+use crate::error::Error;
+use crate::estimate::Estimate;
+use crate::failures::RepoFailure;
+use crate::filter::{RepoFilter, TOO_LARGE};
+use crate::forge::{CloneProtocol, ForgeKind, Repo, RepoSource};
+use crate::history::HistoryMode;
+use crate::pipeline::{CloneQueue, QueuedJob, RepoJob};
+use crate::presets::Preset;
+use crate::progress::{Event, Phase, Progress, RepoProgress};
+use crate::registry::{RegistryKind, USER_AGENT};
+use crate::sample::Strata;
+use crate::search::{
+    EncodingMode, LanguageBreakdown, Scope, SearchOptions, StringMode, SymlinkMode,
+};
+use crate::size::ByteSize;
+use crate::submodules::SubmoduleMode;
+use crate::swh::SwhClient;
+use anyhow::{anyhow, Result};
+use argh::FromArgs;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, BufReader};
+use tokio::sync::{mpsc, OwnedSemaphorePermit};
+use tokio_util::sync::CancellationToken;
+use url::Url;
+
+/// How long to wait for the repos in progress after Ctrl-C, unless given.
+const DEFAULT_GRACE_PERIOD: HumanDuration = HumanDuration(Duration::from_secs(30));
+
+/// How often a failed clone is retried, unless given.
+const DEFAULT_CLONE_RETRIES: u32 = 2;
+
+/// How long to wait before retrying a failed clone the first time, unless given.
+const DEFAULT_CLONE_BACKOFF: HumanDuration = HumanDuration(Duration::from_secs(10));
+
+/// Parts of git's errors that retrying a clone won't fix.
+const PERMANENT_CLONE_ERRORS: [&str; 4] = [
+    "not found",
+    "Authentication failed",
+    "could not read Username",
+    "took longer than",
+];
+
+command! {
+    /// Search GitHub, clone matching repos, and search through the repos
+    #[derive(FromArgs)]
+    #[argh(subcommand, name = "run")]
+    pub struct RunCmd uses [find_options, size_options, analyze_options] {
+        /// file to read code queries from; optional with --preset, --metadata-only, or
+        /// --estimate
+        #[argh(option, short = 'q')]
+        query_file: Option<PathBuf>,
+
+        /// don't clone or search any repos, but write the stars, language, last push, size, and
+        /// topics of the repos found to the out file
+        #[argh(switch)]
+        metadata_only: bool,
+
+        /// search the repos already cloned into the target directory, as <owner>/<name>, without
+        /// searching for, cloning, or updating any repos
+        #[argh(switch)]
+        local_only: bool,
+
+        /// run again and again, this long after the start of the previous run, e.g. "24h";
+        /// every run writes its results into a new file, named after the out file and the time
+        /// it started, and updates the repos cloned before unless --rm is given
+        #[argh(option)]
+        every: Option<HumanDuration>,
+    }
+}
+
+command! {
+    /// Search for repos, and write them with their metadata into a CSV file, which analyze can
+    /// read as a repo list
+    #[derive(FromArgs)]
+    #[argh(subcommand, name = "search")]
+    pub struct SearchCmd uses [find_options, size_options] {
+        /// file to read code queries from, needed for --prefilter
+        #[argh(option, short = 'q')]
+        query_file: Option<PathBuf>,
+
+        /// filename to write the repos found into, with their metadata, as CSV
+        #[argh(option, short = 'o')]
+        out_file: PathBuf,
+    }
+}
+
+command! {
+    /// Clone the repos in a repo list, or take those cloned before, and search through them
+    #[derive(FromArgs)]
+    #[argh(subcommand, name = "analyze")]
+    pub struct AnalyzeCmd uses [analyze_options, size_options] {
+        /// file to read code queries from; optional with --preset
+        #[argh(option, short = 'q')]
+        query_file: Option<PathBuf>,
+
+        /// file listing the repos to clone and analyze, like the CSV file search writes; without
+        /// it, the repos already cloned into the target directory are analyzed
+        #[argh(option)]
+        repo_list: Option<PathBuf>,
+    }
+}
+
+impl RunCmd {
+    /// Whether the run prints the repos or asks about them on the terminal, instead of only
+    /// logging its progress.
+    pub fn uses_terminal(&self) -> bool {
+        self.interactive || self.dry_run
+    }
+}
+
+impl RunCmd {
+    /// The options of run, as argh defaults them, with a target directory and an output file
+    /// to be filled in.
+    fn defaults() -> Self {
+        RunCmd::from_args(&["run"], &["--target-dir", "", "--out-file", ""])
+            .unwrap_or_else(|_| unreachable!("run's defaults are valid"))
+    }
+}
+
+impl From<SearchCmd> for RunCmd {
+    fn from(cmd: SearchCmd) -> Self {
+        let mut run = RunCmd::defaults();
+        find_options!(copy_options!(run, cmd));
+        size_options!(copy_options!(run, cmd));
+        run.query_file = cmd.query_file;
+        run.out_file = cmd.out_file;
+        run.metadata_only = true;
+        run
+    }
+}
+
+impl From<AnalyzeCmd> for RunCmd {
+    fn from(cmd: AnalyzeCmd) -> Self {
+        let mut run = RunCmd::defaults();
+        analyze_options!(copy_options!(run, cmd));
+        size_options!(copy_options!(run, cmd));
+        // without a list of repos, the repos cloned before are analyzed
+        run.local_only = cmd.repo_list.is_none() && run.retry_from.is_none();
+        run.query_file = cmd.query_file;
+        run.repo_list = cmd.repo_list;
+        run
+    }
+}
+
+/// The arguments to fetch `depth` commits, or all of them into a `shallow` clone.
+fn depth_args(depth: Option<u32>, shallow: bool) -> Vec<String> {
+    match depth {
+        Some(depth) => vec!["--depth".to_owned(), depth.to_string()],
+        None if shallow => vec!["--unshallow".to_owned()],
+        None => Vec::new(),
+    }
+}
+
+/// The commit checked out in a clone, or nothing for directories that aren't clones, like
+/// Software Heritage snapshots.
+async fn head_commit(path: &Path) -> Option<String> {
+    // git would look for a repo in the directories above, too
+    if !tokio::fs::try_exists(path.join(".git")).await.ok()? {
+        return None;
+    }
+    let output = git::command()
+        .arg("-C")
+        .arg(path.as_os_str())
+        .arg("rev-parse")
+        .arg("HEAD")
+        .kill_on_drop(true)
+        .output()
+        .await
+        .ok()?;
+    let commit = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    (output.status.success() && !commit.is_empty()).then_some(commit)
+}
+
+async fn update_repo(
+    path: &Path,
+    git_ref: Option<&str>,
+    depth: Option<u32>,
+    keep_local_changes: bool,
+    timeout: Option<HumanDuration>,
+) -> Result<()> {
+    let shallow = tokio::fs::try_exists(path.join(".git").join("shallow")).await?;
+    let depth = depth_args(depth, shallow);
+    let mut fetch = vec!["fetch", "--quiet"];
+    fetch.extend(depth.iter().map(String::as_str));
+    fetch.extend(["origin", git_ref.unwrap_or("HEAD")]);
+
+    // fetched and checked out rather than pulled, since a tag can't be pulled, and a clone of
+    // a tag, or of another ref than the one wanted now, isn't on the right branch. Checking
+    // out by force resets it like a hard reset would, whatever was force-pushed or changed
+    // locally, while without force, git keeps the local changes it doesn't have to overwrite.
+    let mut checkout = vec!["checkout", "--quiet", "--detach", "FETCH_HEAD"];
+    if !keep_local_changes {
+        checkout.insert(1, "--force");
+    }
+    let commands = [fetch, checkout];
+    let updated = async {
+        for args in commands {
+            let output = git::command()
+                .arg("-C")
+                .arg(path.as_os_str())
+                .args(args)
+                .kill_on_drop(true)
+                .output()
+                .await?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "Failed to update {:?}: {}",
+                    path,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        }
+        Ok(())
+    };
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout.duration(), updated)
+            .await
+            .map_err(|_| anyhow!("Updating {:?} took longer than {timeout}", path))??,
+        None => updated.await?,
+    }
+
+    log::debug!("Successfully updated {:?}", path);
+    Ok(())
+}
+
+async fn clone_repo(
+    clone_path: &Path,
+    owner: &str,
+    name: &str,
+    clone_url: &str,
+    clone_options: &CloneOptions,
+    progress: &RepoProgress,
+) -> Result<()> {
+    tokio::fs::create_dir_all(&clone_path).await?;
+
+    let stall_timeout = clone_options.stall_timeout;
+    let mut command = git::command();
+    command.arg("clone");
+    if clone_options.bare {
+        command.arg("--bare");
+    }
+    if let Some(git_ref) = &clone_options.git_ref {
+        command.arg("--branch").arg(git_ref);
+    }
+    if let Some(store) = clone_options
+        .object_store
+        .as_ref()
+        .and_then(ObjectStore::path)
+    {
+        command.arg("--reference").arg(store.as_os_str());
+    }
+    // git's progress tells whether a clone is stalled
+    match progress.show() || stall_timeout.is_some() {
+        true => command.arg("--progress"),
+        false => command.arg("--quiet"),
+    };
+    if let Some(depth) = clone_options.depth {
+        command.arg("--depth").arg(depth.to_string());
+    }
+
+    let mut child = command
+        .arg(clone_url)
+        .arg(clone_path.as_os_str())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    // git redraws its progress with carriage returns
+    let mut fatal = None;
+    if let Some(stderr) = child.stderr.take() {
+        let mut stderr = BufReader::new(stderr);
+        let mut line = Vec::new();
+        loop {
+            let read = stderr.read_u8();
+            let read = match stall_timeout {
+                Some(stall_timeout) => tokio::time::timeout(stall_timeout.duration(), read)
+                    .await
+                    .map_err(|_| {
+                    anyhow!("Cloning {owner}/{name} stalled, without progress for {stall_timeout}")
+                })?,
+                None => read.await,
+            };
+            let byte = match read {
+                Ok(byte) => byte,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            };
+            if byte == b'\r' || byte == b'\n' {
+                let line_str = String::from_utf8_lossy(&line);
+                if line_str.starts_with("fatal: ") {
+                    fatal = Some(line_str.into_owned());
+                } else {
+                    progress.git_progress(&line_str);
+                }
+                line.clear();
+            } else {
+                line.push(byte);
+            }
+        }
+    }
+    let status = child.wait().await?;
+
+    if status.success() {
+        log::debug!("Successfully cloned {}/{}", owner, name);
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Failed to clone repo {}/{}! Exit code: {}{}",
+            owner,
+            name,
+            status,
+            fatal.map(|fatal| format!(" ({fatal})")).unwrap_or_default()
+        ))
+    }
+}
+
+/// Up to a quarter of `wait`, to add to it so that repos that failed together don't retry
+/// together.
+fn jitter(wait: Duration) -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.subsec_nanos())
+        .unwrap_or_default();
+    wait.mul_f64(f64::from(nanos % 1000) / 4000.0)
+}
+
+/// Like [`clone_repo`], but retry with exponential backoff and jitter after errors that
+/// may be transient, like network errors.
+async fn clone_repo_with_retries(
+    clone_path: &Path,
+    repo: &Repo,
+    clone_options: &CloneOptions,
+    progress: &RepoProgress,
+) -> Result<()> {
+    let Repo { owner, name, .. } = repo;
+    let full_name = format!("{owner}/{name}");
+    let clone_url = repo.remote_url(clone_options.protocol);
+    let mut backoff = clone_options.retry_backoff.duration();
+    let mut retries = 0;
+    loop {
+        let cloned = clone_repo(clone_path, owner, name, &clone_url, clone_options, progress);
+        let cloned = match clone_options.git_timeout {
+            Some(timeout) => tokio::time::timeout(timeout.duration(), cloned)
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("Cloning {full_name} took longer than {timeout}"))),
+            None => cloned.await,
+        };
+        let Err(e) = cloned else {
+            return Ok(());
+        };
+
+        // git may leave a partial clone behind, which would be mistaken for a clone later
+        if let Err(e) = tokio::fs::remove_dir_all(clone_path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to remove {:?}: {e}", clone_path);
+            }
+        }
+
+        let message = e.to_string();
+        let permanent = PERMANENT_CLONE_ERRORS
+            .iter()
+            .any(|permanent| message.contains(permanent));
+        if permanent || retries == clone_options.retries {
+            return Err(e);
+        }
+
+        retries += 1;
+        let wait = backoff + jitter(backoff);
+        log::warn!(
+            repo = full_name, phase = Phase::Clone.name();
+            "{e}, retrying in {:.1}s ({retries}/{})",
+            wait.as_secs_f64(),
+            clone_options.retries
+        );
+        tokio::time::sleep(wait).await;
+        backoff *= 2;
+    }
+}
+
+/// How to get repos onto disk, and what to do with them after searching them.
+#[derive(Debug, Clone)]
+struct CloneOptions {
+    /// Repos are cloned to `<target_dir>/<owner>/<name>`.
+    target_dir: PathBuf,
+    /// Remove repos after searching them.
+    remove: bool,
+    /// `target_dir` is a temporary directory of the repo's own, to be removed as a whole.
+    temporary: bool,
+    /// Search repos that are already in the target directory, without cloning or updating
+    /// them.
+    local_only: bool,
+    /// Give up on a repo after this long.
+    timeout: Option<HumanDuration>,
+    /// How often to retry a failed clone.
+    retries: u32,
+    /// The wait before retrying a failed clone the first time.
+    retry_backoff: HumanDuration,
+    /// Give up on a git clone or update after this long.
+    git_timeout: Option<HumanDuration>,
+    /// Give up on a git clone after this long without progress.
+    stall_timeout: Option<HumanDuration>,
+    /// The disk space clones may take, shared by all repos.
+    disk_budget: Option<DiskBudget>,
+    /// Record the disk space the clone takes.
+    disk_usage: bool,
+    /// The results of earlier runs, shared by all repos.
+    cache: Option<Cache>,
+    /// Keep bare clones, and check them out only for searching.
+    bare: bool,
+    /// The object store the repo shares with the other repos of its family.
+    object_store: Option<ObjectStore>,
+    /// The branch or tag to clone instead of the default branch.
+    git_ref: Option<String>,
+    /// The number of commits to clone, or all of them.
+    depth: Option<u32>,
+    submodules: SubmoduleMode,
+    protocol: CloneProtocol,
+    /// Update clones without discarding their local changes.
+    keep_local_changes: bool,
+    /// Where the repo waits its turn to be cloned.
+    clone_queue: Option<CloneQueue>,
+}
+
+impl CloneOptions {
+    fn from_argh(argh: &RunCmd) -> Self {
+        Self {
+            target_dir: argh.target_dir.clone(),
+            remove: argh.rm,
+            temporary: false,
+            local_only: argh.local_only,
+            timeout: argh.repo_timeout,
+            retries: argh.clone_retries,
+            retry_backoff: argh.clone_backoff,
+            git_timeout: argh.git_timeout,
+            stall_timeout: argh.stall_timeout,
+            disk_budget: None,
+            disk_usage: argh.disk_usage,
+            cache: None,
+            bare: argh.bare,
+            object_store: None,
+            git_ref: argh.git_ref.clone(),
+            depth: match argh.full_history || argh.history.is_some() {
+                true => None,
+                false => Some(argh.clone_depth.unwrap_or(1)),
+            },
+            submodules: argh.submodules,
+            protocol: argh.clone_protocol,
+            keep_local_changes: argh.keep_local_changes,
+            clone_queue: None,
+        }
+    }
+
+    /// Where a repo is searched.
+    fn path(&self, repo: &Repo) -> PathBuf {
+        self.target_dir.join(&repo.owner).join(&repo.name)
+    }
+
+    /// Where a repo's bare clone is kept.
+    fn git_dir(&self, repo: &Repo) -> PathBuf {
+        self.target_dir
+            .join(&repo.owner)
+            .join(format!("{}.git", repo.name))
+    }
+
+    /// Everything on disk for a repo, to be removed after searching it.
+    fn paths(&self, repo: &Repo) -> Vec<PathBuf> {
+        if self.temporary {
+            return vec![self.target_dir.clone()];
+        }
+        let mut paths = vec![self.path(repo)];
+        if self.bare {
+            paths.push(self.git_dir(repo));
+        }
+        paths
+    }
+}
+
+async fn handle_repo(
+    repo: Repo,
+    queries: CodeQueries,
+    options: SearchOptions,
+    clone_options: CloneOptions,
+    progress: &RepoProgress,
+    turn: Option<OwnedSemaphorePermit>,
+    cancel: &CancellationToken,
+) -> Result<Vec<QueryResults>> {
+    let clone_path = clone_options.path(&repo);
+    // a bare clone is checked out into `clone_path` only for searching
+    let git_dir = clone_options.bare.then(|| clone_options.git_dir(&repo));
+    let clone_dir = git_dir.as_deref().unwrap_or(&clone_path);
+    let git_ref = clone_options.git_ref.as_deref();
+    let depth = clone_options.depth;
+    let Repo {
+        owner,
+        name,
+        clone_url,
+        swh_directory,
+        ..
+    } = &repo;
+
+    let full_name = format!("{owner}/{name}");
+    // released once the clone is removed
+    let mut reservation = None;
+    // repos listed with a file URL were found with --local-only, and are searched in place
+    let local = clone_options.local_only || clone_url.scheme() == "file";
+
+    // local repos may have changed without a new version, so they are always searched
+    let mut version = None;
+    if let Some(cache) = clone_options.cache.as_ref().filter(|_| !local) {
+        match cache.lookup(&repo, clone_options.protocol).await {
+            Lookup::Cached(results) => {
+                log::info!(
+                    repo = full_name;
+                    "Reusing the results of {full_name}, unchanged since the last run"
+                );
+                return Ok(results);
+            }
+            Lookup::Changed(changed) => version = changed,
+        }
+    }
+
+    if local {
+        // nothing to fetch
+    } else if let Some(directory) = swh_directory {
+        // archived snapshots don't change, so there's nothing to update
+        if !tokio::fs::try_exists(&clone_path).await? {
+            log::info!(repo = full_name, phase = Phase::Download.name(); "Downloading {full_name}");
+            progress.set_phase(Phase::Download);
+            SwhClient::new()?.download(directory, &clone_path).await?;
+        }
+    } else if tokio::fs::try_exists(clone_dir).await? {
+        log::info!(repo = full_name, phase = Phase::Update.name(); "Updating {full_name}");
+        progress.set_phase(Phase::Update);
+        match &git_dir {
+            Some(git_dir) => {
+                bare::fetch(git_dir, git_ref, depth, clone_options.git_timeout).await?
+            }
+            None => {
+                let keep = clone_options.keep_local_changes;
+                update_repo(&clone_path, git_ref, depth, keep, clone_options.git_timeout).await?
+            }
+        }
+    } else {
+        if let Some(budget) = &clone_options.disk_budget {
+            let size = repo.meta.size.unwrap_or_default() * 1024 * estimate::DISK_FACTOR;
+            let reserved = budget
+                .reserve(&clone_options.target_dir, &full_name, size)
+                .await?;
+            reservation = Some(reserved);
+        }
+
+        log::info!(repo = full_name, phase = Phase::Clone.name(); "Cloning {full_name}");
+        progress.set_phase(Phase::Clone);
+        if let Some(store) = &clone_options.object_store {
+            store
+                .prepare(clone_options.protocol, clone_options.git_timeout)
+                .await;
+        }
+        clone_repo_with_retries(clone_dir, &repo, &clone_options, progress).await?;
+
+        if let Some(reserved) = &mut reservation {
+            reserved.resize(disk::dir_size(clone_dir.to_path_buf()).await?);
+        }
+        if let Some(reserved) = reservation.take().filter(|_| !clone_options.remove) {
+            reserved.keep();
+        }
+    }
+    // local repos were checked out by someone else, at some point
+    let checked_out_at =
+        (!local).then(|| chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+    log::info!(repo = full_name, phase = Phase::Search.name(); "Searching {full_name}");
+    progress.set_phase(Phase::Search);
+    if let Some(git_dir) = &git_dir {
+        bare::add_worktree(git_dir, &clone_path).await?;
+    }
+    if !local && swh_directory.is_none() {
+        let timeout = clone_options.git_timeout;
+        if let Err(e) = submodules::update(&clone_path, clone_options.submodules, timeout).await {
+            log::warn!(repo = full_name; "{e}, so they aren't searched");
+        }
+    }
+    // the next repo may be cloned while this one is searched
+    drop(turn);
+    // measured with its submodules, but not a bare clone's worktree, which isn't kept
+    let disk_usage = match clone_options.disk_usage {
+        true => Some(disk::dir_size(clone_dir.to_path_buf()).await?),
+        false => None,
+    };
+    // revisions searched in history mode are recorded with each revision instead
+    let commit = match options.history {
+        None => head_commit(&clone_path).await,
+        Some(_) => None,
+    };
+
+    // try to avoid EMFILE (too many open files)
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let results = match options.history {
+        Some(mode) => {
+            history::search_history(&clone_path, owner, name, &queries, &options, mode, cancel)
+                .await
+        }
+        None => search::search_repo(
+            &clone_path,
+            owner.to_owned(),
+            name.to_owned(),
+            &queries,
+            &options,
+            cancel,
+        )
+        .await
+        .map(|results| vec![results])
+        .map_err(anyhow::Error::from),
+    };
+    // the analyses of plugins, semgrep, and the like see the checkout that was searched
+    let mut analysis_columns = Vec::new();
+    if results.is_ok() {
+        analysis_columns = plugins::run(&options.plugins, &clone_path, &full_name).await;
+        if let Some(rules) = &options.semgrep_rules {
+            match semgrep::run(rules, &clone_path).await {
+                Ok(columns) => analysis_columns.extend(columns),
+                Err(e) => log::warn!(repo = full_name; "{e:#}, so its findings are left empty"),
+            }
+        }
+        analysis_columns.extend(analyzers::run(&options.analyzers, &clone_path, &full_name).await);
+        if options.detect_license {
+            let license = license::detect(&clone_path, &full_name).await;
+            analysis_columns.push(("detected_license".to_owned(), license));
+        }
+        if !options.dependencies.is_empty() {
+            let dependencies = &options.dependencies;
+            let columns = manifests::run(dependencies, &clone_path, &options, &full_name).await;
+            analysis_columns.extend(columns);
+        }
+        if options.ci {
+            analysis_columns.extend(ci::run(&clone_path, &full_name).await);
+        }
+    }
+    // removed whether or not the search succeeded, since it's checked out anew next time
+    if let Some(git_dir) = &git_dir {
+        bare::remove_worktree(git_dir, &clone_path).await?;
+    }
+    let mut results = results?;
+    for results in results.iter_mut() {
+        results.extra.extend(analysis_columns.iter().cloned());
+        if let Some(disk_usage) = disk_usage {
+            let column = ("disk_usage".to_owned(), disk_usage.to_string());
+            results.extra.insert(0, column);
+        }
+        if let Some(checked_out_at) = &checked_out_at {
+            let column = ("checked_out_at".to_owned(), checked_out_at.clone());
+            results.extra.insert(0, column);
+        }
+        if let Some(commit) = &commit {
+            results
+                .extra
+                .insert(0, ("commit".to_owned(), commit.clone()));
+        }
+    }
+
+    if clone_options.remove {
+        let path = match clone_options.temporary {
+            true => &clone_options.target_dir,
+            false => clone_dir,
+        };
+        log::debug!("Removing {:?}", path);
+        tokio::fs::remove_dir_all(path).await?;
+    }
+
+    if let (Some(cache), Some(version)) = (&clone_options.cache, version) {
+        cache.store(&repo, version, &results);
+    }
+
+    Ok(results)
+}
+
+/// Like [`handle_repo`], but give up after the timeout, if there is one, or once `cancel` is
+/// cancelled, with [`Error::Interrupted`].
+async fn handle_repo_with_timeout(
+    repo: Repo,
+    queries: CodeQueries,
+    options: SearchOptions,
+    clone_options: CloneOptions,
+    progress: Progress,
+    cancel: CancellationToken,
+) -> Result<Vec<QueryResults>> {
+    // waiting for its turn doesn't count towards a repo's timeout
+    let local = clone_options.local_only || repo.clone_url.scheme() == "file";
+    let turn = match &clone_options.clone_queue {
+        Some(queue) if !local => tokio::select! {
+            turn = queue.enter() => Some(turn?),
+            _ = cancel.cancelled() => return Err(Error::Interrupted.into()),
+        },
+        _ => None,
+    };
+    let progress = progress.repo(&repo.owner, &repo.name);
+    let full_name = format!("{}/{}", repo.owner, repo.name);
+    let failed_repo = repo.clone();
+    let started = Instant::now();
+    let mut timed_out = false;
+    let temp_dir = clone_options
+        .temporary
+        .then(|| clone_options.target_dir.clone());
+
+    let paths = clone_options.paths(&repo);
+    let remove = clone_options.remove;
+    let timeout = clone_options.timeout;
+    // git is killed when the clone is dropped, while searches notice the cancellation
+    // themselves
+    let handled = handle_repo(
+        repo,
+        queries,
+        options,
+        clone_options,
+        &progress,
+        turn,
+        &cancel,
+    );
+    let handled = async {
+        tokio::select! {
+            results = handled => results,
+            _ = cancel.cancelled() => Err(Error::Interrupted.into()),
+        }
+    };
+    let results = match timeout {
+        None => handled.await,
+        Some(timeout) => match tokio::time::timeout(timeout.duration(), handled).await {
+            Ok(results) => results,
+            Err(_) => {
+                timed_out = true;
+                for path in paths.iter().filter(|_| remove) {
+                    log::debug!("Removing {:?}", path);
+                    if let Err(e) = tokio::fs::remove_dir_all(path).await {
+                        if e.kind() != std::io::ErrorKind::NotFound {
+                            log::warn!("Failed to remove {:?}: {e}", path);
+                        }
+                    }
+                }
+                Err(anyhow!("{full_name} timed out after {timeout}"))
+            }
+        },
+    };
+
+    // a failed repo's temporary directory is of no use to later runs either
+    if let Some(temp_dir) = temp_dir.filter(|_| results.is_err()) {
+        if let Err(e) = tokio::fs::remove_dir_all(&temp_dir).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to remove {:?}: {e}", temp_dir);
+            }
+        }
+    }
+    // a cancelled repo didn't fail, and its results are left out
+    if results.is_err() && cancel.is_cancelled() {
+        log::info!(repo = full_name, phase = "cancelled"; "Cancelled {full_name}");
+        return Err(Error::Interrupted.into());
+    }
+
+    let duration = started.elapsed().as_secs_f64();
+    let results = match results {
+        Ok(results) => {
+            log::info!(
+                repo = full_name, phase = "done", duration;
+                "Finished {full_name} in {duration:.1}s"
+            );
+            Ok(results)
+        }
+        Err(error) => {
+            let phase = progress.phase();
+            log::error!(
+                repo = full_name, phase = phase.map(Phase::name), duration, error:% = error;
+                "Failed: {error}"
+            );
+            Err(RepoFailure {
+                repo: failed_repo,
+                phase,
+                timed_out,
+                error,
+            }
+            .into())
+        }
+    };
+    progress.finish(&results);
+    results
+}
+
+/// Stop starting repos on Ctrl-C, and cancel the repos in progress after the grace period,
+/// or right away on another Ctrl-C.
+async fn watch_ctrl_c(
+    stop: CancellationToken,
+    cancel: CancellationToken,
+    grace_period: HumanDuration,
+) {
+    if tokio::signal::ctrl_c().await.is_err() {
+        return;
+    }
+    log::warn!(
+        "Interrupted! Waiting up to {grace_period} for the repos in progress, press Ctrl-C again to cancel them"
+    );
+    stop.cancel();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = tokio::time::sleep(grace_period.duration()) => {}
+    }
+    log::warn!("Cancelling the repos in progress");
+    cancel.cancel();
+}
+
+/// Finds repos on a forge, or in a list or the target directory, and clones and searches
+/// them, all with the options of a [`RunCmd`].
+pub struct Runner {
+    cli_app: RunCmd,
+    forge: Box<dyn RepoSource>,
+    code_queries: CodeQueries,
+    search_options: SearchOptions,
+    rm_paths: HashSet<PathBuf>,
+    /// The lowercase full names of the repos of the run, whose clones --prune keeps.
+    corpus: HashSet<String>,
+    /// The number of temporary directories repos were given so far, with --temp-dir.
+    temp_dirs: usize,
+    /// The keys of all repos so far, since several pages or sources may find the same repo.
+    seen: HashSet<String>,
+    /// Used to follow redirects from repos' URLs, if requested.
+    redirect_client: Option<reqwest::Client>,
+    filter: RepoFilter,
+    disk_budget: Option<DiskBudget>,
+    cache: Option<Cache>,
+    object_stores: Option<ObjectStores>,
+    /// The repos skipped for their size, to be recorded in the results.
+    skipped: Vec<String>,
+    progress: Progress,
+    /// Whether all repos were returned already, if they are needed at once for sampling or
+    /// confirmation.
+    collected: bool,
+    /// The seed that the repos were sampled with, which is written into the results so that
+    /// the sample can be drawn again.
+    sample_seed: Option<u64>,
+    /// The number of pages of repos fetched so far.
+    pages: usize,
+    clone_queue: CloneQueue,
+    /// Where the results go instead of the out file, if anywhere.
+    sink: Option<Box<dyn OutputSink>>,
+    /// Cancels the repos in progress, on a second Ctrl-C or by whoever holds a clone.
+    cancel: CancellationToken,
+}
+
+impl Runner {
+    /// Set up a run with the options of `cli_app`, which reports its progress to `progress`:
+    /// check that the options go together, read the queries, and set up the forge.
+    pub async fn new(cli_app: RunCmd, progress: Progress) -> Result<Self, Error> {
+        Ok(Self::from_argh(cli_app, progress).await?)
+    }
+
+    async fn from_argh(cli_app: RunCmd, progress: Progress) -> Result<Self> {
+        let mut code_queries = match &cli_app.query_file {
+            Some(path) => CodeQueries::from_file(path, cli_app.engine).await?,
+            None if cli_app.preset.is_some() || cli_app.metadata_only || cli_app.estimate => {
+                CodeQueries::default()
+            }
+            None => {
+                return Err(anyhow!(
+                "--query-file is needed, unless --preset, --metadata-only, or --estimate is given"
+            ))
+            }
+        };
+        if let Some(preset) = cli_app.preset {
+            code_queries.extend(CodeQueries::from_preset(preset, cli_app.engine)?);
+        }
+        if cli_app.metadata_only && cli_app.local_only {
+            return Err(anyhow!(
+                "--metadata-only needs repos found on a forge, not --local-only"
+            ));
+        }
+
+        let forge = forge::from_argh(&cli_app, &code_queries)?;
+        credentials::configure(&cli_app)?;
+        bandwidth::configure(cli_app.max_bandwidth).await?;
+
+        let mut search_options = SearchOptions::from_argh(&cli_app);
+        // these analyze the checkout, not each revision
+        for (flag, given) in [
+            ("--plugins", cli_app.plugins.is_some()),
+            ("--semgrep-rules", cli_app.semgrep_rules.is_some()),
+            ("--analyzers", cli_app.analyzers.is_some()),
+            ("--detect-license", cli_app.detect_license),
+            ("--dependencies", cli_app.dependencies.is_some()),
+            ("--ci", cli_app.ci),
+        ] {
+            if given && cli_app.history.is_some() {
+                return Err(anyhow!(
+                    "{flag} can't be combined with --history, since it only sees the latest revision"
+                ));
+            }
+        }
+        if let Some(path) = &cli_app.plugins {
+            search_options.plugins = plugins::from_file(path).await?;
+        }
+        if let Some(path) = &cli_app.dependencies {
+            search_options.dependencies = manifests::from_file(path).await?;
+        }
+        // semgrep runs in the clones, so a rule file is needed by its full path
+        if let Some(rules) = &cli_app.semgrep_rules {
+            let path = Path::new(rules);
+            if tokio::fs::try_exists(path).await? {
+                let path = tokio::fs::canonicalize(path).await?;
+                search_options.semgrep_rules = Some(path.to_string_lossy().into_owned());
+            }
+        }
+
+        let filter = RepoFilter::from_argh(&cli_app)?;
+        let disk_budget = cli_app
+            .max_disk
+            .map(|max_disk| DiskBudget::new(max_disk, cli_app.rm));
+        let object_stores = cli_app
+            .share_objects
+            .then(|| ObjectStores::new(&cli_app.target_dir));
+        let has_queries = cli_app.query_file.is_some() || cli_app.preset.is_some();
+        let cache = match cli_app.cache && has_queries {
+            true => {
+                let query_file = cli_app.query_file.as_deref();
+                let git_ref = cli_app.git_ref.clone();
+                let target_dir = &cli_app.target_dir;
+                let preset = cli_app.preset;
+                Some(Cache::load(target_dir, query_file, preset, &search_options, git_ref).await?)
+            }
+            false => None,
+        };
+
+        if (cli_app.bare || cli_app.git_ref.is_some()) && cli_app.from_swh.is_some() {
+            return Err(anyhow!(
+                "--bare and --ref can't be combined with --from-swh, whose snapshots aren't git repos"
+            ));
+        }
+        if cli_app.clone_depth.is_some() && (cli_app.full_history || cli_app.history.is_some()) {
+            return Err(anyhow!(
+                "--clone-depth can't be combined with --full-history or --history"
+            ));
+        }
+        if cli_app.temp_dir.is_some() && (!cli_app.rm || cli_app.local_only) {
+            return Err(anyhow!(
+                "--temp-dir needs --rm, and repos to clone rather than those in the target directory"
+            ));
+        }
+        // a retry finds only the repos that failed, not all of them
+        if cli_app.prune && (cli_app.rm || cli_app.local_only || cli_app.retry_from.is_some()) {
+            return Err(anyhow!(
+                "--prune can't be combined with --rm, --retry-from, or the repos in the target directory"
+            ));
+        }
+        // a sample or the first pages only find some of the repos, whose clones would be pruned
+        if cli_app.prune && (cli_app.sample.is_some() || cli_app.max_pages.is_some()) {
+            return Err(anyhow!(
+                "--prune can't be combined with --sample or --max-pages, which only find some of the repos"
+            ));
+        }
+        if cli_app.prune_dry_run && !cli_app.prune {
+            return Err(anyhow!("--prune-dry-run needs --prune"));
+        }
+        if cli_app.clone_depth == Some(0) {
+            return Err(anyhow!("--clone-depth must be at least 1"));
+        }
+        if cli_app.clone_jobs == Some(0) {
+            return Err(anyhow!("--clone-jobs must be at least 1"));
+        }
+        if cli_app.seed.is_some() && cli_app.sample.is_none() {
+            return Err(anyhow!("--seed needs --sample"));
+        }
+        if cli_app.strata.is_some() && cli_app.sample.is_none() {
+            return Err(anyhow!("--strata needs --sample"));
+        }
+        if cli_app.sarif && cli_app.examples.unwrap_or(0) == 0 {
+            return Err(anyhow!(
+                "--sarif needs --examples, whose matches it reports"
+            ));
+        }
+        if cli_app.every.is_some() && cli_app.interactive {
+            return Err(anyhow!(
+                "--every runs unattended, so it can't be --interactive"
+            ));
+        }
+        // stdin is read to its end by the first pass, so later ones would find no repos
+        if cli_app.every.is_some() && cli_app.repo_list.as_deref() == Some(Path::new("-")) {
+            return Err(anyhow!(
+                "--every can't read --repo-list from stdin, which only holds the list once"
+            ));
+        }
+
+        let redirect_client = match cli_app.resolve_redirects {
+            true => Some(reqwest::Client::builder().user_agent(USER_AGENT).build()?),
+            false => None,
+        };
+
+        let clone_jobs = cli_app.clone_jobs.unwrap_or(pipeline::CLONE_JOBS);
+        Ok(Runner {
+            cli_app,
+            forge,
+            code_queries,
+            search_options,
+            rm_paths: HashSet::new(),
+            corpus: HashSet::new(),
+            temp_dirs: 0,
+            seen: HashSet::new(),
+            redirect_client,
+            filter,
+            disk_budget,
+            cache,
+            object_stores,
+            skipped: Vec::new(),
+            progress,
+            collected: false,
+            sample_seed: None,
+            pages: 0,
+            clone_queue: CloneQueue::new(clone_jobs),
+            sink: None,
+            cancel: CancellationToken::new(),
+        })
+    }
+
+    /// Take the repos from `source` instead of from the one the options name, e.g. from a
+    /// forge of one's own. [`restart`](Self::restart) sets up the one the options name again.
+    pub fn with_source(mut self, source: Box<dyn RepoSource>) -> Self {
+        self.forge = source;
+        self
+    }
+
+    /// Write the results into `sink` instead of the out file, e.g. into a database. The
+    /// out file still names the files written next to it, like the failures.
+    pub fn with_sink(mut self, sink: Box<dyn OutputSink>) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// A token that cancels the run in progress, like a second Ctrl-C does: no more repos are
+    /// started, those in progress are given up, and the results of those finished so far are
+    /// written before [`run`](Self::run) returns [`Error::Interrupted`]. A cancelled runner
+    /// gets a new token once it's [restarted](Self::restart).
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// The events of the repos from now on, like that one was cloned, as they happen, to show
+    /// the progress of the run. They are sent until the runner is dropped, across restarts.
+    pub fn events(&mut self) -> mpsc::UnboundedReceiver<Event> {
+        self.progress.subscribe()
+    }
+
+    /// The repos of a page that should be analyzed, i.e. that aren't filtered out and weren't
+    /// found before.
+    async fn accept(&mut self, repos: Vec<Repo>) -> Vec<Repo> {
+        let mut accepted = Vec::with_capacity(repos.len());
+
+        for repo in repos {
+            let repo = match &self.redirect_client {
+                Some(client) => match repo.resolve_redirects(client).await {
+                    Ok(resolved) => resolved,
+                    Err(e) => {
+                        log::warn!("Failed to resolve {}/{}: {e}", repo.owner, repo.name);
+                        repo
+                    }
+                },
+                None => repo,
+            };
+            if let Some(reason) = self.filter.skip_reason(&repo) {
+                log::info!("Skipping {}/{}, which is {reason}", repo.owner, repo.name);
+                if reason == TOO_LARGE {
+                    self.skipped.push(format!("{}/{}", repo.owner, repo.name));
+                }
+                continue;
+            }
+            if !self.seen.insert(repo.key()) {
+                log::info!(
+                    "Skipping {}/{}, which was found before",
+                    repo.owner,
+                    repo.name
+                );
+                continue;
+            }
+            accepted.push(repo);
+        }
+
+        accepted
+    }
+
+    /// The jobs that handle the repos of a page, with the clone paths to remove if they are
+    /// cancelled.
+    fn handle_page(&mut self, repos: Vec<Repo>) -> Vec<QueuedJob> {
+        let mut handles = Vec::with_capacity(repos.len());
+        self.progress.add_repos(repos.len());
+        let object_stores = match &mut self.object_stores {
+            Some(stores) => stores.plan(&repos),
+            None => vec![None; repos.len()],
+        };
+
+        for (repo, object_store) in repos.into_iter().zip(object_stores) {
+            self.progress.queue_repo(&repo.owner, &repo.name);
+            let full_name = format!("{}/{}", repo.owner, repo.name);
+            self.corpus.insert(full_name.to_lowercase());
+            // repos retried with a file URL are searched in place, and must not be removed
+            let remove = self.cli_app.rm && repo.clone_url.scheme() != "file";
+            let temp_dir = self.cli_app.temp_dir.as_ref().filter(|_| remove);
+            if remove && temp_dir.is_none() {
+                let rm_path = self.cli_app.target_dir.join(&repo.owner);
+                self.rm_paths.insert(rm_path);
+            }
+
+            let mut clone_options = CloneOptions::from_argh(&self.cli_app);
+            clone_options.remove = remove;
+            if let Some(temp_dir) = temp_dir {
+                // numbered by process too, so that concurrent runs can share a temp dir
+                let name = format!("octosurfer.{}.{}", std::process::id(), self.temp_dirs);
+                self.temp_dirs += 1;
+                clone_options.target_dir = temp_dir.join(name);
+                clone_options.temporary = true;
+            }
+            clone_options.disk_budget = self.disk_budget.clone();
+            clone_options.cache = self.cache.clone();
+            clone_options.object_store = object_store;
+            clone_options.clone_queue = Some(self.clone_queue.clone());
+            let rm_paths = match remove {
+                true => clone_options.paths(&repo),
+                false => Vec::new(),
+            };
+            let job: RepoJob = Box::pin(handle_repo_with_timeout(
+                repo,
+                self.code_queries.clone(),
+                self.search_options.clone(),
+                clone_options,
+                self.progress.clone(),
+                self.cancel.clone(),
+            ));
+            handles.push((rm_paths, job));
+        }
+
+        handles
+    }
+
+    /// The next page of repos to analyze, or all of them at once when sampling or asking
+    /// which to analyze.
+    async fn next_repos(&mut self) -> Result<Option<Vec<Repo>>> {
+        if self.cli_app.sample.is_none() && !self.cli_app.interactive {
+            return match self.forge.next_page().await? {
+                Some(repos) => {
+                    self.pages += 1;
+                    Ok(Some(self.accept(repos).await))
+                }
+                None => Ok(None),
+            };
+        }
+        if self.collected {
+            return Ok(None);
+        }
+        self.collected = true;
+
+        let mut repos = Vec::new();
+        while let Some(page) = self.forge.next_page().await? {
+            self.pages += 1;
+            repos.extend(self.accept(page).await);
+        }
+
+        if let Some(n) = self.cli_app.sample {
+            let seed = self.cli_app.seed.unwrap_or_else(sample::random_seed);
+            log::info!("Sampling {n} of {} repos with --seed {seed}", repos.len());
+            self.sample_seed = Some(seed);
+            // in an order of their own, since a forge may list the same repos in another one
+            repos.sort_by_cached_key(|repo| repo.key());
+            repos = match &self.cli_app.strata {
+                Some(strata) => sample::stratified_sample(repos, n, seed, strata),
+                None => sample::sample(repos, n, seed),
+            };
+        }
+        if self.cli_app.interactive {
+            repos = interactive::confirm(repos).await?;
+        }
+        Ok(Some(repos))
+    }
+
+    /// Print the repos that would be analyzed, and the query they were found with, without
+    /// cloning any.
+    async fn dry_run(&mut self) -> Result<()> {
+        if let Some(query) = self.forge.query() {
+            println!("Query: {query}");
+        }
+
+        let mut count = 0;
+        let mut total_size = 0;
+        let mut unknown_size = 0;
+        while let Some(repos) = self.next_repos().await? {
+            for repo in repos {
+                count += 1;
+                let size = match repo.meta.size {
+                    Some(size) => {
+                        total_size += size * 1024;
+                        ByteSize(size * 1024).to_string()
+                    }
+                    None => {
+                        unknown_size += 1;
+                        "?".to_owned()
+                    }
+                };
+                println!("{}/{}\t{size}\t{}", repo.owner, repo.name, repo.clone_url);
+            }
+        }
+
+        print!("{count} repos, {} in total", ByteSize(total_size));
+        match unknown_size {
+            0 => println!(),
+            n => println!(", not counting {n} repos of unknown size"),
+        }
+        Ok(())
+    }
+
+    /// Print an estimate of the run from the repos' metadata, without cloning any.
+    async fn estimate(&mut self) -> Result<()> {
+        let mut repos = Vec::new();
+        while let Some(page) = self.next_repos().await? {
+            repos.extend(page);
+        }
+
+        let code_searches = match self.cli_app.prefilter {
+            true => github::prefilter_terms(&self.code_queries)?.len(),
+            false => 0,
+        };
+        print!("{}", Estimate::new(&repos, self.pages, code_searches));
+        Ok(())
+    }
+
+    /// Write the metadata of the repos found, without cloning any.
+    async fn metadata_only(&mut self) -> Result<()> {
+        let mut repos = Vec::new();
+        while let Some(page) = self.next_repos().await? {
+            repos.extend(page);
+        }
+
+        metadata::write(&self.cli_app.out_file, &repos).await?;
+        log::info!(
+            "Wrote metadata of {} repos to {:?}",
+            repos.len(),
+            self.cli_app.out_file
+        );
+        Ok(())
+    }
+
+    /// Find the repos, and clone and search them, or only list or estimate them, as the
+    /// options say. Ctrl-C stops finding repos, and a second one cancels those in progress,
+    /// like the [cancellation token](Self::cancellation_token) does.
+    pub async fn run(&mut self) -> Result<(), Error> {
+        if self.cli_app.dry_run {
+            return Ok(self.dry_run().await?);
+        }
+        if self.cli_app.estimate {
+            return Ok(self.estimate().await?);
+        }
+        if self.cli_app.metadata_only {
+            return Ok(self.metadata_only().await?);
+        }
+
+        // cancelling the run stops finding repos, too
+        let stop = self.cancel.child_token();
+        let grace_period = self.cli_app.grace_period;
+        let watcher = tokio::spawn(watch_ctrl_c(
+            stop.clone(),
+            self.cancel.clone(),
+            grace_period,
+        ));
+        let result = self.analyze(stop).await;
+        watcher.abort();
+        for limit in self.forge.rate_limits() {
+            log::debug!(
+                "Left of the {} rate limit: {}/{}, until {}",
+                limit.resource,
+                limit.remaining,
+                limit.limit,
+                limit.reset
+            );
+        }
+        Ok(result?)
+    }
+
+    /// Prepare another run with the same options, which finds the repos anew.
+    pub fn restart(&mut self) -> Result<(), Error> {
+        self.forge = forge::from_argh(&self.cli_app, &self.code_queries)?;
+        self.seen.clear();
+        self.rm_paths.clear();
+        self.corpus.clear();
+        self.skipped.clear();
+        self.collected = false;
+        self.pages = 0;
+        self.progress = self.progress.restart();
+        if self.cancel.is_cancelled() {
+            self.cancel = CancellationToken::new();
+        }
+        Ok(())
+    }
+
+    /// Queue the jobs of the repos as they are found, until all are found or Ctrl-C was
+    /// pressed.
+    async fn find(
+        &mut self,
+        queue: mpsc::UnboundedSender<QueuedJob>,
+        stop: CancellationToken,
+    ) -> Result<()> {
+        loop {
+            let repos = tokio::select! {
+                repos = self.next_repos() => repos?,
+                _ = stop.cancelled() => None,
+            };
+            let Some(repos) = repos else {
+                return Ok(());
+            };
+            for job in self.handle_page(repos) {
+                // only closed if the run was cancelled
+                let _ = queue.send(job);
+            }
+        }
+    }
+
+    /// Clone and search the repos, until all are done or Ctrl-C was pressed. The repos of a
+    /// page are cloned and searched while the next pages are found.
+    async fn analyze(&mut self, stop: CancellationToken) -> Result<()> {
+        let (queue, queued) = mpsc::unbounded_channel();
+        let cancel = self.cancel.clone();
+        let (found, collected) = tokio::join!(
+            self.find(queue, stop.clone()),
+            pipeline::collect(queued, cancel),
+        );
+        found?;
+
+        let mut aggregator = aggregate::Aggregator::new(&self.code_queries, &self.search_options);
+        for repo in self.skipped.drain(..) {
+            aggregator.add_status(repo, "skipped");
+        }
+
+        let mut succeeded = 0;
+        let mut failed = 0;
+        let mut cancelled = 0;
+        let mut failures = Vec::new();
+        let mut disk_usage = HashMap::new();
+
+        for results in collected? {
+            match results {
+                Some(Ok(results)) => {
+                    succeeded += 1;
+                    for mut result in results {
+                        if let Some(seed) = self.sample_seed {
+                            result
+                                .extra
+                                .insert(0, ("sample_seed".to_owned(), seed.to_string()));
+                        }
+                        let size = result
+                            .extra
+                            .iter()
+                            .find(|(column, _)| column == "disk_usage");
+                        if let Some(size) = size.and_then(|(_, size)| size.parse().ok()) {
+                            let full_name = format!("{}/{}", result.repo_owner, result.repo_name);
+                            disk_usage.insert(full_name, size);
+                        }
+                        aggregator.add(result);
+                    }
+                }
+
+                // logged by the task already
+                Some(Err(e)) => {
+                    failed += 1;
+                    if let Ok(failure) = e.downcast::<RepoFailure>() {
+                        let repo = &failure.repo;
+                        let full_name = format!("{}/{}", repo.owner, repo.name);
+                        aggregator.add_status(full_name, failure.status());
+                        failures.push(failure);
+                    }
+                }
+                None => cancelled += 1,
+            }
+        }
+
+        self.progress.finish();
+        let total = succeeded + failed;
+        log::info!("Checked {total} repos, of which {succeeded} succeeded and {failed} failed.");
+        if cancelled > 0 {
+            log::warn!("Cancelled {cancelled} repos in progress");
+        }
+        disk::log_usage(&disk_usage);
+
+        if self.search_options.examples > 0 {
+            let examples_file = self.cli_app.out_file.with_extension("examples.txt");
+            aggregator.write_examples(&examples_file).await?;
+            log::info!("Wrote examples to {:?}", examples_file);
+        }
+
+        if self.cli_app.sarif {
+            let sarif_file = self.cli_app.out_file.with_extension("sarif");
+            aggregator.write_sarif(&sarif_file).await?;
+            log::info!("Wrote SARIF to {:?}", sarif_file);
+        }
+
+        if self.search_options.by_language == Some(LanguageBreakdown::Long) {
+            let languages_file = self.cli_app.out_file.with_extension("languages.csv");
+            aggregator.write_language_breakdown(&languages_file).await?;
+            log::info!("Wrote language breakdown to {:?}", languages_file);
+        }
+
+        match &self.cli_app.retry_from {
+            Some(_) if tokio::fs::try_exists(&self.cli_app.out_file).await? => {
+                let retried_file = self.cli_app.out_file.with_extension("retried.csv");
+                aggregator.write(&mut CsvSink::new(&retried_file)).await?;
+                report::merge_into(&self.cli_app.out_file, &retried_file).await?;
+                log::info!("Merged results into {:?}", self.cli_app.out_file);
+            }
+            _ => match &mut self.sink {
+                Some(sink) => aggregator.write(sink.as_mut()).await?,
+                None => {
+                    let mut sink = CsvSink::new(&self.cli_app.out_file);
+                    aggregator.write(&mut sink).await?;
+                    log::info!("Wrote results to {:?}", self.cli_app.out_file);
+                }
+            },
+        }
+
+        if let Some(cache) = &self.cache {
+            cache.save().await?;
+        }
+
+        let failures_file = failures::path(&self.cli_app.out_file);
+        if !failures.is_empty() {
+            failures::write(&failures_file, &failures).await?;
+            log::info!("Wrote failures to {:?}", failures_file);
+        } else if tokio::fs::try_exists(&failures_file).await? {
+            // those of an earlier run would be mistaken for this run's
+            tokio::fs::remove_file(&failures_file).await?;
+        }
+
+        // an interrupted run didn't find all of its repos
+        if self.cli_app.prune && !stop.is_cancelled() {
+            // the clones of the repos beyond those a search returns would be pruned, and
+            // others each run
+            if self.forge.complete() {
+                let dry_run = self.cli_app.prune_dry_run;
+                clean::prune(&self.cli_app.target_dir, &self.corpus, dry_run).await?;
+            } else {
+                log::warn!(
+                    "Not pruning {:?}, since the search only returned some of the repos it found",
+                    self.cli_app.target_dir
+                );
+            }
+        }
+
+        if let Some(stores) = self.object_stores.as_mut().filter(|_| self.cli_app.rm) {
+            stores.remove().await;
+        }
+
+        // Repos are cloned to {target_dir}/{owner}/{repo}, and when they are removed after
+        // searching, {target_dir}/{owner} remains! So clean that up here.
+        if self.cli_app.rm {
+            for path in self.rm_paths.iter() {
+                // never created if none of the owner's repos were cloned
+                if !tokio::fs::try_exists(path).await? {
+                    continue;
+                }
+                log::info!("Removing {}", path.display());
+                tokio::fs::remove_dir(path).await?;
+            }
+        }
+
+        if stop.is_cancelled() {
+            return Err(Error::Interrupted.into());
+        }
+        Ok(())
+    }
+}
+
+/// Run with the options of `cli_app`, like the run, search, and analyze subcommands do:
+/// once, or with --every, again and again until Ctrl-C is pressed.
+pub async fn run(cli_app: RunCmd, progress: Progress) -> Result<(), Error> {
+    let mut runner = Runner::new(cli_app, progress).await?;
+
+    let Some(every) = runner.cli_app.every else {
+        return runner.run().await;
+    };
+    let out_file = runner.cli_app.out_file.clone();
+    loop {
+        let started = Instant::now();
+        runner.cli_app.out_file = snapshot_path(&out_file, chrono::Utc::now());
+        match runner.run().await {
+            Ok(()) => {}
+            Err(Error::Interrupted) => return Err(Error::Interrupted),
+            Err(e) => log::error!("Run failed: {e}"),
+        }
+
+        let next = HumanDuration(every.duration().saturating_sub(started.elapsed()));
+        log::info!("Running again in {next}");
+        tokio::select! {
+            _ = tokio::time::sleep(next.duration()) => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+        runner.restart()?;
+    }
+}
+
+/// The file to write the results of a run started at `time` into, with --every, e.g.
+/// "results.20240131T120000Z.csv" for "results.csv".
+fn snapshot_path(out_file: &Path, time: chrono::DateTime<chrono::Utc>) -> PathBuf {
+    let timestamp = time.format("%Y%m%dT%H%M%SZ");
+    let stem = out_file.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match out_file.extension() {
+        Some(extension) => format!("{stem}.{timestamp}.{}", extension.to_string_lossy()),
+        None => format!("{stem}.{timestamp}"),
+    };
+    out_file.with_file_name(name)
+}
@@ -0,0 +1,262 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Files larger than this aren't license texts.
+const MAX_LICENSE_SIZE: u64 = 256 * 1024;
+
+/// The share of a template's word pairs that a file needs to contain to be under its license.
+const MIN_SCORE: f64 = 0.8;
+
+/// Licenses whose scores are this close are told apart by how much of the file they explain,
+/// since e.g. a BSD-3-Clause text contains all of BSD-2-Clause.
+const TIE: f64 = 0.05;
+
+/// What's written for license files that match no template.
+const UNKNOWN: &str = "NOASSERTION";
+
+/// SPDX ids and texts of the common licenses. Long licenses are recognized by their opening
+/// paragraphs, which set them apart from their relatives, and a license may have more than one
+/// text, like the notice that Apache-2.0 projects often ship instead of the license.
+const TEMPLATES: &[(&str, &str)] = &[
+    (
+        "0BSD",
+        "Permission to use, copy, modify, and/or distribute this software for any purpose with \
+         or without fee is hereby granted. THE SOFTWARE IS PROVIDED \"AS IS\" AND THE AUTHOR \
+         DISCLAIMS ALL WARRANTIES WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES \
+         OF MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, \
+         DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM LOSS \
+         OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS \
+         ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.",
+    ),
+    (
+        "AGPL-3.0",
+        "GNU AFFERO GENERAL PUBLIC LICENSE Version 3, 19 November 2007. Preamble. The GNU Affero \
+         General Public License is a free, copyleft license for software and other kinds of \
+         works, specifically designed to ensure cooperation with the community in the case of \
+         network server software.",
+    ),
+    (
+        "Apache-2.0",
+        "Apache License Version 2.0, January 2004 http://www.apache.org/licenses/ TERMS AND \
+         CONDITIONS FOR USE, REPRODUCTION, AND DISTRIBUTION 1. Definitions. \"License\" shall \
+         mean the terms and conditions for use, reproduction, and distribution as defined by \
+         Sections 1 through 9 of this document. \"Licensor\" shall mean the copyright owner or \
+         entity authorized by the copyright owner that is granting the License.",
+    ),
+    (
+        "Apache-2.0",
+        "Licensed under the Apache License, Version 2.0 (the \"License\"); you may not use this \
+         file except in compliance with the License. You may obtain a copy of the License at \
+         http://www.apache.org/licenses/LICENSE-2.0 Unless required by applicable law or agreed \
+         to in writing, software distributed under the License is distributed on an \"AS IS\" \
+         BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.",
+    ),
+    (
+        "BSD-2-Clause",
+        "Redistribution and use in source and binary forms, with or without modification, are \
+         permitted provided that the following conditions are met: 1. Redistributions of source \
+         code must retain the above copyright notice, this list of conditions and the following \
+         disclaimer. 2. Redistributions in binary form must reproduce the above copyright notice, \
+         this list of conditions and the following disclaimer in the documentation and/or other \
+         materials provided with the distribution. THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT \
+         HOLDERS AND CONTRIBUTORS \"AS IS\" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT \
+         NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR \
+         PURPOSE ARE DISCLAIMED.",
+    ),
+    (
+        "BSD-3-Clause",
+        "Redistribution and use in source and binary forms, with or without modification, are \
+         permitted provided that the following conditions are met: 1. Redistributions of source \
+         code must retain the above copyright notice, this list of conditions and the following \
+         disclaimer. 2. Redistributions in binary form must reproduce the above copyright notice, \
+         this list of conditions and the following disclaimer in the documentation and/or other \
+         materials provided with the distribution. 3. Neither the name of the copyright holder \
+         nor the names of its contributors may be used to endorse or promote products derived \
+         from this software without specific prior written permission. THIS SOFTWARE IS PROVIDED \
+         BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS \"AS IS\" AND ANY EXPRESS OR IMPLIED \
+         WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND \
+         FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.",
+    ),
+    (
+        "BSL-1.0",
+        "Boost Software License - Version 1.0 - August 17th, 2003 Permission is hereby granted, \
+         free of charge, to any person or organization obtaining a copy of the software and \
+         accompanying documentation covered by this license (the \"Software\") to use, reproduce, \
+         display, distribute, execute, and transmit the Software, and to prepare derivative works \
+         of the Software, and to permit third-parties to whom the Software is furnished to do so, \
+         all subject to the following:",
+    ),
+    (
+        "CC0-1.0",
+        "Creative Commons Legal Code CC0 1.0 Universal CREATIVE COMMONS CORPORATION IS NOT A LAW \
+         FIRM AND DOES NOT PROVIDE LEGAL SERVICES. Statement of Purpose The laws of most \
+         jurisdictions throughout the world automatically confer exclusive Copyright and Related \
+         Rights (defined below) upon the creator and subsequent owner(s) (each and all, an \
+         \"owner\") of an original work of authorship and/or a database (each, a \"Work\").",
+    ),
+    (
+        "GPL-2.0",
+        "GNU GENERAL PUBLIC LICENSE Version 2, June 1991. Preamble. The licenses for most software \
+         are designed to take away your freedom to share and change it. By contrast, the GNU \
+         General Public License is intended to guarantee your freedom to share and change free \
+         software--to make sure the software is free for all its users. This General Public \
+         License applies to most of the Free Software Foundation's software and to any other \
+         program whose authors commit to using it.",
+    ),
+    (
+        "GPL-3.0",
+        "GNU GENERAL PUBLIC LICENSE Version 3, 29 June 2007. Preamble. The GNU General Public \
+         License is a free, copyleft license for software and other kinds of works. The licenses \
+         for most software and other practical works are designed to take away your freedom to \
+         share and change the works. By contrast, the GNU General Public License is intended to \
+         guarantee your freedom to share and change all versions of a program--to make sure it \
+         remains free software for all its users. We, the Free Software Foundation, use the GNU \
+         General Public License for most of our software; it applies also to any other work \
+         released this way by its authors.",
+    ),
+    (
+        "ISC",
+        "Permission to use, copy, modify, and/or distribute this software for any purpose with \
+         or without fee is hereby granted, provided that the above copyright notice and this \
+         permission notice appear in all copies. THE SOFTWARE IS PROVIDED \"AS IS\" AND THE \
+         AUTHOR DISCLAIMS ALL WARRANTIES WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED \
+         WARRANTIES OF MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR \
+         ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER \
+         RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, \
+         NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR \
+         PERFORMANCE OF THIS SOFTWARE.",
+    ),
+    (
+        "LGPL-2.1",
+        "GNU LESSER GENERAL PUBLIC LICENSE Version 2.1, February 1999. This is the first released \
+         version of the Lesser GPL. It also counts as the successor of the GNU Library Public \
+         License, version 2, hence the version number 2.1. Preamble. This license, the Lesser \
+         General Public License, applies to some specially designated software packages--\
+         typically libraries--of the Free Software Foundation and other authors who decide to \
+         use it.",
+    ),
+    (
+        "LGPL-3.0",
+        "GNU LESSER GENERAL PUBLIC LICENSE Version 3, 29 June 2007. This version of the GNU \
+         Lesser General Public License incorporates the terms and conditions of version 3 of the \
+         GNU General Public License, supplemented by the additional permissions listed below.",
+    ),
+    (
+        "MIT",
+        "Permission is hereby granted, free of charge, to any person obtaining a copy of this \
+         software and associated documentation files (the \"Software\"), to deal in the Software \
+         without restriction, including without limitation the rights to use, copy, modify, \
+         merge, publish, distribute, sublicense, and/or sell copies of the Software, and to \
+         permit persons to whom the Software is furnished to do so, subject to the following \
+         conditions: The above copyright notice and this permission notice shall be included in \
+         all copies or substantial portions of the Software. THE SOFTWARE IS PROVIDED \"AS IS\", \
+         WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED.",
+    ),
+    (
+        "MPL-2.0",
+        "Mozilla Public License Version 2.0 1. Definitions 1.1. \"Contributor\" means each \
+         individual or legal entity that creates, contributes to the creation of, or owns \
+         Covered Software. 1.2. \"Contributor Version\" means the combination of the \
+         Contributions of others (if any) used by a Contributor and that particular \
+         Contributor's Contribution.",
+    ),
+    (
+        "Unlicense",
+        "This is free and unencumbered software released into the public domain. Anyone is free \
+         to copy, modify, publish, use, compile, sell, or distribute this software, either in \
+         source code form or as a compiled binary, for any purpose, commercial or \
+         non-commercial, and by any means.",
+    ),
+    (
+        "Zlib",
+        "This software is provided 'as-is', without any express or implied warranty. In no event \
+         will the authors be held liable for any damages arising from the use of this software. \
+         Permission is granted to anyone to use this software for any purpose, including \
+         commercial applications, and to alter it and redistribute it freely, subject to the \
+         following restrictions:",
+    ),
+];
+
+/// Detect the licenses of the clone in `path` from its license files, like LICENSE or
+/// COPYING.LESSER, as SPDX ids joined with "/", like "Apache-2.0/MIT". This is empty without
+/// license files, and [`UNKNOWN`] if none of them is under a known license.
+pub async fn detect(path: &Path, repo: &str) -> String {
+    let texts = match license_texts(path).await {
+        Ok(texts) => texts,
+        Err(e) => {
+            log::warn!(repo; "Failed to read the license files of {repo}: {e}");
+            return String::new();
+        }
+    };
+    if texts.is_empty() {
+        return String::new();
+    }
+
+    let mut licenses: Vec<&str> = texts.iter().filter_map(|text| classify(text)).collect();
+    licenses.sort_unstable();
+    licenses.dedup();
+    match licenses.is_empty() {
+        true => UNKNOWN.to_owned(),
+        false => licenses.join("/"),
+    }
+}
+
+/// The texts of the license files at the top of the clone.
+async fn license_texts(path: &Path) -> std::io::Result<Vec<String>> {
+    let mut texts = Vec::new();
+    let mut entries = tokio::fs::read_dir(path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_lowercase();
+        if !["license", "licence", "copying", "unlicense"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+        {
+            continue;
+        }
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() || metadata.len() > MAX_LICENSE_SIZE {
+            continue;
+        }
+        let bytes = tokio::fs::read(entry.path()).await?;
+        texts.push(String::from_utf8_lossy(&bytes).into_owned());
+    }
+    Ok(texts)
+}
+
+/// The license of `text`: of the templates whose word pairs it mostly contains, the one with
+/// the best score, or the longest of those that are about as good.
+fn classify(text: &str) -> Option<&'static str> {
+    let pairs = word_pairs(text);
+    let mut matches: Vec<(&str, f64, usize)> = TEMPLATES
+        .iter()
+        .filter_map(|(id, template)| {
+            let template = word_pairs(template);
+            let found = template.intersection(&pairs).count();
+            let score = found as f64 / template.len() as f64;
+            (score >= MIN_SCORE).then_some((*id, score, template.len()))
+        })
+        .collect();
+
+    let best = matches
+        .iter()
+        .map(|(_, score, _)| *score)
+        .fold(0.0, f64::max);
+    matches.retain(|(_, score, _)| best - score <= TIE);
+    matches
+        .into_iter()
+        .max_by_key(|(_, _, len)| *len)
+        .map(|(id, _, _)| id)
+}
+
+/// The pairs of adjacent words of `text`, ignoring case, punctuation, and line breaks.
+fn word_pairs(text: &str) -> HashSet<(String, String)> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words
+        .windows(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect()
+}
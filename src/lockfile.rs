@@ -0,0 +1,306 @@
+use crate::forge::Repo;
+use crate::registry::{self, RegistryKind, USER_AGENT};
+use crate::RunCmd;
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+use url::Url;
+
+/// A dependency found in a lockfile or SBOM.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Dependency {
+    /// A package, whose repository the registry knows.
+    Package(RegistryKind, String),
+    /// A repository URL.
+    Repo(String),
+}
+
+/// The dependencies in a Cargo.lock. Packages without a source belong to the workspace.
+fn cargo_lock(text: &str) -> Vec<Dependency> {
+    let mut dependencies = Vec::new();
+    let mut name = None;
+    for line in text.lines().map(str::trim) {
+        if line == "[[package]]" {
+            name = None;
+        } else if let Some(value) = line.strip_prefix("name = ") {
+            name = Some(value.trim_matches('"').to_owned());
+        } else if let Some(source) = line.strip_prefix("source = ") {
+            let source = source.trim_matches('"');
+            if source.starts_with("registry+") || source.starts_with("sparse+") {
+                dependencies.extend(
+                    name.take()
+                        .map(|name| Dependency::Package(RegistryKind::CratesIo, name)),
+                );
+            } else if source.starts_with("git+") {
+                dependencies.push(Dependency::Repo(source.to_owned()));
+            }
+        }
+    }
+    dependencies
+}
+
+/// The dependencies in a package-lock.json or npm-shrinkwrap.json, of any version.
+fn package_lock(json: &Value) -> Vec<Dependency> {
+    let mut dependencies = Vec::new();
+
+    // lockfile versions 2 and 3, keyed by path, e.g. "node_modules/a/node_modules/@b/c"
+    if let Some(packages) = json.get("packages").and_then(Value::as_object) {
+        for (path, package) in packages {
+            let Some((_, name)) = path.rsplit_once("node_modules/") else {
+                // the project itself
+                continue;
+            };
+            if package.get("link").and_then(Value::as_bool) == Some(true) {
+                continue;
+            }
+            dependencies.push(npm_dependency(name, package));
+        }
+        return dependencies;
+    }
+
+    // lockfile version 1, nested by name
+    let mut stack = vec![json];
+    while let Some(node) = stack.pop() {
+        let Some(nested) = node.get("dependencies").and_then(Value::as_object) else {
+            continue;
+        };
+        for (name, package) in nested {
+            dependencies.push(npm_dependency(name, package));
+            stack.push(package);
+        }
+    }
+    dependencies
+}
+
+/// A package from npm, or a repo for packages installed from git.
+fn npm_dependency(name: &str, package: &Value) -> Dependency {
+    let resolved = package
+        .get("resolved")
+        .or_else(|| package.get("version"))
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+
+    if resolved.starts_with("git+") || resolved.starts_with("git://") {
+        return Dependency::Repo(resolved.to_owned());
+    }
+    if let Some(full_name) = resolved.strip_prefix("github:") {
+        let full_name = full_name.split('#').next().unwrap_or(full_name);
+        return Dependency::Repo(format!("https://github.com/{full_name}"));
+    }
+    Dependency::Package(RegistryKind::Npm, name.to_owned())
+}
+
+/// The dependency a package URL, like "pkg:cargo/serde@1.0.0", refers to, if its type is
+/// supported.
+fn purl(purl: &str) -> Option<Dependency> {
+    let purl = purl.strip_prefix("pkg:")?;
+    let purl = purl.split(['?', '#']).next().unwrap_or(purl);
+    // the version follows the name, while namespaces may start with an unencoded "@" too
+    let name_start = purl.rfind('/').unwrap_or(0);
+    let purl = match purl[name_start..].find('@') {
+        Some(at) => &purl[..name_start + at],
+        None => purl,
+    };
+    let (kind, path) = purl.split_once('/')?;
+    // namespaces are percent-encoded, e.g. "%40types/node"
+    let path = path
+        .replace("%40", "@")
+        .replace("%2F", "/")
+        .replace("%2f", "/");
+
+    let dependency = match kind {
+        "cargo" => Dependency::Package(RegistryKind::CratesIo, path),
+        "npm" => Dependency::Package(RegistryKind::Npm, path),
+        "pypi" => Dependency::Package(RegistryKind::Pypi, path),
+        "github" => Dependency::Repo(format!("https://github.com/{path}")),
+        "gitlab" => Dependency::Repo(format!("https://gitlab.com/{path}")),
+        "bitbucket" => Dependency::Repo(format!("https://bitbucket.org/{path}")),
+        _ => return None,
+    };
+    Some(dependency)
+}
+
+/// The dependencies in an SPDX SBOM, by their package URLs, or their download locations
+/// if those are repos.
+fn spdx(json: &Value) -> Vec<Dependency> {
+    let packages = json.get("packages").and_then(Value::as_array);
+    packages
+        .into_iter()
+        .flatten()
+        .filter_map(|package| {
+            let refs = package.get("externalRefs").and_then(Value::as_array);
+            let from_purl = refs
+                .into_iter()
+                .flatten()
+                .filter(|r| r.get("referenceType").and_then(Value::as_str) == Some("purl"))
+                .filter_map(|r| r.get("referenceLocator").and_then(Value::as_str))
+                .find_map(purl);
+
+            from_purl.or_else(|| {
+                let location = package.get("downloadLocation").and_then(Value::as_str)?;
+                location
+                    .starts_with("git+")
+                    .then(|| Dependency::Repo(location.to_owned()))
+            })
+        })
+        .collect()
+}
+
+/// The dependencies in a CycloneDX SBOM, by their package URLs, or their VCS references.
+fn cyclonedx(json: &Value) -> Vec<Dependency> {
+    let mut dependencies = Vec::new();
+    let mut stack: Vec<&Value> = json
+        .get("components")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .collect();
+
+    while let Some(component) = stack.pop() {
+        let from_purl = component.get("purl").and_then(Value::as_str).and_then(purl);
+        let from_vcs = || {
+            let refs = component
+                .get("externalReferences")
+                .and_then(Value::as_array);
+            refs.into_iter()
+                .flatten()
+                .find(|r| r.get("type").and_then(Value::as_str) == Some("vcs"))
+                .and_then(|r| r.get("url").and_then(Value::as_str))
+                .map(|url| Dependency::Repo(url.to_owned()))
+        };
+        dependencies.extend(from_purl.or_else(from_vcs));
+
+        // components may be nested
+        let nested = component.get("components").and_then(Value::as_array);
+        stack.extend(nested.into_iter().flatten());
+    }
+    dependencies
+}
+
+/// The dependencies in a lockfile or SBOM, told apart by its name and contents.
+fn dependencies(path: &Path, text: &str) -> Result<Vec<Dependency>> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    if file_name == "Cargo.lock" {
+        return Ok(cargo_lock(text));
+    }
+
+    let json: Value = serde_json::from_str(text)
+        .with_context(|| format!("{path:?} is neither a Cargo.lock nor JSON"))?;
+    if json.get("lockfileVersion").is_some() {
+        Ok(package_lock(&json))
+    } else if json.get("spdxVersion").is_some() {
+        Ok(spdx(&json))
+    } else if json.get("bomFormat").and_then(Value::as_str) == Some("CycloneDX") {
+        Ok(cyclonedx(&json))
+    } else {
+        Err(anyhow!(
+            "{path:?} is not a package-lock.json, or an SPDX or CycloneDX SBOM"
+        ))
+    }
+}
+
+/// Whether `text`, read from `path`, is a lockfile or SBOM that [`LockfileDependencies`]
+/// reads.
+pub fn is_lockfile(path: &Path, text: &str) -> bool {
+    dependencies(path, text).is_ok()
+}
+
+/// The repos of the dependencies in a lockfile or SBOM.
+pub struct LockfileDependencies {
+    client: reqwest::Client,
+    /// The dependencies still to resolve, taken from the back.
+    remaining: Vec<Dependency>,
+    seen: HashSet<Url>,
+    /// The number of dependencies whose repos couldn't be looked up.
+    failed: usize,
+}
+
+impl LockfileDependencies {
+    /// Dependencies resolved per page.
+    const PAGE_SIZE: usize = 50;
+
+    pub fn from_argh(argh: &RunCmd) -> Result<Self> {
+        let Some(path) = &argh.from_lockfile else {
+            return Err(anyhow!("No lockfile given"));
+        };
+        registry::check_unsupported(argh, "lockfiles")?;
+        Self::from_path(path)
+    }
+
+    /// The repos of the dependencies in the lockfile or SBOM in `path`.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+        let mut unique = HashSet::new();
+        let mut remaining: Vec<Dependency> = dependencies(path, &text)?
+            .into_iter()
+            .filter(|dependency| unique.insert(dependency.clone()))
+            .collect();
+        log::info!("Found {} dependencies in {:?}", remaining.len(), path);
+        remaining.reverse();
+
+        Ok(Self {
+            client: reqwest::Client::builder().user_agent(USER_AGENT).build()?,
+            remaining,
+            seen: HashSet::new(),
+            failed: 0,
+        })
+    }
+
+    async fn resolve(&self, dependency: &Dependency) -> Result<Option<Repo>> {
+        let url = match dependency {
+            Dependency::Repo(url) => url.clone(),
+            Dependency::Package(registry, package) => {
+                if *registry == RegistryKind::CratesIo {
+                    // crates.io asks crawlers for at most one request per second
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                match registry::package_repository(&self.client, *registry, package).await? {
+                    Some(url) => url,
+                    None => {
+                        log::debug!("Skipping {package}, which has no repository");
+                        return Ok(None);
+                    }
+                }
+            }
+        };
+        Repo::from_url(&url).map(Some)
+    }
+
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Repo>>> {
+        if self.remaining.is_empty() {
+            return Ok(None);
+        }
+        let page = self
+            .remaining
+            .split_off(self.remaining.len().saturating_sub(Self::PAGE_SIZE));
+
+        let mut repos = Vec::new();
+        for dependency in page.into_iter().rev() {
+            match self.resolve(&dependency).await {
+                Ok(Some(repo)) => {
+                    // repos often hold several packages
+                    if self.seen.insert(repo.clone_url.clone()) {
+                        repos.push(repo);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log::warn!("Skipping {dependency:?}: {e}");
+                    self.failed += 1;
+                }
+            }
+        }
+        Ok(Some(repos))
+    }
+
+    /// Whether the repos of all dependencies were looked up so far.
+    pub fn complete(&self) -> bool {
+        self.failed == 0
+    }
+}
@@ -0,0 +1,86 @@
+use chrono::{SecondsFormat, Utc};
+use log::kv::{Key, Value, VisitSource};
+use serde_json::{Map, Number};
+use std::io::Write;
+use std::str::FromStr;
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// For people to read.
+    #[default]
+    Text,
+    /// One JSON object per line, for programs to read.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format: {s}")),
+        }
+    }
+}
+
+/// Logs one JSON object per line to stderr, with the time, level, target, and message of
+/// each record, and its key-values, like `repo` and `phase`.
+pub struct JsonLogger {
+    level: log::LevelFilter,
+}
+
+impl JsonLogger {
+    pub fn new(level: log::LevelFilter) -> Self {
+        Self { level }
+    }
+}
+
+/// Collects the key-values of a record into a JSON object.
+struct Fields<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = if let Some(n) = value.to_u64() {
+            n.into()
+        } else if let Some(n) = value.to_i64() {
+            n.into()
+        } else if let Some(n) = value.to_f64().and_then(Number::from_f64) {
+            n.into()
+        } else if let Some(b) = value.to_bool() {
+            b.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+impl log::Log for JsonLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut event = Map::new();
+        let time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        event.insert("time".to_owned(), time.into());
+        event.insert("level".to_owned(), record.level().as_str().into());
+        event.insert("target".to_owned(), record.target().into());
+        event.insert("message".to_owned(), record.args().to_string().into());
+        // a key-value that can't be visited is left out, rather than losing the event
+        let _ = record.key_values().visit(&mut Fields(&mut event));
+
+        let line = serde_json::Value::Object(event).to_string();
+        let _ = writeln!(std::io::stderr().lock(), "{line}");
+    }
+
+    fn flush(&self) {}
+}
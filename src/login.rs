@@ -0,0 +1,88 @@
+use crate::duration::HumanDuration;
+use crate::registry::USER_AGENT;
+use crate::token;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::time::Duration;
+
+const DEVICE_CODE_URL: &str = "https://github.com/login/device/code";
+const ACCESS_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// The codes that start the device flow, for the user to enter in a browser.
+#[derive(Debug, Deserialize)]
+struct DeviceCodes {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+/// A poll for the token, which is pending until the user entered the code.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Poll {
+    Token { access_token: String },
+    Error { error: String },
+}
+
+/// Log in to GitHub with its device flow: the user enters a code on github.com to authorize
+/// the OAuth app `client_id`, and its token is stored like [`token::store`] does, so that
+/// later runs use it. With `private`, the token can read private repos, too.
+pub async fn login(client_id: &str, private: bool) -> Result<()> {
+    let client = reqwest::Client::builder().user_agent(USER_AGENT).build()?;
+    let scope = match private {
+        true => "repo",
+        false => "",
+    };
+    let codes: DeviceCodes = client
+        .post(DEVICE_CODE_URL)
+        .header("Accept", "application/json")
+        .form(&[("client_id", client_id), ("scope", scope)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    eprintln!(
+        "Open {} and enter the code {} within {}",
+        codes.verification_uri,
+        codes.user_code,
+        HumanDuration(Duration::from_secs(codes.expires_in))
+    );
+
+    let mut interval = Duration::from_secs(codes.interval);
+    let token = loop {
+        tokio::time::sleep(interval).await;
+        let poll: Poll = client
+            .post(ACCESS_TOKEN_URL)
+            .header("Accept", "application/json")
+            .form(&[
+                ("client_id", client_id),
+                ("device_code", &codes.device_code),
+                ("grant_type", GRANT_TYPE),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match poll {
+            Poll::Token { access_token } => break access_token,
+            Poll::Error { error } => match error.as_str() {
+                "authorization_pending" => {}
+                // GitHub wants 5 more seconds between polls from then on
+                "slow_down" => interval += Duration::from_secs(5),
+                "expired_token" => return Err(anyhow!("The code expired, log in again")),
+                "access_denied" => return Err(anyhow!("The authorization was denied")),
+                error => return Err(anyhow!("Failed to log in: {error}")),
+            },
+        }
+    };
+
+    let stored = token::store(&token)?;
+    eprintln!("Logged in, the token is stored in {stored}");
+    Ok(())
+}
@@ -1,308 +1,292 @@
-mod aggregate;
-mod code_queries;
-mod github_query;
-mod search;
-
-use crate::code_queries::{CodeQueries, QueryResults};
-use crate::github_query::GithubQuery;
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
 use argh::FromArgs;
-use chrono::TimeZone;
-use octocrab::models::Repository;
-use octocrab::Octocrab;
-use std::collections::HashSet;
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use indicatif_log_bridge::LogWrapper;
+use octosurfer::code_queries::Engine;
+use octosurfer::logging::{JsonLogger, LogFormat};
+use octosurfer::progress::Progress;
+#[cfg(feature = "tui")]
+use octosurfer::tui;
+use octosurfer::{check, clean, login, proxy, report, run, token, AnalyzeCmd, RunCmd, SearchCmd};
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use tokio::process::Command;
-use tokio::task::JoinHandle;
 use url::Url;
 
 /// Clone all GitHub repositories matching a query and search them
 #[derive(FromArgs)]
 pub struct OctoSurfer {
-    /// keywords to use when searching for repos (comma-separated)
-    #[argh(option, short = 'k')]
-    keywords: String,
-
-    /// limit search to repos that use these languages (comma-separated)
-    #[argh(option, short = 'l')]
-    languages: Option<String>,
-
-    /// limit search by date, e.g. ">1970-01-01" for repos updated after Jan 1st, 1970
-    #[argh(option, short = 'p')]
-    pushed: Option<String>,
-
-    /// limit search by stars, e.g. ">100" for repos with more than 100 stars
-    #[argh(option, short = 's')]
-    stars: Option<String>,
-
-    /// limit search by these topics (comma-separated)
-    #[argh(option, short = 't')]
-    topics: Option<String>,
-
-    /// path to a directory into which repositories should be cloned
-    #[argh(option, short = 'd')]
-    target_dir: PathBuf,
-
-    /// file to read code queries from
-    #[argh(option, short = 'q')]
-    query_file: PathBuf,
-
-    /// filename to write CSV results into
-    #[argh(option, short = 'o')]
-    out_file: PathBuf,
-
-    /// remove repos after analysis is complete
-    #[argh(switch)]
-    rm: bool,
+    #[argh(subcommand)]
+    command: Subcommand,
 
     /// sets the verbosity (off, error, warn, info, debug, or trace)
     #[argh(option, short = 'v', default = "log::LevelFilter::Info")]
     verbosity: log::LevelFilter,
-}
 
-async fn update_repo(path: &Path) -> Result<()> {
-    let output = Command::new("git")
-        .arg("-C")
-        .arg(path.as_os_str())
-        .arg("pull")
-        .output()
-        .await?;
+    /// how to write log lines: text, or json for one object per line with fields like repo,
+    /// phase, duration, and error
+    #[argh(option, default = "LogFormat::Text")]
+    log_format: LogFormat,
 
-    if output.status.success() {
-        log::debug!("Successfully updated {:?}", path);
-        Ok(())
-    } else {
-        Err(anyhow!("Failed to update {:?}", path))
-    }
-}
+    /// don't draw progress bars below the log, which are drawn if stderr is a terminal
+    #[argh(switch)]
+    no_progress: bool,
 
-async fn clone_repo(clone_path: &Path, owner: &str, name: &str, clone_url: &Url) -> Result<()> {
-    tokio::fs::create_dir_all(&clone_path).await?;
+    /// draw a dashboard of the repos, matches, GitHub's rate limits, and the log instead,
+    /// if octosurfer was built with the "tui" feature
+    #[argh(switch)]
+    tui: bool,
 
-    let output = Command::new("git")
-        .arg("clone")
-        .arg("--quiet")
-        .arg("--depth")
-        .arg("1")
-        .arg(clone_url.as_str())
-        .arg(clone_path.as_os_str())
-        .output()
-        .await?;
+    /// the proxy to go through for the APIs and for cloning, like http://host:port or
+    /// socks5h://host:port, instead of the one of http_proxy, https_proxy, or all_proxy
+    #[argh(option)]
+    proxy: Option<Url>,
 
-    if output.status.success() {
-        log::debug!("Successfully cloned {}/{}", owner, name);
-        Ok(())
-    } else {
-        Err(anyhow!(
-            "Failed to clone repo {}/{}! Exit code: {}",
-            owner,
-            name,
-            output.status
-        ))
-    }
+    /// read the GitHub token from this file instead of GITHUB_TOKEN; without either, the
+    /// token of the gh CLI is used, or else the one in the OS keyring
+    #[argh(option)]
+    token_file: Option<PathBuf>,
 }
 
-async fn handle_repo(
-    repo: Repository,
-    base: PathBuf,
-    queries: CodeQueries,
-    remove: bool,
-) -> Result<QueryResults> {
-    let name = &repo.name;
-    let owner = &repo
-        .owner
-        .as_ref()
-        .ok_or_else(|| anyhow!("Repo without an owner!"))?
-        .login;
-    let clone_url = &repo
-        .clone_url
-        .as_ref()
-        .ok_or_else(|| anyhow!("Repo without a clone URL!"))?;
-
-    let clone_path = base.join(owner).join(name);
-
-    if tokio::fs::try_exists(&clone_path).await? {
-        log::info!("Updating {}/{}", owner, name);
-        update_repo(&clone_path).await?;
-    } else {
-        log::info!("Cloning {}/{}", owner, name);
-        clone_repo(&clone_path, owner, name, clone_url).await?;
-    }
+/// The options of [`OctoSurfer`] that take a value, and its switches, which stay before the
+/// subcommand when `run` is implied.
+const GLOBAL_OPTIONS: &[&str] = &[
+    "-v",
+    "--verbosity",
+    "--log-format",
+    "--proxy",
+    "--token-file",
+];
+const GLOBAL_SWITCHES: &[&str] = &["--no-progress", "--tui"];
+
+/// The names of the subcommands, and of argh's own help.
+const SUBCOMMANDS: &[&str] = &[
+    "run",
+    "search",
+    "analyze",
+    "report",
+    "clean",
+    "check-queries",
+    "login",
+    "help",
+    "--help",
+];
+
+// only ever constructed once, so the size difference doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Subcommand {
+    Run(RunCmd),
+    Search(SearchCmd),
+    Analyze(AnalyzeCmd),
+    Report(ReportCmd),
+    Clean(CleanCmd),
+    CheckQueries(CheckQueriesCmd),
+    Login(LoginCmd),
+}
 
-    // try to avoid EMFILE (too many open files)
-    tokio::time::sleep(Duration::from_millis(100)).await;
+/// Merge results files written by run or analyze, and print how many repos matched each
+/// query
+#[derive(FromArgs)]
+#[argh(subcommand, name = "report")]
+pub struct ReportCmd {
+    /// results files to merge; rows in later files replace rows for the same repos in
+    /// earlier files
+    #[argh(positional)]
+    results: Vec<PathBuf>,
+
+    /// filename to write the merged results into
+    #[argh(option, short = 'o')]
+    out_file: Option<PathBuf>,
+}
 
-    let results =
-        search::search_repo(&clone_path, owner.to_owned(), name.to_owned(), &queries).await?;
+/// Remove the repos cloned into a target directory
+#[derive(FromArgs)]
+#[argh(subcommand, name = "clean")]
+pub struct CleanCmd {
+    /// path to the directory the repos were cloned into
+    #[argh(option, short = 'd')]
+    target_dir: PathBuf,
 
-    if remove {
-        log::debug!("Removing {:?}", clone_path);
-        tokio::fs::remove_dir_all(&clone_path).await?;
-    }
+    /// only list the clones that would be removed, with their sizes, without removing any
+    #[argh(switch)]
+    dry_run: bool,
 
-    Ok(results)
+    /// only remove the clones of repos that aren't in this file anymore: a results file of
+    /// run or analyze, a repo list like --repo-list takes, or a lockfile or SBOM like
+    /// --from-lockfile takes
+    #[argh(option)]
+    prune: Option<PathBuf>,
 }
 
-struct Runner {
-    cli_app: OctoSurfer,
-    octocrab: Octocrab,
-    code_queries: CodeQueries,
-    rm_paths: HashSet<PathBuf>,
+/// Log in to GitHub in a browser, and store the token for later runs
+#[derive(FromArgs)]
+#[argh(subcommand, name = "login")]
+pub struct LoginCmd {
+    /// the client ID of the GitHub OAuth app to authorize, which needs to have the device
+    /// flow enabled
+    #[argh(option)]
+    client_id: String,
+
+    /// have the token read private repos, too, for searching and --clone-with-token
+    #[argh(switch)]
+    private: bool,
 }
 
-impl Runner {
-    async fn wait_for_reset(&self, reset_ts: u64) -> Result<()> {
-        let reset_ts = reset_ts.try_into()?;
-        let reset = chrono::Utc.timestamp_opt(reset_ts, 0).unwrap();
-        let now = chrono::Utc::now();
-
-        if reset > now {
-            let delta = reset - now;
-            let delta = delta.to_std()?;
-            log::info!("Sleeping for {:?}", delta);
-            tokio::time::sleep(delta).await;
-        } else {
-            log::warn!("Rate limit reset time was in the past!");
-        }
-
-        Ok(())
-    }
-
-    async fn check_rate_limit(&self) -> Result<()> {
-        // GitHub gives 30 search requests per minute
-        // https://docs.github.com/en/rest/search?apiVersion=2022-11-28
+/// Validate a query file without searching or cloning anything
+#[derive(FromArgs)]
+#[argh(subcommand, name = "check-queries")]
+pub struct CheckQueriesCmd {
+    /// file to read code queries from
+    #[argh(option, short = 'q')]
+    query_file: PathBuf,
 
-        let rate = self.octocrab.ratelimit().get().await?.resources.search;
-        let remaining = rate.remaining;
-        log::trace!("Remaining requests: {remaining}/30");
+    /// regex engine for queries without an engine prefix (rust or pcre2)
+    #[argh(option, default = "Engine::Rust")]
+    engine: Engine,
+}
 
-        if remaining == 0 {
-            log::warn!("Search rate limit exhausted!");
-            self.wait_for_reset(rate.reset).await?;
-        } else if remaining < 10 {
-            log::warn!("Running low on search requests: {remaining}/30");
-            tokio::time::sleep(Duration::from_secs(5)).await;
-        }
-        Ok(())
+#[tokio::main]
+async fn main() -> Result<()> {
+    let octosurfer = from_env();
+    proxy::configure(octosurfer.proxy.as_ref())?;
+    token::configure(octosurfer.token_file.as_deref())?;
+    if octosurfer.tui {
+        return run_with_dashboard(octosurfer).await;
     }
 
-    async fn handle_page(
-        &mut self,
-        repos: Vec<Repository>,
-    ) -> Result<Vec<JoinHandle<Result<QueryResults>>>> {
-        let mut handles = Vec::with_capacity(repos.len());
-
-        for repo in repos {
-            if self.cli_app.rm {
-                let owner = &repo
-                    .owner
-                    .as_ref()
-                    .ok_or_else(|| anyhow!("Repo without an owner!"))?
-                    .login;
-                let rm_path = self.cli_app.target_dir.join(owner);
-                self.rm_paths.insert(rm_path);
-            }
-
-            let handle = tokio::spawn(handle_repo(
-                repo,
-                self.cli_app.target_dir.clone(),
-                self.code_queries.clone(),
-                self.cli_app.rm,
-            ));
-            handles.push(handle);
-        }
-
-        Ok(handles)
+    // log lines are printed above the progress bars, if there are any
+    let multi = MultiProgress::new();
+    if octosurfer.no_progress || !std::io::stderr().is_terminal() {
+        multi.set_draw_target(ProgressDrawTarget::hidden());
     }
-
-    async fn run(&mut self) -> Result<()> {
-        self.check_rate_limit().await?;
-
-        let query_string = GithubQuery::from_argh(&self.cli_app).to_query_string()?;
-        let mut page = self
-            .octocrab
-            .search()
-            .repositories(&query_string)
-            .sort("updated")
-            .order("desc")
-            .send()
-            .await?;
-
-        let mut handles = Vec::new();
-        loop {
-            let handle = self.handle_page(page.items).await?;
-            handles.extend(handle);
-
-            self.check_rate_limit().await?;
-
-            match self.octocrab.get_page(&page.next).await? {
-                Some(next_page) => {
-                    page = next_page;
-                }
-                None => break,
-            };
-        }
-
-        let mut aggregator = aggregate::Aggregator::new(&self.code_queries);
-
-        let mut succeeded = 0;
-        let mut failed = 0;
-
-        for handle in handles {
-            match handle.await? {
-                Ok(results) => {
-                    succeeded += 1;
-                    aggregator.add(results);
+    match octosurfer.verbosity {
+        log::LevelFilter::Off => {}
+        level => {
+            match octosurfer.log_format {
+                LogFormat::Text => {
+                    let logger = simple_logger::SimpleLogger::new().with_level(level);
+                    LogWrapper::new(multi.clone(), logger).try_init()?;
                 }
-
-                Err(e) => {
-                    log::error!("Failed: {e}");
-                    failed += 1;
+                LogFormat::Json => {
+                    LogWrapper::new(multi.clone(), JsonLogger::new(level)).try_init()?;
                 }
             }
+            log::set_max_level(level);
         }
+    }
+    let progress = Progress::new(multi);
+
+    match octosurfer.command {
+        Subcommand::Run(cli_app) => Ok(run(cli_app, progress).await?),
+        Subcommand::Search(cmd) => Ok(run(cmd.into(), progress).await?),
+        Subcommand::Analyze(cmd) => Ok(run(cmd.into(), progress).await?),
+        Subcommand::Report(cmd) => report::report(&cmd.results, cmd.out_file.as_deref()).await,
+        Subcommand::Clean(cmd) => match &cmd.prune {
+            Some(list) => clean::prune_against(&cmd.target_dir, list, cmd.dry_run).await,
+            None => clean::clean(&cmd.target_dir, cmd.dry_run).await,
+        },
+        Subcommand::CheckQueries(cmd) => check::check_queries(&cmd.query_file, cmd.engine).await,
+        Subcommand::Login(cmd) => login::login(&cmd.client_id, cmd.private).await,
+    }
+}
 
-        let total = succeeded + failed;
-        log::info!("Checked {total} repos, of which {succeeded} succeeded and {failed} failed.");
-
-        aggregator.write(&self.cli_app.out_file).await?;
-        log::info!("Wrote results to {:?}", self.cli_app.out_file);
-
-        // Repos are cloned to {target_dir}/{owner}/{repo}, and when they are removed after
-        // searching, {target_dir}/{owner} remains! So clean that up here.
-        if self.cli_app.rm {
-            for path in self.rm_paths.iter() {
-                log::info!("Removing {}", path.display());
-                tokio::fs::remove_dir(path).await?;
+/// Parse the command line like [`argh::from_env`], but with `run` implied if no subcommand
+/// is given, as in `octosurfer -k mpi -d /tmp/octosurfer -q queries.txt -o results.csv`,
+/// which is how octosurfer was invoked before it had subcommands.
+fn from_env() -> OctoSurfer {
+    let args = implied_run(std::env::args().collect());
+    let command = Path::new(&args[0])
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&args[0]);
+    let strs: Vec<&str> = args.iter().map(String::as_str).collect();
+    OctoSurfer::from_args(&[command], &strs[1..]).unwrap_or_else(|early_exit| {
+        std::process::exit(match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                0
             }
-        }
+            Err(()) => {
+                eprintln!(
+                    "{}\nRun {command} --help for more information.",
+                    early_exit.output
+                );
+                1
+            }
+        })
+    })
+}
 
-        Ok(())
+/// `args` with `run` inserted after the global options, unless they name a subcommand. The
+/// global options can be given anywhere then, as before.
+fn implied_run(args: Vec<String>) -> Vec<String> {
+    let mut global = Vec::new();
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    let program = args.next().unwrap_or_default();
+    while let Some(arg) = args.next() {
+        if GLOBAL_OPTIONS.contains(&arg.as_str()) {
+            global.push(arg);
+            global.extend(args.next());
+        } else if GLOBAL_SWITCHES.contains(&arg.as_str()) {
+            global.push(arg);
+        } else if rest.is_empty() && SUBCOMMANDS.contains(&arg.as_str()) {
+            // given explicitly, so the arguments are left as they are
+            return std::iter::once(program)
+                .chain(global)
+                .chain(std::iter::once(arg))
+                .chain(args)
+                .collect();
+        } else {
+            rest.push(arg);
+        }
     }
+    if rest.is_empty() {
+        return std::iter::once(program).chain(global).collect();
+    }
+    std::iter::once(program)
+        .chain(global)
+        .chain(std::iter::once("run".to_owned()))
+        .chain(rest)
+        .collect()
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli_app: OctoSurfer = argh::from_env();
-
-    match cli_app.verbosity {
-        log::LevelFilter::Off => {}
-        _ => simple_logger::init_with_level(cli_app.verbosity.to_level().unwrap())?,
+/// Run or analyze like `main`, while drawing the dashboard.
+#[cfg(feature = "tui")]
+async fn run_with_dashboard(octosurfer: OctoSurfer) -> Result<()> {
+    let cli_app: RunCmd = match octosurfer.command {
+        Subcommand::Run(cli_app) => cli_app,
+        Subcommand::Analyze(cmd) => cmd.into(),
+        _ => return Err(anyhow!("--tui only applies to run and analyze")),
+    };
+    if cli_app.uses_terminal() {
+        return Err(anyhow!(
+            "--tui can't be combined with --interactive or --dry-run"
+        ));
+    }
+    if octosurfer.log_format == LogFormat::Json {
+        return Err(anyhow!("--tui can't be combined with --log-format json"));
+    }
+    if !std::io::stderr().is_terminal() {
+        return Err(anyhow!("--tui needs stderr to be a terminal"));
     }
 
-    let gh_token =
-        std::env::var("GITHUB_TOKEN").context("Must set GITHUB_TOKEN environment variable!")?;
-    let octocrab = Octocrab::builder().personal_token(gh_token).build()?;
-
-    let code_queries = CodeQueries::from_file(&cli_app.query_file).await?;
+    let board = tui::Board::default();
+    log::set_boxed_logger(Box::new(tui::BoardLogger::new(board.clone())))?;
+    log::set_max_level(octosurfer.verbosity);
+    let multi = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+    let progress = Progress::new(multi).with_board(board.clone());
 
-    let mut runner = Runner {
-        cli_app,
-        octocrab,
-        code_queries,
-        rm_paths: HashSet::new(),
-    };
+    let dashboard = tui::Dashboard::start(board)?;
+    let result = run(cli_app, progress).await;
+    dashboard.stop().await?;
+    Ok(result?)
+}
 
-    runner.run().await
+#[cfg(not(feature = "tui"))]
+async fn run_with_dashboard(_: OctoSurfer) -> Result<()> {
+    Err(anyhow!(
+        "--tui needs octosurfer to be built with the \"tui\" feature"
+    ))
 }
@@ -0,0 +1,392 @@
+use crate::search::SearchOptions;
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use walkdir::WalkDir;
+
+/// Prefix of the columns with the declared versions of each dependency.
+const COLUMN_PREFIX: &str = "dependency: ";
+
+/// What's written for dependencies declared without a version, like those from a path or git.
+const ANY_VERSION: &str = "*";
+
+/// The kind of manifest a dependency is declared in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ecosystem {
+    /// Cargo.toml
+    Cargo,
+    /// package.json
+    Npm,
+    /// go.mod
+    Go,
+    /// requirements.txt
+    Pypi,
+}
+
+impl FromStr for Ecosystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cargo" => Ok(Ecosystem::Cargo),
+            "npm" => Ok(Ecosystem::Npm),
+            "go" => Ok(Ecosystem::Go),
+            "pypi" => Ok(Ecosystem::Pypi),
+            _ => Err(format!("unknown ecosystem: {s}")),
+        }
+    }
+}
+
+impl Ecosystem {
+    /// The ecosystem of a manifest, by its file name.
+    fn of_manifest(file_name: &str) -> Option<Self> {
+        match file_name {
+            "Cargo.toml" => Some(Ecosystem::Cargo),
+            "package.json" => Some(Ecosystem::Npm),
+            "go.mod" => Some(Ecosystem::Go),
+            "requirements.txt" => Some(Ecosystem::Pypi),
+            _ => None,
+        }
+    }
+}
+
+/// A dependency to look for in each clone's manifests, like `serde`, or `npm:lodash` for
+/// only those declared in a package.json.
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    /// The line of the dependency file, which names its column.
+    pub column: String,
+    pub ecosystem: Option<Ecosystem>,
+    pub name: String,
+}
+
+impl Dependency {
+    fn matches(&self, ecosystem: Ecosystem, name: &str) -> bool {
+        if self.ecosystem.is_some_and(|e| e != ecosystem) {
+            return false;
+        }
+        match ecosystem {
+            // PyPI names are compared normalized, so "PyYAML" is "pyyaml"
+            Ecosystem::Pypi => normalize_pypi(&self.name) == normalize_pypi(name),
+            _ => self.name == name,
+        }
+    }
+}
+
+/// Read the dependencies of a dependency file, one per line, optionally prefixed with their
+/// ecosystem, like `cargo:serde` or `go:github.com/pkg/errors`.
+pub async fn from_file(path: &Path) -> Result<Vec<Dependency>> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read {path:?}"))?;
+
+    let mut dependencies: Vec<Dependency> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.contains(',') {
+            return Err(anyhow!(
+                "Commas aren't allowed on line {} of {path:?}",
+                i + 1
+            ));
+        }
+
+        let (ecosystem, name) = match line.split_once(':') {
+            Some((ecosystem, name)) => {
+                let ecosystem = ecosystem
+                    .parse()
+                    .map_err(|e| anyhow!("{e} on line {} of {path:?}", i + 1))?;
+                (Some(ecosystem), name.trim())
+            }
+            None => (None, line),
+        };
+        if dependencies
+            .iter()
+            .any(|dependency| dependency.column == line)
+        {
+            return Err(anyhow!("Dependency {line} is listed twice in {path:?}"));
+        }
+        dependencies.push(Dependency {
+            column: line.to_owned(),
+            ecosystem,
+            name: name.to_owned(),
+        });
+    }
+
+    if dependencies.is_empty() {
+        return Err(anyhow!("No dependencies in {path:?}"));
+    }
+    Ok(dependencies)
+}
+
+/// Look for the dependencies in the manifests of the clone in `path`, and return a column
+/// for each of them that is declared, with the versions it's declared with.
+pub async fn run(
+    dependencies: &[Dependency],
+    path: &Path,
+    options: &SearchOptions,
+    repo: &str,
+) -> Vec<(String, String)> {
+    let declared = tokio::task::spawn_blocking({
+        let path = path.to_owned();
+        let options = options.clone();
+        move || declared(&path, &options)
+    })
+    .await;
+
+    let declared = match declared {
+        Ok(declared) => declared,
+        Err(e) => {
+            log::warn!(repo; "Failed to read the manifests of {repo}: {e}");
+            return Vec::new();
+        }
+    };
+
+    let mut columns = Vec::new();
+    for dependency in dependencies {
+        let versions: BTreeSet<&str> = declared
+            .iter()
+            .filter(|(ecosystem, name, _)| dependency.matches(*ecosystem, name))
+            .map(|(_, _, version)| version.as_str())
+            .collect();
+        if !versions.is_empty() {
+            let versions: Vec<&str> = versions.into_iter().collect();
+            let column = format!("{COLUMN_PREFIX}{}", dependency.column);
+            columns.push((column, versions.join("; ")));
+        }
+    }
+    columns
+}
+
+/// All dependencies declared in the manifests of the clone in `path`, with their versions.
+fn declared(path: &Path, options: &SearchOptions) -> Vec<(Ecosystem, String, String)> {
+    let manifests: Vec<(Ecosystem, PathBuf)> = WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|entry| options.include_entry(entry))
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let ecosystem = Ecosystem::of_manifest(entry.file_name().to_str()?)?;
+            Some((ecosystem, entry.into_path()))
+        })
+        .collect();
+
+    let mut declared = Vec::new();
+    for (ecosystem, manifest) in manifests {
+        let text = match std::fs::read(&manifest) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                log::debug!("Failed to read {manifest:?}: {e}");
+                continue;
+            }
+        };
+        let dependencies = match ecosystem {
+            Ecosystem::Cargo => cargo_toml(&text),
+            Ecosystem::Npm => match serde_json::from_str(&text) {
+                Ok(json) => package_json(&json),
+                Err(e) => {
+                    log::debug!("Failed to parse {manifest:?}: {e}");
+                    continue;
+                }
+            },
+            Ecosystem::Go => go_mod(&text),
+            Ecosystem::Pypi => requirements_txt(&text),
+        };
+        for (name, version) in dependencies {
+            // the CSV isn't quoted, and e.g. ">=1.0, <2.0" reads the same without the comma
+            let version = version
+                .replace(',', " ")
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            let version = match version.is_empty() {
+                true => ANY_VERSION.to_owned(),
+                false => version,
+            };
+            declared.push((ecosystem, name, version));
+        }
+    }
+    declared
+}
+
+/// The dependencies in a Cargo.toml, of all kinds and targets, by their package names. Inline
+/// tables can't span lines in TOML, so each is declared on a line, or in a table of its own.
+fn cargo_toml(text: &str) -> Vec<(String, String)> {
+    let mut dependencies = Vec::new();
+    // in a table like [dependencies], or in one of a single dependency, like
+    // [dependencies.serde], with its name and version so far
+    let mut in_dependencies = false;
+    let mut table: Option<(String, String)> = None;
+
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            dependencies.extend(table.take());
+            let header = line
+                .trim_start_matches('[')
+                .split(']')
+                .next()
+                .unwrap_or_default();
+            let header = header.trim();
+            in_dependencies = header.ends_with("dependencies");
+            if let Some((section, name)) = header.rsplit_once('.') {
+                if section.ends_with("dependencies") {
+                    table = Some((unquote(name).to_owned(), String::new()));
+                }
+            }
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if let Some((name, version)) = table.as_mut() {
+            match key {
+                "version" => *version = unquote(value).to_owned(),
+                "package" => *name = unquote(value).to_owned(),
+                _ => {}
+            }
+        } else if in_dependencies {
+            // e.g. `serde.workspace = true`
+            let name = unquote(key.split('.').next().unwrap_or(key));
+            let dependency = match value.strip_prefix('{') {
+                Some(fields) => {
+                    let name = inline_field(fields, "package").unwrap_or(name);
+                    let version = inline_field(fields, "version").unwrap_or_default();
+                    (name.to_owned(), version.to_owned())
+                }
+                None if value.starts_with('"') || value.starts_with('\'') => {
+                    (name.to_owned(), unquote(value).to_owned())
+                }
+                None => (name.to_owned(), String::new()),
+            };
+            dependencies.push(dependency);
+        }
+    }
+    dependencies.extend(table);
+    dependencies
+}
+
+/// A field of an inline table like `{ version = "1.0", features = ["derive"] }`.
+fn inline_field<'a>(fields: &'a str, field: &str) -> Option<&'a str> {
+    // commas in strings, like in `version = ">=1.0, <2.0"`, don't separate fields
+    let mut quote = None;
+    fields
+        .split(|c| {
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), c) if q == c => quote = None,
+                _ => {}
+            }
+            c == ',' && quote.is_none()
+        })
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| key.trim() == field)
+        .map(|(_, value)| unquote(value.trim()))
+}
+
+/// A TOML string without its quotes and anything after them, like a comment.
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    match value.chars().next() {
+        Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+        _ => value,
+    }
+}
+
+/// The dependencies in a package.json, including those only for development.
+fn package_json(json: &Value) -> Vec<(String, String)> {
+    let mut dependencies = Vec::new();
+    for field in [
+        "dependencies",
+        "devDependencies",
+        "peerDependencies",
+        "optionalDependencies",
+    ] {
+        let Some(declared) = json.get(field).and_then(Value::as_object) else {
+            continue;
+        };
+        for (name, version) in declared {
+            let version = version.as_str().unwrap_or_default();
+            dependencies.push((name.clone(), version.to_owned()));
+        }
+    }
+    dependencies
+}
+
+/// The modules required in a go.mod, on single lines or in blocks.
+fn go_mod(text: &str) -> Vec<(String, String)> {
+    let mut dependencies = Vec::new();
+    let mut in_block = false;
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        let requirement = if in_block {
+            if line == ")" {
+                in_block = false;
+                continue;
+            }
+            line
+        } else if let Some(rest) = line.strip_prefix("require") {
+            let rest = rest.trim();
+            if rest == "(" {
+                in_block = true;
+                continue;
+            }
+            rest
+        } else {
+            continue;
+        };
+
+        let mut words = requirement.split_whitespace();
+        if let Some(module) = words.next() {
+            let version = words.next().unwrap_or_default();
+            dependencies.push((module.to_owned(), version.to_owned()));
+        }
+    }
+    dependencies
+}
+
+/// The packages in a requirements.txt, with their version specifiers, like ">=2.0".
+fn requirements_txt(text: &str) -> Vec<(String, String)> {
+    let mut dependencies = Vec::new();
+    for line in text.lines() {
+        // options like `-r other.txt` or `-e .` aren't packages of their own
+        let line = line.split(" #").next().unwrap_or_default();
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('-') {
+            continue;
+        }
+
+        let end = line
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.')))
+            .unwrap_or(line.len());
+        let (name, rest) = line.split_at(end);
+        // URLs, like git+https://..., aren't names
+        if name.is_empty() || rest.starts_with(['+', ':']) {
+            continue;
+        }
+        // extras, like requests[socks]
+        let rest = match rest.trim_start().strip_prefix('[') {
+            Some(rest) => rest
+                .split_once(']')
+                .map(|(_, rest)| rest)
+                .unwrap_or_default(),
+            None => rest,
+        };
+        dependencies.push((name.to_owned(), rest.trim().to_owned()));
+    }
+    dependencies
+}
+
+/// A PyPI name as PyPI compares them, in lowercase and with runs of "-", "_", and "." as "-".
+fn normalize_pypi(name: &str) -> String {
+    name.to_lowercase()
+        .split(['-', '_', '.'])
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
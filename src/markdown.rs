@@ -0,0 +1,45 @@
+use regex::Regex;
+use std::collections::HashSet;
+
+/// First path segments of github.com URLs that don't belong to users or organizations.
+const RESERVED_OWNERS: &[&str] = &[
+    "about",
+    "apps",
+    "collections",
+    "contact",
+    "enterprise",
+    "features",
+    "login",
+    "marketplace",
+    "orgs",
+    "pricing",
+    "settings",
+    "site",
+    "sponsors",
+    "topics",
+    "trending",
+];
+
+/// The owners and names of all GitHub repos linked from a markdown document, in order of
+/// first appearance, without duplicates.
+pub fn github_repos(text: &str) -> Vec<(String, String)> {
+    let link = Regex::new(r"https?://(?:www\.)?github\.com/([A-Za-z0-9-]+)/([A-Za-z0-9._-]+)")
+        .expect("valid regex");
+
+    let mut seen = HashSet::new();
+    let mut repos = Vec::new();
+    for captures in link.captures_iter(text) {
+        let owner = &captures[1];
+        let name = captures[2].trim_end_matches(".git").trim_end_matches('.');
+        if name.is_empty() || RESERVED_OWNERS.contains(&owner.to_lowercase().as_str()) {
+            continue;
+        }
+
+        // GitHub doesn't care about case
+        if seen.insert(format!("{owner}/{name}").to_lowercase()) {
+            repos.push((owner.to_owned(), name.to_owned()));
+        }
+    }
+
+    repos
+}
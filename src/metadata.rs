@@ -0,0 +1,67 @@
+use crate::forge::{parse_date, Repo, RepoMeta};
+use anyhow::{anyhow, Result};
+use std::io;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+/// The header of the CSV that `write` writes.
+pub const HEADER: &str = "repo,clone_url,stars,language,pushed_at,size_kb,topics";
+
+/// A repo, with its metadata, from a row of the CSV that `write` writes.
+pub fn parse_row(row: &str) -> Result<Repo> {
+    let columns: Vec<&str> = row.split(',').collect();
+    let [_, clone_url, stars, language, pushed_at, size, topics] = columns[..] else {
+        return Err(anyhow!("Expected the columns {HEADER}"));
+    };
+
+    let mut repo = Repo::from_url(clone_url)?;
+    repo.meta = RepoMeta {
+        stars: stars.parse().ok(),
+        language: Some(language.to_owned()).filter(|language| !language.is_empty()),
+        size: size.parse().ok(),
+        pushed_at: parse_date(pushed_at),
+        topics: topics
+            .split(';')
+            .filter(|topic| !topic.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+        parent: None,
+        ssh_url: None,
+    };
+    Ok(repo)
+}
+
+/// Write what the forges told about repos as CSV, one row per repo. Topics are separated by
+/// semicolons, and unknown values are left empty.
+pub async fn write(path: &Path, repos: &[Repo]) -> io::Result<()> {
+    let f = File::create(path).await?;
+    let mut writer = BufWriter::new(f);
+
+    writer.write_all(HEADER.as_bytes()).await?;
+    writer.write_u8(b'\n').await?;
+
+    for repo in repos {
+        let meta = &repo.meta;
+        let row = format!(
+            "{}/{},{},{},{},{},{},{}\n",
+            repo.owner,
+            repo.name,
+            repo.clone_url,
+            meta.stars
+                .map(|stars| stars.to_string())
+                .unwrap_or_default(),
+            meta.language.as_deref().unwrap_or_default(),
+            meta.pushed_at
+                .map(|pushed_at| pushed_at.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+                .unwrap_or_default(),
+            meta.size.map(|size| size.to_string()).unwrap_or_default(),
+            meta.topics.join(";"),
+        );
+        writer.write_all(row.as_bytes()).await?;
+    }
+
+    writer.flush().await?;
+
+    Ok(())
+}
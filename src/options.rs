@@ -0,0 +1,507 @@
+//! The options of the run, search, and analyze subcommands, of which search and analyze take
+//! some each. argh can't flatten structs, so the options are declared once in groups, each a
+//! macro that hands its fields to another one: `command` to declare a subcommand with them,
+//! or `copy_options` to copy them from one subcommand's options to another's.
+
+/// Declare a subcommand with the options of `groups`, in order, followed by its own.
+macro_rules! command {
+    ($(#[$attr:meta])* pub struct $name:ident uses [$($group:ident),*] { $($own:tt)* }) => {
+        command!(@collect [$(#[$attr])* pub struct $name] [$($group)*] [] [$($own)*]);
+    };
+    (@collect $head:tt $groups:tt [$($fields:tt)*] $own:tt { $($more:tt)* }) => {
+        command!(@collect $head $groups [$($fields)* $($more)*] $own);
+    };
+    (@collect $head:tt [$group:ident $($groups:ident)*] $fields:tt $own:tt) => {
+        $group!(command!(@collect $head [$($groups)*] $fields $own));
+    };
+    (@collect [$($head:tt)*] [] [$($fields:tt)*] [$($own:tt)*]) => {
+        $($head)* { $($fields)* $($own)* }
+    };
+}
+
+/// Copy the options of a group from the subcommand options in `from` to those in `to`.
+macro_rules! copy_options {
+    ($to:ident, $from:ident { $($(#[$attr:meta])* $field:ident: $ty:ty,)* }) => {
+        $($to.$field = $from.$field;)*
+    };
+}
+
+/// The options that find repos and filter them, of run and search.
+macro_rules! find_options {
+    ($callback:ident!($($args:tt)*)) => {
+        $callback! { $($args)* {
+            /// where to search for repos: GitHub, a Gitea instance like Codeberg, or Bitbucket
+            /// Cloud (github, gitea, or bitbucket, default: github)
+            #[argh(option, default = "ForgeKind::Github")]
+            forge: ForgeKind,
+
+            /// base URL of the forge, e.g. "https://gitea.example.org" (default for gitea:
+            /// "https://codeberg.org", for bitbucket: "https://api.bitbucket.org/2.0/")
+            #[argh(option)]
+            forge_url: Option<Url>,
+
+            /// analyze the repos of packages from a package registry instead of searching a forge:
+            /// the most downloaded packages matching the keywords (crates.io, npm, or pypi)
+            #[argh(option)]
+            registry: Option<RegistryKind>,
+
+            /// with --registry crates.io, only take crates from this category
+            #[argh(option)]
+            category: Option<String>,
+
+            /// with --registry, only take packages downloaded at least this often (on npm and pypi:
+            /// in the last 30 days)
+            #[argh(option)]
+            min_downloads: Option<u64>,
+
+            /// keywords to use when searching for repos (comma-separated)
+            #[argh(option, short = 'k')]
+            keywords: Option<String>,
+
+            /// limit search to repos that use these languages (comma-separated)
+            #[argh(option, short = 'l')]
+            languages: Option<String>,
+
+            /// limit search by date, e.g. ">1970-01-01" for repos updated after Jan 1st, 1970
+            #[argh(option, short = 'p')]
+            pushed: Option<String>,
+
+            /// limit search by stars, e.g. ">100" for repos with more than 100 stars
+            #[argh(option, short = 's')]
+            stars: Option<String>,
+
+            /// limit search by these topics (comma-separated)
+            #[argh(option, short = 't')]
+            topics: Option<String>,
+
+            /// limit search to the repos of this GitHub organization
+            #[argh(option)]
+            org: Option<String>,
+
+            /// limit search to the repos of this GitHub user
+            #[argh(option)]
+            user: Option<String>,
+
+            /// list all repos of the organization given by --org, or of the user given by --user,
+            /// instead of searching them, which doesn't use up the search rate limit; --languages
+            /// still applies
+            #[argh(switch)]
+            all_repos: bool,
+
+            /// analyze the repos starred by this GitHub user instead of searching for repos;
+            /// --languages still applies
+            #[argh(option)]
+            starred_by: Option<String>,
+
+            /// analyze the GitHub repos linked from this markdown file, e.g. an awesome list,
+            /// instead of searching for repos; --languages still applies
+            #[argh(option)]
+            from_markdown: Option<PathBuf>,
+
+            /// analyze the GitHub repos depending on this repo, given as owner/name, as listed
+            /// on its "Used by" page, instead of searching for repos; --languages still applies
+            #[argh(option)]
+            dependents_of: Option<String>,
+
+            /// analyze the repos in this file, one per line, given as owner/name on GitHub or as
+            /// URLs, instead of searching for repos; "-" reads them from stdin, e.g. piped from
+            /// `gh repo list`
+            #[argh(option)]
+            repo_list: Option<PathBuf>,
+
+            /// analyze the repos of all dependencies in this Cargo.lock, package-lock.json, or SPDX
+            /// or CycloneDX SBOM in JSON, instead of searching for repos
+            #[argh(option)]
+            from_lockfile: Option<PathBuf>,
+
+            /// analyze the latest snapshots archived by Software Heritage of the origin URLs or
+            /// SWHIDs in this file, one per line, instead of searching for repos; they are
+            /// downloaded instead of cloned, so repos that no longer exist can be analyzed
+            #[argh(option)]
+            from_swh: Option<PathBuf>,
+
+            /// analyze all forks of this GitHub repo, given as owner/name, and the repo itself,
+            /// instead of searching for repos; --languages still applies
+            #[argh(option)]
+            forks_of: Option<String>,
+
+            /// limit search to repos under one of these licenses, given as SPDX ids like "mit" or
+            /// "apache-2.0" (comma-separated)
+            #[argh(option)]
+            licenses: Option<String>,
+
+            /// search with GitHub's GraphQL API, which fetches the metadata of all found repos in
+            /// the same requests, and applies --skip-archived, --skip-forks, and --any-language to
+            /// searches
+            #[argh(switch)]
+            graphql: bool,
+
+            /// with --graphql, only keep repos that use one of these languages anywhere, not just
+            /// as their main language (comma-separated)
+            #[argh(option)]
+            any_language: Option<String>,
+
+            /// when listing instead of searching repos, skip archived repos
+            #[argh(switch)]
+            skip_archived: bool,
+
+            /// when listing instead of searching repos, skip forks
+            #[argh(switch)]
+            skip_forks: bool,
+
+            /// before cloning a GitHub repo, check with GitHub's code search that it contains a
+            /// literal from at least one query, and skip it otherwise; code search only covers
+            /// default branches and is limited to 10 requests per minute
+            #[argh(switch)]
+            prefilter: bool,
+
+            /// how many repos to get from GitHub per request, up to 100, which is the default, or
+            /// 50 with --graphql
+            #[argh(option)]
+            per_page: Option<u32>,
+
+            /// stop after this many pages of repos from GitHub, e.g. to try out a query, or to
+            /// bound one that finds far more than expected
+            #[argh(option)]
+            max_pages: Option<u32>,
+
+            /// keep the responses of GitHub's API in this directory, and ask for them again only if
+            /// they changed, which doesn't count against the rate limit, e.g. to resume a run
+            #[argh(option)]
+            api_cache: Option<PathBuf>,
+
+            /// skip the repos matching any of the owner/name patterns in this file, one per line,
+            /// wherever they were found; "*" matches any part of an owner or name, e.g. "me/*" or
+            /// "*/*-mirror"
+            #[argh(option)]
+            exclude_file: Option<PathBuf>,
+
+            /// only analyze the repos of these users or organizations (comma-separated), wherever
+            /// they were found
+            #[argh(option)]
+            only_owners: Option<String>,
+
+            /// skip the repos of these users or organizations (comma-separated), e.g. bots or
+            /// mirror accounts, wherever they were found
+            #[argh(option)]
+            skip_owners: Option<String>,
+
+            /// analyze this many repos, picked at random from all repos found, instead of all of
+            /// them; all repos are found before any are cloned
+            #[argh(option)]
+            sample: Option<usize>,
+
+            /// with --sample, pick that many repos from each stratum instead: each main language
+            /// ("language"), or ranges of stars or sizes, e.g. "stars:10-100,100-1k,1k-" or
+            /// "size:-1M,1M-100M"; repos outside of all strata are skipped
+            #[argh(option)]
+            strata: Option<Strata>,
+
+            /// seed for --sample, to pick the same repos again; a random seed is logged and written
+            /// into the results otherwise
+            #[argh(option)]
+            seed: Option<u64>,
+
+            /// only print the repos that would be analyzed, with their sizes and clone URLs, and
+            /// the query they were found with, without cloning or searching any
+            #[argh(switch)]
+            dry_run: bool,
+
+            /// only estimate the download size, disk footprint, API requests, and runtime of the
+            /// run from the repos' metadata, without cloning or searching any
+            #[argh(switch)]
+            estimate: bool,
+
+            /// list the repos found, with their total size, and ask which of them to analyze before
+            /// cloning any
+            #[argh(switch)]
+            interactive: bool,
+
+            /// before cloning a repo, follow redirects from its URL, so that repos that were
+            /// renamed or transferred are only analyzed once, under their current names
+            #[argh(switch)]
+            resolve_redirects: bool,
+        } }
+    };
+}
+
+/// The options of run, search, and analyze alike.
+macro_rules! size_options {
+    ($callback:ident!($($args:tt)*)) => {
+        $callback! { $($args)* {
+            /// skip repos larger than this, e.g. "1G", as told by the forge, and record them as
+            /// skipped in the results; repos of unknown size aren't skipped
+            #[argh(option)]
+            max_repo_size: Option<ByteSize>,
+        } }
+    };
+}
+
+/// The options that clone repos and search them, of run and analyze.
+macro_rules! analyze_options {
+    ($callback:ident!($($args:tt)*)) => {
+        $callback! { $($args)* {
+            /// analyze the repos that failed in an earlier run, as listed in the failures CSV it
+            /// wrote, and merge their results into the out file
+            #[argh(option)]
+            retry_from: Option<PathBuf>,
+
+            /// path to a directory into which repositories should be cloned
+            #[argh(option, short = 'd')]
+            target_dir: PathBuf,
+
+            /// search with a built-in set of queries as well (secrets)
+            #[argh(option)]
+            preset: Option<Preset>,
+
+            /// filename to write CSV results into
+            #[argh(option, short = 'o')]
+            out_file: PathBuf,
+
+            /// remove repos after analysis is complete
+            #[argh(switch)]
+            rm: bool,
+
+            /// with --rm, clone every repo into a directory of its own within this one, e.g. on a
+            /// tmpfs like /dev/shm, rather than into the target directory, and remove that
+            /// directory as a whole after searching the repo
+            #[argh(option)]
+            temp_dir: Option<PathBuf>,
+
+            /// keep the clones of the run within this much disk space, e.g. "50G", and skip repos
+            /// whose clones wouldn't fit in it or on the disk, or with --rm, wait for other repos'
+            /// clones to be removed first
+            #[argh(option)]
+            max_disk: Option<ByteSize>,
+
+            /// record the disk space each repo's clone takes, in bytes, in a disk_usage column, and
+            /// log the total, and which repos take the most
+            #[argh(switch)]
+            disk_usage: bool,
+
+            /// reuse the results of repos that haven't changed since an earlier run with the same
+            /// queries and options, as recorded in a manifest in the target directory, instead of
+            /// cloning and searching them again
+            #[argh(switch)]
+            cache: bool,
+
+            /// keep repos as bare clones, as <owner>/<name>.git, and search them through temporary
+            /// worktrees that are removed after searching, so that clones take about half the disk
+            /// space between runs, and updating them is a fetch
+            #[argh(switch)]
+            bare: bool,
+
+            /// clone the forks of a repo, and the repo itself, with a bare clone of the repo as a
+            /// shared object store, kept in .objects in the target directory, so that the objects
+            /// they have in common are stored once
+            #[argh(switch)]
+            share_objects: bool,
+
+            /// once all repos are done, remove the clones in the target directory of repos that the
+            /// run didn't find, e.g. since they no longer match the search or are no longer
+            /// dependencies, to keep the clones in sync with the repos found
+            #[argh(switch)]
+            prune: bool,
+
+            /// with --prune, only list the clones that would be removed, without removing them
+            #[argh(switch)]
+            prune_dry_run: bool,
+
+            /// do not count matches inside comments (c, cpp, go, javascript, python, and rust
+            /// files)
+            #[argh(switch)]
+            skip_comments: bool,
+
+            /// what to do with matches inside string literals: count them like any other match
+            /// (default), exclude them, or count them in a separate column (count, exclude,
+            /// separate)
+            #[argh(option, default = "StringMode::Count")]
+            strings: StringMode,
+
+            /// regex engine for queries without an engine prefix (rust or pcre2)
+            #[argh(option, default = "Engine::Rust")]
+            engine: Engine,
+
+            /// skip files larger than this, e.g. "512K" or "10M"; the number of skipped files is
+            /// written into a skipped_files column
+            #[argh(option)]
+            max_file_size: Option<ByteSize>,
+
+            /// only search files with these extensions or languages, e.g. "rs,c,h" or "rust,c"
+            /// (comma-separated)
+            #[argh(option)]
+            search_ext: Option<String>,
+
+            /// only search the files of this part of each repo: READMEs and docs, tests, or the
+            /// rest of the code (all, readme, tests, or code, default: all)
+            #[argh(option, default = "Scope::All")]
+            scope: Scope,
+
+            /// also search node_modules, vendor, third_party, target, and dist directories
+            #[argh(switch)]
+            include_vendored: bool,
+
+            /// also search hidden files and directories, e.g. .github/workflows (.git is always
+            /// skipped)
+            #[argh(switch)]
+            hidden: bool,
+
+            /// clone and search this branch or tag of each repo, e.g. "v1.0", instead of its
+            /// default branch; repos without it fail
+            #[argh(option, long = "ref")]
+            git_ref: Option<String>,
+
+            /// clone this many of the latest commits of each repo (default: 1)
+            #[argh(option)]
+            clone_depth: Option<u32>,
+
+            /// clone the full history of each repo instead of its latest commit; implied by
+            /// --history
+            #[argh(switch)]
+            full_history: bool,
+
+            /// also check out the submodules of each repo, recursively: only their latest commits
+            /// ("shallow"), their full histories ("full"), or not at all (none, shallow, or full,
+            /// default: none)
+            #[argh(option, default = "SubmoduleMode::None")]
+            submodules: SubmoduleMode,
+
+            /// how to clone repos: over HTTPS, or over SSH with the user's SSH keys, e.g. where
+            /// HTTPS is blocked (https or ssh, default: https)
+            #[argh(option, default = "CloneProtocol::Https")]
+            clone_protocol: CloneProtocol,
+
+            /// clone and update repos from GitHub, Gitea, and Bitbucket with the tokens in
+            /// GITHUB_TOKEN, GITEA_TOKEN, and BITBUCKET_TOKEN, so that the private repos they have
+            /// access to can be searched, too
+            #[argh(switch)]
+            clone_with_token: bool,
+
+            /// update the clones of earlier runs without discarding the changes made to them
+            /// locally, and fail repos whose changes would be overwritten, rather than resetting
+            /// them to what was fetched
+            #[argh(switch)]
+            keep_local_changes: bool,
+
+            /// limit the transfers of all clones and updates together to this many bytes per
+            /// second, e.g. "5M", so that a run doesn't saturate a shared uplink; only those over
+            /// HTTP(S), and downloads from Software Heritage, are limited
+            #[argh(option)]
+            max_bandwidth: Option<ByteSize>,
+
+            /// the most repos to clone, update, or download at once, while the others wait their
+            /// turn, and those cloned already are searched; 16 by default
+            #[argh(option)]
+            clone_jobs: Option<usize>,
+
+            /// clone full histories and search several revisions of each repo: every tag ("tags"),
+            /// every commit on the default branch ("commits"), or a number of commits evenly spread
+            /// over its history (e.g. "10")
+            #[argh(option)]
+            history: Option<HistoryMode>,
+
+            /// also count the files containing each query, in additional "<query> (files)" columns
+            #[argh(switch)]
+            file_counts: bool,
+
+            /// record up to this many example matches per query and repo, with surrounding lines,
+            /// in a file next to the out-file
+            #[argh(option)]
+            examples: Option<usize>,
+
+            /// also write the examples as SARIF, for code scanning dashboards and editors, next to
+            /// the out-file; needs --examples
+            #[argh(switch)]
+            sarif: bool,
+
+            /// count the files and non-blank lines searched in each repo, and its top languages
+            #[argh(switch)]
+            stats: bool,
+
+            /// run the commands of this file in each clone, one `name = command` per line, and add
+            /// the numbers they print, alone or in JSON, as columns
+            #[argh(option)]
+            plugins: Option<PathBuf>,
+
+            /// run semgrep with these rules in each clone, a file or a registry name like
+            /// "p/rust", and add the number of findings of each rule as columns
+            #[argh(option)]
+            semgrep_rules: Option<String>,
+
+            /// run these linters in each clone of their language, and add the number of warnings
+            /// of each lint as columns (comma-separated: clippy, eslint)
+            #[argh(option)]
+            analyzers: Option<Analyzers>,
+
+            /// detect each repo's license from its LICENSE or COPYING files, in a
+            /// "detected_license" column
+            #[argh(switch)]
+            detect_license: bool,
+
+            /// look for the dependencies of this file, one per line, in each clone's Cargo.toml,
+            /// package.json, go.mod, and requirements.txt files, and add the versions they're
+            /// declared with as columns
+            #[argh(option)]
+            dependencies: Option<PathBuf>,
+
+            /// look at each repo's CI configuration, even if hidden, and add the CI systems it's
+            /// for, and its number of jobs, steps, and uses of each GitHub action as columns
+            #[argh(switch)]
+            ci: bool,
+
+            /// break down match counts by file language: in additional "<query> [<language>]"
+            /// columns, or in long format in a file next to the out-file (columns or long)
+            #[argh(option)]
+            by_language: Option<LanguageBreakdown>,
+
+            /// whether to follow symbolic links inside repos, skipping cycles and broken links
+            /// (skip or follow, default: skip)
+            #[argh(option, default = "SymlinkMode::Skip")]
+            symlinks: SymlinkMode,
+
+            /// how to decode files before searching them: "auto" transcodes files with a byte
+            /// order mark and decodes other non-UTF-8 files as windows-1252, "none" searches raw
+            /// bytes and is fastest, anything else names an encoding to use for every file
+            /// (default: auto)
+            #[argh(option, default = "EncodingMode::Auto")]
+            encoding: EncodingMode,
+
+            /// give up on a repo if cloning and searching it takes longer than this, e.g. "90s",
+            /// "10m", or "1h", and count it as failed
+            #[argh(option)]
+            repo_timeout: Option<HumanDuration>,
+
+            /// how often to retry a failed clone before counting the repo as failed (default: 2)
+            #[argh(option, default = "DEFAULT_CLONE_RETRIES")]
+            clone_retries: u32,
+
+            /// how long to wait before retrying a failed clone the first time, e.g. "10s", doubled
+            /// for every retry after (default: 10s)
+            #[argh(option, default = "DEFAULT_CLONE_BACKOFF")]
+            clone_backoff: HumanDuration,
+
+            /// give up on a git clone or update that takes longer than this, e.g. "30m"
+            #[argh(option)]
+            git_timeout: Option<HumanDuration>,
+
+            /// give up on a git clone that makes no progress for this long, e.g. "2m", and retry it
+            #[argh(option)]
+            stall_timeout: Option<HumanDuration>,
+
+            /// after Ctrl-C, wait this long for the repos in progress before cancelling them, e.g.
+            /// "30s" or "5m" (default: 30s); the results so far are written either way
+            #[argh(option, default = "DEFAULT_GRACE_PERIOD")]
+            grace_period: HumanDuration,
+
+            /// only record whether each query occurs in a repo (1) or not (0), which lets the
+            /// search stop as soon as every query was found
+            #[argh(switch)]
+            presence: bool,
+
+            /// search files through memory maps instead of reading them, which can be faster for
+            /// large files on local SSDs
+            #[argh(switch)]
+            mmap: bool,
+        } }
+    };
+}
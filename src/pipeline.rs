@@ -0,0 +1,114 @@
+use crate::code_queries::QueryResults;
+use crate::error::Error;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// The repos cloned, updated, or downloaded at once with --clone-jobs, by default.
+pub const CLONE_JOBS: usize = 16;
+
+/// Handles a repo, see [`handle_repo_with_timeout`](crate::handle_repo_with_timeout).
+pub type RepoJob = Pin<Box<dyn Future<Output = Result<Vec<QueryResults>>> + Send>>;
+
+/// A repo's job, with the clone paths to remove if it's cancelled.
+pub type QueuedJob = (Vec<PathBuf>, RepoJob);
+
+/// The repos being cloned, updated, or downloaded, which are bound by the network and the
+/// disks, up to a limit, while the others wait their turn. Searching is bound by the CPUs
+/// instead, and is limited by the thread pool that all repos are searched on.
+#[derive(Debug, Clone)]
+pub struct CloneQueue {
+    slots: Arc<Semaphore>,
+}
+
+impl CloneQueue {
+    pub fn new(jobs: usize) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(jobs)),
+        }
+    }
+
+    /// Wait for a repo's turn to be cloned, which lasts until the permit is dropped.
+    pub async fn enter(&self) -> Result<OwnedSemaphorePermit> {
+        Ok(self.slots.clone().acquire_owned().await?)
+    }
+}
+
+/// Run the jobs of repos as they are found, until `queued` is closed and all are done, or
+/// until `cancel` is cancelled. The results are returned in the order the jobs were queued
+/// in, with `None` for those that were cancelled.
+pub async fn collect(
+    mut queued: mpsc::UnboundedReceiver<QueuedJob>,
+    cancel: CancellationToken,
+) -> Result<Vec<Option<Result<Vec<QueryResults>>>>> {
+    let mut jobs = JoinSet::new();
+    // the index of each job's results, and its clone paths
+    let mut started = HashMap::new();
+    let mut results = Vec::new();
+    let mut open = true;
+
+    while open || !jobs.is_empty() {
+        tokio::select! {
+            job = queued.recv(), if open => match job {
+                Some((rm_paths, job)) => {
+                    let handle = jobs.spawn(job);
+                    started.insert(handle.id(), (results.len(), rm_paths));
+                    results.push(None);
+                }
+                None => open = false,
+            },
+            Some(joined) = jobs.join_next_with_id(), if !jobs.is_empty() => {
+                let (id, result) = joined?;
+                let (index, rm_paths) = &started[&id];
+                match interrupted(&result) {
+                    true => remove_clones(rm_paths).await,
+                    false => results[*index] = Some(result),
+                }
+            }
+            _ = cancel.cancelled() => break,
+        }
+    }
+    if !open && jobs.is_empty() {
+        return Ok(results);
+    }
+
+    // those that weren't started yet are cancelled, too
+    queued.close();
+    while queued.recv().await.is_some() {
+        results.push(None);
+    }
+    jobs.abort_all();
+    // wait for git to be killed, before removing the clones
+    while let Some(joined) = jobs.join_next_with_id().await {
+        match joined {
+            Ok((id, result)) if interrupted(&result) => remove_clones(&started[&id].1).await,
+            Ok((id, result)) => {
+                let (index, _) = started[&id];
+                results[index] = Some(result);
+            }
+            Err(e) if e.is_cancelled() => remove_clones(&started[&e.id()].1).await,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(results)
+}
+
+/// Whether a job stopped because the run was cancelled, which searches notice themselves, so
+/// they may stop before they are aborted.
+fn interrupted(result: &Result<Vec<QueryResults>>) -> bool {
+    matches!(result, Err(e) if matches!(e.downcast_ref(), Some(Error::Interrupted)))
+}
+
+/// Remove the clones of a cancelled job.
+async fn remove_clones(rm_paths: &[PathBuf]) {
+    for rm_path in rm_paths {
+        // the clone may not have started, or may be removed already
+        let _ = tokio::fs::remove_dir_all(rm_path).await;
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// The runtime that WASM plugins are run with.
+const WASM_RUNTIME: &str = "wasmtime";
+
+/// Where a WASM plugin finds the clone.
+const WASM_REPO_DIR: &str = "/repo";
+
+/// A command that is run in each clone, like `cargo geiger --output json`, whose numbers
+/// become columns of the results.
+#[derive(Debug, Clone)]
+pub struct Plugin {
+    pub name: String,
+    pub command: Command,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Run with `sh`.
+    Shell(String),
+    /// A WASM module run with [`WASM_RUNTIME`], which can only get at the clone, in
+    /// [`WASM_REPO_DIR`], but can write to it as well as read it, and which is given these
+    /// arguments.
+    Wasm { module: PathBuf, args: Vec<String> },
+}
+
+impl Command {
+    /// The command of a line of a plugin file in `dir`: a WASM module if it names a `.wasm`
+    /// file, relative to the plugin file, or else a shell command.
+    fn parse(command: &str, dir: &Path) -> Self {
+        let mut words = command.split_whitespace();
+        match words.next() {
+            Some(module) if module.ends_with(".wasm") => Command::Wasm {
+                module: dir.join(module),
+                args: words.map(str::to_owned).collect(),
+            },
+            _ => Command::Shell(command.to_owned()),
+        }
+    }
+}
+
+/// Read the plugins of a plugin file, one `name = command` per line.
+pub async fn from_file(path: &Path) -> Result<Vec<Plugin>> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read {path:?}"))?;
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut plugins: Vec<Plugin> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, command) = line
+            .split_once('=')
+            .map(|(name, command)| (name.trim(), command.trim()))
+            .filter(|(name, command)| !name.is_empty() && !command.is_empty())
+            .ok_or_else(|| anyhow!("Expected `name = command` on line {} of {path:?}", i + 1))?;
+        if plugins.iter().any(|plugin| plugin.name == name) {
+            return Err(anyhow!("Plugin {name} is defined twice in {path:?}"));
+        }
+        plugins.push(Plugin {
+            name: name.to_owned(),
+            command: Command::parse(command, dir),
+        });
+    }
+
+    if plugins.is_empty() {
+        return Err(anyhow!("No plugins in {path:?}"));
+    }
+    Ok(plugins)
+}
+
+/// Run the plugins in the clone in `path`, and return their columns. A plugin that fails
+/// is logged, and its columns are left empty.
+pub async fn run(plugins: &[Plugin], path: &Path, repo: &str) -> Vec<(String, String)> {
+    let mut columns = Vec::new();
+    for plugin in plugins {
+        match run_plugin(plugin, path).await {
+            Ok(plugin_columns) => columns.extend(plugin_columns),
+            Err(e) => log::warn!(repo; "Plugin {} failed for {repo}: {e:#}", plugin.name),
+        }
+    }
+    columns
+}
+
+async fn run_plugin(plugin: &Plugin, path: &Path) -> Result<Vec<(String, String)>> {
+    let mut command = match &plugin.command {
+        Command::Shell(command) => {
+            let mut shell = tokio::process::Command::new("sh");
+            shell.arg("-c").arg(command);
+            shell
+        }
+        Command::Wasm { module, args } => {
+            let mut runtime = tokio::process::Command::new(WASM_RUNTIME);
+            runtime
+                .arg("run")
+                .arg("--dir")
+                .arg(format!("{}::{WASM_REPO_DIR}", path.display()))
+                .arg(module)
+                .args(args);
+            runtime
+        }
+    };
+    let output = command
+        .current_dir(path)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .with_context(|| match &plugin.command {
+            Command::Shell(_) => "Failed to run sh".to_owned(),
+            Command::Wasm { .. } => format!("Failed to run {WASM_RUNTIME}"),
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match stderr.trim() {
+            "" => anyhow!("{}", output.status),
+            stderr => anyhow!("{}: {stderr}", output.status),
+        });
+    }
+
+    columns(&plugin.name, &String::from_utf8_lossy(&output.stdout))
+}
+
+/// The columns of a plugin's output: a number is a column named like the plugin, and each
+/// number in JSON is a column named by its path, like "geiger.packages.0.unsafety.used".
+fn columns(name: &str, output: &str) -> Result<Vec<(String, String)>> {
+    let output = output.trim();
+    if output.parse::<f64>().is_ok() {
+        return Ok(vec![(name.to_owned(), output.to_owned())]);
+    }
+
+    let value: Value =
+        serde_json::from_str(output).map_err(|_| anyhow!("Printed neither a number nor JSON"))?;
+    let mut columns = Vec::new();
+    flatten(name.to_owned(), &value, &mut columns);
+    if columns.is_empty() {
+        return Err(anyhow!("Printed JSON without numbers"));
+    }
+    Ok(columns)
+}
+
+fn flatten(column: String, value: &Value, columns: &mut Vec<(String, String)>) {
+    match value {
+        Value::Number(number) => columns.push((column, number.to_string())),
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                flatten(format!("{column}.{i}"), value, columns);
+            }
+        }
+        Value::Object(values) => {
+            for (key, value) in values {
+                flatten(format!("{column}.{key}"), value, columns);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,77 @@
+use std::str::FromStr;
+
+/// Secrets that shouldn't be committed, like keys and tokens, by their well-known formats.
+/// Those formats that plain words or placeholders can match, too, only count matches that
+/// look random enough.
+const SECRETS: &[(&str, &str)] = &[
+    (
+        "aws_access_key_id",
+        r"entropy:3.2:\b(AKIA|ASIA)[0-9A-Z]{16}\b",
+    ),
+    (
+        "aws_secret_access_key",
+        r#"entropy:4.0:(?i)aws.{0,20}secret.{0,20}['"][A-Za-z0-9/+=]{40}['"]"#,
+    ),
+    (
+        "private_key",
+        r"-----BEGIN ((RSA|DSA|EC|OPENSSH|PGP|ENCRYPTED) )?PRIVATE KEY( BLOCK)?-----",
+    ),
+    (
+        "github_token",
+        r"entropy:3.5:\bgh[pousr]_[A-Za-z0-9]{36,255}\b",
+    ),
+    ("github_pat", r"entropy:3.5:\bgithub_pat_[A-Za-z0-9_]{82}\b"),
+    ("gitlab_token", r"entropy:3.5:\bglpat-[A-Za-z0-9_-]{20}\b"),
+    ("slack_token", r"entropy:3.0:\bxox[baprs]-[A-Za-z0-9-]{10,}"),
+    (
+        "slack_webhook",
+        r"https://hooks\.slack\.com/services/T[A-Z0-9]+/B[A-Z0-9]+/[A-Za-z0-9]+",
+    ),
+    ("google_api_key", r"entropy:3.5:\bAIza[0-9A-Za-z_-]{35}\b"),
+    ("stripe_key", r"entropy:3.5:\b[sr]k_live_[0-9a-zA-Z]{24,}\b"),
+    (
+        "sendgrid_key",
+        r"entropy:3.5:\bSG\.[A-Za-z0-9_-]{22}\.[A-Za-z0-9_-]{43}\b",
+    ),
+    (
+        "jwt",
+        r"entropy:3.5:\beyJ[A-Za-z0-9_-]{10,}\.eyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}",
+    ),
+    (
+        "generic_secret",
+        r#"entropy:3.5:(?i)(api_?key|secret|token|passwd|password)["']?\s*[:=]\s*["'][A-Za-z0-9/+_=-]{16,}["']"#,
+    ),
+];
+
+/// A built-in set of queries, for common questions like whether repos contain secrets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    Secrets,
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "secrets" => Ok(Preset::Secrets),
+            _ => Err(format!("unknown preset: {s}")),
+        }
+    }
+}
+
+impl Preset {
+    /// The preset's name, which its columns start with.
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Secrets => "secrets",
+        }
+    }
+
+    /// The preset's queries, with the names of their columns.
+    pub fn queries(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Preset::Secrets => SECRETS,
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use crate::code_queries::QueryResults;
+#[cfg(feature = "tui")]
+use crate::tui::Board;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// At most this many repos get a bar of their own at a time, since more wouldn't fit on
+/// most screens.
+const MAX_REPO_BARS: usize = 8;
+
+/// A repo's bar while in a phase of unknown progress.
+const PHASE_TEMPLATE: &str = "  {spinner} {prefix} {msg}";
+/// A repo's bar while git reports its progress.
+const GIT_TEMPLATE: &str = "  {spinner} {prefix} {msg} [{bar:20}] {percent:>3}%";
+
+fn style(template: &str) -> ProgressStyle {
+    // the templates are fixed, so they are known to be valid
+    ProgressStyle::with_template(template)
+        .unwrap()
+        .progress_chars("=> ")
+}
+
+/// A bar that isn't drawn until it's added to a `MultiProgress`.
+fn hidden_bar(len: u64, template: &str) -> ProgressBar {
+    let bar = ProgressBar::with_draw_target(Some(len), ProgressDrawTarget::hidden());
+    bar.set_style(style(template));
+    bar
+}
+
+/// The phases of handling a repo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Download,
+    Update,
+    Clone,
+    Search,
+}
+
+impl Phase {
+    /// The name of the phase in logs and reports, like "clone".
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Download => "download",
+            Phase::Update => "update",
+            Phase::Clone => "clone",
+            Phase::Search => "search",
+        }
+    }
+
+    /// The phase as shown next to a repo, like "cloning".
+    fn label(self) -> &'static str {
+        match self {
+            Phase::Download => "downloading",
+            Phase::Update => "updating",
+            Phase::Clone => "cloning",
+            Phase::Search => "searching",
+        }
+    }
+}
+
+/// What happened to a repo, for those embedding octosurfer to show the progress of a run
+/// their own way, from [`Runner::events`](crate::Runner::events). Repos are named like
+/// "owner/name".
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The repo was found, and is going to be analyzed.
+    RepoQueued { repo: String },
+    /// The repo is being cloned, or updated or downloaded.
+    CloneStarted { repo: String },
+    /// The repo is being searched.
+    SearchStarted { repo: String },
+    /// The repo was searched, or its results of an earlier run were reused, with the match
+    /// counts of the queries. In history mode, there are counts for each revision searched.
+    SearchFinished {
+        repo: String,
+        counts: Vec<QueryResults>,
+    },
+    /// The repo failed in a phase, if it got to one.
+    RepoFailed {
+        repo: String,
+        phase: Option<Phase>,
+        error: String,
+    },
+}
+
+/// Progress bars for the repos of a run, drawn to stderr below the log: one for all repos,
+/// and one for each of some repos in progress.
+#[derive(Clone)]
+pub struct Progress {
+    multi: MultiProgress,
+    /// The dashboard's state, if it's drawn instead of the bars.
+    #[cfg(feature = "tui")]
+    board: Option<Board>,
+    overall: ProgressBar,
+    /// Whether the overall bar was added, which is once there are repos to count.
+    started: Arc<AtomicBool>,
+    /// The number of repos with a bar of their own.
+    repo_bars: Arc<AtomicUsize>,
+    /// Where events are sent, if anywhere.
+    events: Option<mpsc::UnboundedSender<Event>>,
+}
+
+impl Progress {
+    pub fn new(multi: MultiProgress) -> Self {
+        let overall = hidden_bar(
+            0,
+            "{elapsed_precise} [{wide_bar}] {pos}/{len} repos, ETA {eta}",
+        );
+        Self {
+            multi,
+            #[cfg(feature = "tui")]
+            board: None,
+            overall,
+            started: Arc::new(AtomicBool::new(false)),
+            repo_bars: Arc::new(AtomicUsize::new(0)),
+            events: None,
+        }
+    }
+
+    /// Send the events of the repos to a channel, too, whose receiver is returned.
+    pub fn subscribe(&mut self) -> mpsc::UnboundedReceiver<Event> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.events = Some(sender);
+        receiver
+    }
+
+    fn send(&self, event: Event) {
+        if let Some(events) = &self.events {
+            // nobody listening anymore is fine
+            let _ = events.send(event);
+        }
+    }
+
+    /// Track the repos on the dashboard, too.
+    #[cfg(feature = "tui")]
+    pub fn with_board(mut self, board: Board) -> Self {
+        self.board = Some(board);
+        self
+    }
+
+    /// New bars for another run.
+    pub fn restart(&self) -> Self {
+        let mut progress = Self::new(self.multi.clone());
+        progress.events = self.events.clone();
+        #[cfg(feature = "tui")]
+        if let Some(board) = &self.board {
+            board.restart();
+            progress.board = Some(board.clone());
+        }
+        progress
+    }
+
+    /// Tell the events that a repo is going to be analyzed.
+    pub fn queue_repo(&self, owner: &str, name: &str) {
+        self.send(Event::RepoQueued {
+            repo: format!("{owner}/{name}"),
+        });
+    }
+
+    /// Count more repos to analyze.
+    pub fn add_repos(&self, count: usize) {
+        if !self.started.swap(true, Ordering::Relaxed) {
+            self.multi.add(self.overall.clone());
+        }
+        self.overall.inc_length(count as u64);
+    }
+
+    /// The progress of a repo, which is counted as done once it's dropped.
+    pub fn repo(&self, owner: &str, name: &str) -> RepoProgress {
+        let full_name = format!("{owner}/{name}");
+        let bar = hidden_bar(100, PHASE_TEMPLATE);
+        bar.set_prefix(full_name.clone());
+        RepoProgress {
+            progress: self.clone(),
+            #[cfg(feature = "tui")]
+            board_idx: self
+                .board
+                .as_ref()
+                .map(|board| board.add_repo(full_name.clone())),
+            full_name,
+            bar,
+            shown: AtomicBool::new(false),
+            phase: Mutex::new(None),
+        }
+    }
+
+    /// Remove all bars, once all repos are done.
+    pub fn finish(&self) {
+        self.overall.finish_and_clear();
+    }
+}
+
+/// The progress of a single repo, in one of the overall bar's slots if it got one.
+pub struct RepoProgress {
+    progress: Progress,
+    /// The repo's index on the dashboard.
+    #[cfg(feature = "tui")]
+    board_idx: Option<usize>,
+    full_name: String,
+    bar: ProgressBar,
+    shown: AtomicBool,
+    phase: Mutex<Option<Phase>>,
+}
+
+impl RepoProgress {
+    /// Show the bar if there is room for it, and return whether it is shown.
+    pub fn show(&self) -> bool {
+        if self.shown.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self.progress.multi.is_hidden() {
+            return false;
+        }
+
+        let repo_bars = &self.progress.repo_bars;
+        let claimed = repo_bars
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                (n < MAX_REPO_BARS).then_some(n + 1)
+            })
+            .is_ok();
+        if claimed {
+            self.shown.store(true, Ordering::Relaxed);
+            self.progress.multi.add(self.bar.clone());
+            self.bar.enable_steady_tick(Duration::from_millis(200));
+        }
+        claimed
+    }
+
+    /// Start a phase like cloning or searching, of unknown progress.
+    pub fn set_phase(&self, phase: Phase) {
+        *self.phase.lock().unwrap_or_else(|e| e.into_inner()) = Some(phase);
+        let repo = self.full_name.clone();
+        self.progress.send(match phase {
+            Phase::Download | Phase::Update | Phase::Clone => Event::CloneStarted { repo },
+            Phase::Search => Event::SearchStarted { repo },
+        });
+        #[cfg(feature = "tui")]
+        self.on_board(|board, idx| board.set_phase(idx, phase.label(), None));
+        if self.show() {
+            self.bar.set_style(style(PHASE_TEMPLATE));
+            self.bar.set_message(phase.label());
+        }
+    }
+
+    /// The phase the repo is in, once one was started.
+    pub fn phase(&self) -> Option<Phase> {
+        *self.phase.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Report a line of git's progress output, like "Receiving objects:  45% (45/100)".
+    pub fn git_progress(&self, line: &str) {
+        let line = line.strip_prefix("remote: ").unwrap_or(line);
+        let Some((phase, rest)) = line.split_once(':') else {
+            return;
+        };
+        let Some(Ok(percent)) = rest.split_once('%').map(|(n, _)| n.trim().parse::<u64>()) else {
+            return;
+        };
+        #[cfg(feature = "tui")]
+        self.on_board(|board, idx| {
+            board.set_phase(idx, &phase.trim().to_lowercase(), Some(percent.min(100)))
+        });
+        if self.show() {
+            self.bar.set_style(style(GIT_TEMPLATE));
+            self.bar.set_message(phase.trim().to_lowercase());
+            self.bar.set_position(percent.min(100));
+        }
+    }
+}
+
+impl RepoProgress {
+    #[cfg(feature = "tui")]
+    fn on_board(&self, f: impl FnOnce(&Board, usize)) {
+        if let (Some(board), Some(idx)) = (&self.progress.board, self.board_idx) {
+            f(board, idx);
+        }
+    }
+
+    /// Record the outcome of the repo, for the dashboard and the events.
+    pub fn finish(&self, results: &anyhow::Result<Vec<QueryResults>>) {
+        #[cfg(feature = "tui")]
+        self.on_board(|board, idx| board.finish(idx, results));
+        let repo = self.full_name.clone();
+        self.progress.send(match results {
+            Ok(results) => Event::SearchFinished {
+                repo,
+                counts: results.clone(),
+            },
+            Err(error) => Event::RepoFailed {
+                repo,
+                phase: self.phase(),
+                error: format!("{error:#}"),
+            },
+        });
+    }
+}
+
+impl Drop for RepoProgress {
+    fn drop(&mut self) {
+        if self.shown.load(Ordering::Relaxed) {
+            self.bar.finish_and_clear();
+            self.progress.multi.remove(&self.bar);
+            self.progress.repo_bars.fetch_sub(1, Ordering::Relaxed);
+        }
+        self.progress.overall.inc(1);
+    }
+}
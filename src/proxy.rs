@@ -0,0 +1,133 @@
+use crate::github_cache::{ApiCache, CacheLayer};
+use anyhow::{anyhow, Context, Result};
+use http::header::USER_AGENT;
+use http::{HeaderValue, Uri};
+use hyper_rustls::HttpsConnectorBuilder;
+use hyper_util::client::legacy::connect::proxy::{SocksV5, Tunnel};
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::proxy::matcher::Matcher;
+use hyper_util::rt::TokioExecutor;
+use octocrab::service::middleware::auth_header::AuthHeaderLayer;
+use octocrab::service::middleware::base_uri::BaseUriLayer;
+use octocrab::service::middleware::extra_headers::ExtraHeadersLayer;
+use octocrab::{AuthState, Octocrab, OctocrabBuilder};
+use std::sync::Arc;
+use tower_http::follow_redirect::FollowRedirectLayer;
+use url::Url;
+
+const GITHUB_API: &str = "https://api.github.com";
+const GITHUB_UPLOADS: &str = "https://uploads.github.com";
+
+/// The variables git, reqwest, and the GitHub client all take a proxy from.
+const PROXY_VARS: [&str; 6] = [
+    "http_proxy",
+    "HTTP_PROXY",
+    "https_proxy",
+    "HTTPS_PROXY",
+    "all_proxy",
+    "ALL_PROXY",
+];
+
+/// The proxies octosurfer can go through, by scheme: HTTP proxies, and SOCKS5 proxies that
+/// resolve host names locally (socks5) or themselves (socks5h).
+fn check_scheme(proxy: &Uri) -> Result<()> {
+    match proxy.scheme_str() {
+        Some("http" | "socks5" | "socks5h") => Ok(()),
+        _ => Err(anyhow!(
+            "Unsupported proxy {proxy}, which must be an http, socks5, or socks5h URL"
+        )),
+    }
+}
+
+/// Go through `proxy` for every connection of the run, instead of the one the environment
+/// names, if any. Needs to be called first thing, before anything reads the environment
+/// variables it sets, which everything else takes the proxy from.
+pub fn configure(proxy: Option<&Url>) -> Result<()> {
+    let Some(proxy) = proxy else {
+        return Ok(());
+    };
+    check_scheme(&proxy.as_str().parse()?)?;
+    for var in PROXY_VARS {
+        std::env::set_var(var, proxy.as_str());
+    }
+    Ok(())
+}
+
+/// A client for GitHub's API authenticated by `token`, if any, which goes through the proxy that the
+/// environment names for it, if any, and answers from `cache` what hasn't changed. octocrab's
+/// own client ignores the environment.
+pub fn github_client(token: Option<String>, cache: Option<ApiCache>) -> Result<Octocrab> {
+    let api: Uri = GITHUB_API.parse()?;
+    let proxy = Matcher::from_env().intercept(&api);
+
+    let mut http = HttpConnector::new();
+    // the connections to the API are secured on top of the proxy's, if any
+    http.enforce_http(false);
+    let Some(proxy) = proxy else {
+        return match cache {
+            Some(cache) => client(http, token, api, Some(cache)),
+            None => match token {
+                Some(token) => Ok(Octocrab::builder().personal_token(token).build()?),
+                None => Ok(Octocrab::builder().build()?),
+            },
+        };
+    };
+    check_scheme(proxy.uri())?;
+    log::debug!("Calling GitHub's API through {}", proxy.uri());
+
+    match proxy.uri().scheme_str() {
+        Some("http") => {
+            let mut tunnel = Tunnel::new(proxy.uri().clone(), http);
+            if let Some(auth) = proxy.basic_auth() {
+                tunnel = tunnel.with_auth(auth.clone());
+            }
+            client(tunnel, token, api, cache)
+        }
+        scheme => {
+            let mut socks =
+                SocksV5::new(proxy.uri().clone(), http).local_dns(scheme == Some("socks5"));
+            if let Some((user, pass)) = proxy.raw_auth() {
+                socks = socks.with_auth(user.to_owned(), pass.to_owned());
+            }
+            client(socks, token, api, cache)
+        }
+    }
+}
+
+/// A client for GitHub's API that connects through `connector`, set up like octocrab's own,
+/// with the cache, if any, right in front of the connections.
+fn client<C>(
+    connector: C,
+    token: Option<String>,
+    api: Uri,
+    cache: Option<ApiCache>,
+) -> Result<Octocrab>
+where
+    C: Clone + Send + Sync + 'static,
+    hyper_rustls::HttpsConnector<C>: Connect,
+{
+    let https = HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .context("Failed to load the root certificates")?
+        .https_only()
+        .enable_http1()
+        .wrap_connector(connector);
+    let client = Client::builder(TokioExecutor::new()).build(https);
+
+    let auth = token
+        .map(|token| HeaderValue::from_str(&format!("Bearer {token}")))
+        .transpose()?;
+    let headers = vec![(USER_AGENT, HeaderValue::from_static("octocrab"))];
+    let octocrab = OctocrabBuilder::new_empty()
+        .with_service(client)
+        .with_layer(&CacheLayer(cache))
+        // renamed and transferred repos redirect to where they are now
+        .with_layer(&FollowRedirectLayer::new())
+        .with_layer(&ExtraHeadersLayer::new(Arc::new(headers)))
+        .with_layer(&BaseUriLayer::new(api.clone()))
+        .with_layer(&AuthHeaderLayer::new(auth, api, GITHUB_UPLOADS.parse()?))
+        .with_auth(AuthState::None)
+        .build()?;
+    Ok(octocrab)
+}
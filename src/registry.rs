@@ -0,0 +1,506 @@
+use crate::forge::Repo;
+use crate::RunCmd;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use url::Url;
+
+/// Package registries ask crawlers to identify themselves.
+pub const USER_AGENT: &str = concat!(
+    "octosurfer/",
+    env!("CARGO_PKG_VERSION"),
+    " (https://github.com/tronje/octosurfer)"
+);
+
+/// The package registry to take repos from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegistryKind {
+    CratesIo,
+    Npm,
+    Pypi,
+}
+
+impl FromStr for RegistryKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "crates.io" | "crates-io" => Ok(RegistryKind::CratesIo),
+            "npm" => Ok(RegistryKind::Npm),
+            "pypi" => Ok(RegistryKind::Pypi),
+            _ => Err(format!("unknown registry: {s}")),
+        }
+    }
+}
+
+/// Fail if any of the options only meant for searching forges were given, for repos from
+/// somewhere else.
+pub fn check_unsupported(argh: &RunCmd, source: &str) -> Result<()> {
+    for (flag, value) in [
+        ("--languages", &argh.languages),
+        ("--pushed", &argh.pushed),
+        ("--stars", &argh.stars),
+        ("--topics", &argh.topics),
+        ("--org", &argh.org),
+        ("--user", &argh.user),
+        ("--starred-by", &argh.starred_by),
+        ("--licenses", &argh.licenses),
+        ("--any-language", &argh.any_language),
+    ] {
+        if value.is_some() {
+            return Err(anyhow!("{flag} is not supported for {source}"));
+        }
+    }
+    Ok(())
+}
+
+/// Turn the repository URLs of packages into repos, skipping packages without a
+/// repository and repos that several packages share.
+fn to_repos<'a>(
+    urls: impl Iterator<Item = (&'a str, Option<&'a str>)>,
+    seen: &mut HashSet<Url>,
+) -> Vec<Repo> {
+    let mut repos = Vec::new();
+    for (package, url) in urls {
+        let Some(url) = url else {
+            log::debug!("Skipping {package}, which has no repository");
+            continue;
+        };
+
+        match Repo::from_url(url) {
+            Ok(repo) => {
+                if seen.insert(repo.clone_url.clone()) {
+                    repos.push(repo);
+                }
+            }
+            Err(e) => log::warn!("Skipping {package}: {e}"),
+        }
+    }
+    repos
+}
+
+#[derive(Debug, Deserialize)]
+struct CratesPage {
+    crates: Vec<Crate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Crate {
+    name: String,
+    repository: Option<String>,
+    downloads: u64,
+}
+
+/// The crates on crates.io matching some keywords, most downloaded first.
+pub struct CratesIoSearch {
+    client: reqwest::Client,
+    url: Url,
+    min_downloads: u64,
+    /// The next page to fetch, if any.
+    page: Option<usize>,
+    /// The clone URLs of all repos so far, since a repo often holds several crates.
+    seen: HashSet<Url>,
+}
+
+impl CratesIoSearch {
+    /// Crates per page, the maximum crates.io allows.
+    const PAGE_SIZE: usize = 100;
+
+    pub fn from_argh(argh: &RunCmd) -> Result<Self> {
+        check_unsupported(argh, "package registries")?;
+
+        let mut url = Url::parse("https://crates.io/api/v1/crates")?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(keywords) = &argh.keywords {
+                query.append_pair("q", &keywords.replace(',', " "));
+            }
+            if let Some(category) = &argh.category {
+                query.append_pair("category", category);
+            }
+            query
+                .append_pair("sort", "downloads")
+                .append_pair("per_page", &Self::PAGE_SIZE.to_string());
+        }
+
+        Ok(Self {
+            client: reqwest::Client::builder().user_agent(USER_AGENT).build()?,
+            url,
+            min_downloads: argh.min_downloads.unwrap_or(0),
+            page: Some(1),
+            seen: HashSet::new(),
+        })
+    }
+
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Repo>>> {
+        let Some(page) = self.page else {
+            return Ok(None);
+        };
+
+        // crates.io asks crawlers for at most one request per second
+        if page > 1 {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        let mut url = self.url.clone();
+        url.query_pairs_mut().append_pair("page", &page.to_string());
+        let results: CratesPage = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // crates are sorted by downloads, so none of the later ones have enough either
+        let enough = |krate: &&Crate| krate.downloads >= self.min_downloads;
+        let last = results.crates.len() < Self::PAGE_SIZE
+            || results.crates.last().is_some_and(|krate| !enough(&krate));
+        self.page = match last {
+            true => None,
+            false => Some(page + 1),
+        };
+
+        let crates = results.crates.iter().filter(enough);
+        let urls = crates.map(|krate| (krate.name.as_str(), krate.repository.as_deref()));
+        Ok(Some(to_repos(urls, &mut self.seen)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmPage {
+    objects: Vec<NpmResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmResult {
+    package: NpmPackage,
+    downloads: Option<NpmDownloads>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmPackage {
+    name: String,
+    #[serde(default)]
+    links: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmDownloads {
+    monthly: u64,
+}
+
+/// The packages on npm matching some keywords, most popular first.
+pub struct NpmSearch {
+    client: reqwest::Client,
+    url: Url,
+    min_downloads: u64,
+    /// The offset of the next page to fetch, if any.
+    from: Option<usize>,
+    seen: HashSet<Url>,
+}
+
+impl NpmSearch {
+    /// Packages per page, the maximum npm allows.
+    const PAGE_SIZE: usize = 250;
+
+    pub fn from_argh(argh: &RunCmd) -> Result<Self> {
+        check_unsupported(argh, "package registries")?;
+        if argh.category.is_some() {
+            return Err(anyhow!("--category is only supported for crates.io"));
+        }
+
+        let mut url = Url::parse("https://registry.npmjs.org/-/v1/search")?;
+        url.query_pairs_mut()
+            .append_pair(
+                "text",
+                &argh.keywords.clone().unwrap_or_default().replace(',', " "),
+            )
+            .append_pair("popularity", "1.0")
+            .append_pair("quality", "0.0")
+            .append_pair("maintenance", "0.0")
+            .append_pair("size", &Self::PAGE_SIZE.to_string());
+
+        Ok(Self {
+            client: reqwest::Client::builder().user_agent(USER_AGENT).build()?,
+            url,
+            min_downloads: argh.min_downloads.unwrap_or(0),
+            from: Some(0),
+            seen: HashSet::new(),
+        })
+    }
+
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Repo>>> {
+        let Some(from) = self.from else {
+            return Ok(None);
+        };
+
+        let mut url = self.url.clone();
+        url.query_pairs_mut().append_pair("from", &from.to_string());
+        let results: NpmPage = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        self.from = match results.objects.len() < Self::PAGE_SIZE {
+            true => None,
+            false => Some(from + Self::PAGE_SIZE),
+        };
+
+        // npm sorts by popularity, which isn't quite the number of downloads, so every page
+        // needs to be checked
+        let min_downloads = self.min_downloads;
+        let packages = results.objects.iter().filter(|result| {
+            min_downloads == 0
+                || result
+                    .downloads
+                    .as_ref()
+                    .is_some_and(|downloads| downloads.monthly >= min_downloads)
+        });
+        let urls = packages.map(|result| {
+            let package = &result.package;
+            (
+                package.name.as_str(),
+                package.links.get("repository").map(String::as_str),
+            )
+        });
+        Ok(Some(to_repos(urls, &mut self.seen)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateInfo {
+    #[serde(rename = "crate")]
+    krate: Crate,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmManifest {
+    repository: Option<NpmRepository>,
+}
+
+/// Either a URL, or an object with one.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum NpmRepository {
+    Url(String),
+    Object { url: String },
+}
+
+/// The repository URL of a package, as given in the registry, if it has one.
+pub async fn package_repository(
+    client: &reqwest::Client,
+    registry: RegistryKind,
+    package: &str,
+) -> Result<Option<String>> {
+    let repository = match registry {
+        RegistryKind::CratesIo => {
+            let url = format!("https://crates.io/api/v1/crates/{package}");
+            let info: CrateInfo = client
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            info.krate.repository
+        }
+        RegistryKind::Npm => {
+            // scoped packages keep their slash, encoded
+            let package = package.replace('/', "%2f");
+            let url = format!("https://registry.npmjs.org/{package}/latest");
+            let manifest: NpmManifest = client
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            manifest.repository.map(|repository| match repository {
+                NpmRepository::Url(url) | NpmRepository::Object { url } => url,
+            })
+        }
+        RegistryKind::Pypi => {
+            let url = format!("https://pypi.org/pypi/{package}/json");
+            let project: PypiProject = client
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            project.info.repository().map(ToOwned::to_owned)
+        }
+    };
+    Ok(repository)
+}
+
+/// The most downloaded packages on PyPI in the last 30 days, since PyPI has no search API.
+const TOP_PYPI_PACKAGES: &str =
+    "https://hugovk.github.io/top-pypi-packages/top-pypi-packages-30-days.min.json";
+
+/// Hosts whose URLs are taken to be repos when found among a package's URLs.
+const FORGE_HOSTS: &[&str] = &["github.com", "gitlab.com", "bitbucket.org", "codeberg.org"];
+
+#[derive(Debug, Deserialize)]
+struct TopPackages {
+    rows: Vec<TopPackage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopPackage {
+    project: String,
+    download_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct PypiProject {
+    info: PypiInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct PypiInfo {
+    name: String,
+    summary: Option<String>,
+    keywords: Option<String>,
+    home_page: Option<String>,
+    project_urls: Option<HashMap<String, String>>,
+}
+
+impl PypiInfo {
+    /// Whether any of the keywords occurs in the name, summary, or keywords.
+    fn matches(&self, keywords: &[String]) -> bool {
+        let text = [
+            Some(self.name.as_str()),
+            self.summary.as_deref(),
+            self.keywords.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+
+        keywords.is_empty() || keywords.iter().any(|keyword| text.contains(keyword))
+    }
+
+    /// The first of the project's URLs that points into a forge.
+    fn repository(&self) -> Option<&str> {
+        let project_urls = self.project_urls.iter().flat_map(|urls| urls.values());
+        project_urls
+            .chain(self.home_page.as_ref())
+            .map(String::as_str)
+            .find(|url| {
+                Url::parse(url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(|host| FORGE_HOSTS.contains(&host)))
+                    .unwrap_or(false)
+            })
+    }
+}
+
+/// The most downloaded packages on PyPI matching some keywords.
+pub struct PypiSearch {
+    client: reqwest::Client,
+    /// Lowercase keywords, any of which must occur in a package's name, summary, or
+    /// keywords.
+    keywords: Vec<String>,
+    min_downloads: u64,
+    /// The packages still to look at, most downloaded last, or `None` before fetching them.
+    remaining: Option<Vec<String>>,
+    seen: HashSet<Url>,
+}
+
+impl PypiSearch {
+    /// Packages looked up per page.
+    const PAGE_SIZE: usize = 50;
+
+    pub fn from_argh(argh: &RunCmd) -> Result<Self> {
+        check_unsupported(argh, "package registries")?;
+        if argh.category.is_some() {
+            return Err(anyhow!("--category is only supported for crates.io"));
+        }
+
+        let keywords = argh
+            .keywords
+            .iter()
+            .flat_map(|keywords| keywords.split(','))
+            .map(|keyword| keyword.trim().to_lowercase())
+            .filter(|keyword| !keyword.is_empty())
+            .collect();
+
+        Ok(Self {
+            client: reqwest::Client::builder().user_agent(USER_AGENT).build()?,
+            keywords,
+            min_downloads: argh.min_downloads.unwrap_or(0),
+            remaining: None,
+            seen: HashSet::new(),
+        })
+    }
+
+    async fn top_packages(&self) -> Result<Vec<String>> {
+        let top: TopPackages = self
+            .client
+            .get(TOP_PYPI_PACKAGES)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut packages: Vec<String> = top
+            .rows
+            .into_iter()
+            .filter(|package| package.download_count >= self.min_downloads)
+            .map(|package| package.project)
+            .collect();
+        packages.reverse();
+
+        Ok(packages)
+    }
+
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Repo>>> {
+        let remaining = match &mut self.remaining {
+            Some(remaining) => remaining,
+            None => self.remaining.insert(self.top_packages().await?),
+        };
+        if remaining.is_empty() {
+            return Ok(None);
+        }
+
+        let page = remaining.split_off(remaining.len().saturating_sub(Self::PAGE_SIZE));
+
+        let mut lookups = JoinSet::new();
+        for package in page.into_iter().rev() {
+            let client = self.client.clone();
+            lookups.spawn(async move {
+                let url = format!("https://pypi.org/pypi/{package}/json");
+                let project = async {
+                    let response = client.get(url).send().await?.error_for_status()?;
+                    response.json::<PypiProject>().await
+                };
+                (package, project.await)
+            });
+        }
+
+        let mut projects = Vec::new();
+        while let Some(lookup) = lookups.join_next().await {
+            match lookup? {
+                (_, Ok(project)) => projects.push(project.info),
+                (package, Err(e)) => log::warn!("Failed to look up {package}: {e}"),
+            }
+        }
+
+        let projects = projects.iter().filter(|info| info.matches(&self.keywords));
+        let urls = projects.map(|info| (info.name.as_str(), info.repository()));
+        Ok(Some(to_repos(urls, &mut self.seen)))
+    }
+}
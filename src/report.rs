@@ -0,0 +1,145 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Results of several runs, merged into one table.
+#[derive(Debug, Default)]
+struct Results {
+    columns: Vec<String>,
+    /// Rows by column, in the order their repos were first seen.
+    rows: Vec<HashMap<String, String>>,
+    /// The index of each row, by its repo and revision.
+    index: HashMap<(String, String), usize>,
+}
+
+impl Results {
+    /// Add the rows of a results file, replacing rows for the same repo and revision.
+    fn add(&mut self, path: &Path, text: &str) -> Result<()> {
+        let mut lines = text.lines();
+        let header: Vec<&str> = lines
+            .next()
+            .ok_or_else(|| anyhow!("{path:?} is empty"))?
+            .split(',')
+            .collect();
+        if header.first() != Some(&"repo") {
+            return Err(anyhow!("{path:?} doesn't start with a repo column"));
+        }
+        for column in header.iter() {
+            if !self.columns.iter().any(|c| c == column) {
+                self.columns.push(column.to_string());
+            }
+        }
+
+        for (lineno, line) in lines.enumerate() {
+            let values: Vec<&str> = line.split(',').collect();
+            if values.len() != header.len() {
+                return Err(anyhow!(
+                    "{path:?}, line {}: expected {} columns, got {}",
+                    lineno + 2,
+                    header.len(),
+                    values.len()
+                ));
+            }
+            let row: HashMap<String, String> = header
+                .iter()
+                .zip(values)
+                .map(|(column, value)| (column.to_string(), value.to_owned()))
+                .collect();
+
+            // runs with --history have a row per revision
+            let key = (
+                row["repo"].clone(),
+                row.get("revision").cloned().unwrap_or_default(),
+            );
+            match self.index.get(&key) {
+                Some(&idx) => self.rows[idx] = row,
+                None => {
+                    self.index.insert(key, self.rows.len());
+                    self.rows.push(row);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = self.columns.join(",");
+        csv.push('\n');
+        for row in self.rows.iter() {
+            let values: Vec<&str> = self
+                .columns
+                .iter()
+                .map(|column| row.get(column).map(String::as_str).unwrap_or(""))
+                .collect();
+            csv.push_str(&values.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Print, for every column of counts, how many rows have any and how many there are in
+    /// total.
+    fn print_summary(&self) {
+        println!("{} rows", self.rows.len());
+
+        let width = self.columns.iter().map(String::len).max().unwrap_or(0);
+        println!("{:width$}  {:>10}  {:>12}", "column", "repos", "total");
+        for column in self.columns.iter().skip(1) {
+            let counts: Option<Vec<u64>> = self
+                .rows
+                .iter()
+                .filter_map(|row| row.get(column).filter(|value| !value.is_empty()))
+                .map(|value| value.parse().ok())
+                .collect();
+            // e.g. the languages of --stats, or the dates of --history
+            let Some(counts) = counts else {
+                continue;
+            };
+            let repos = counts.iter().filter(|count| **count > 0).count();
+            let total: u64 = counts.iter().sum();
+            println!("{column:width$}  {repos:>10}  {total:>12}");
+        }
+    }
+}
+
+/// Merge the results in `retried` into those in `path`, replacing the rows for the same repo
+/// and revision, and remove `retried`.
+pub async fn merge_into(path: &Path, retried: &Path) -> Result<()> {
+    let mut results = Results::default();
+    for path in [path, retried] {
+        let text = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read {path:?}"))?;
+        results.add(path, &text)?;
+    }
+
+    tokio::fs::write(path, results.to_csv()).await?;
+    tokio::fs::remove_file(retried).await?;
+    Ok(())
+}
+
+/// Merge results files written by `run` or `analyze`, and summarize them.
+///
+/// Later files take precedence, so that the results of a retry can replace those of the run
+/// before.
+pub async fn report(paths: &[PathBuf], out_file: Option<&Path>) -> Result<()> {
+    if paths.is_empty() {
+        return Err(anyhow!("No results files given"));
+    }
+
+    let mut results = Results::default();
+    for path in paths {
+        let text = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read {path:?}"))?;
+        results.add(path, &text)?;
+    }
+
+    results.print_summary();
+
+    if let Some(path) = out_file {
+        tokio::fs::write(path, results.to_csv()).await?;
+        log::info!("Wrote merged results to {:?}", path);
+    }
+    Ok(())
+}
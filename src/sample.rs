@@ -0,0 +1,194 @@
+use crate::forge::{Repo, RepoMeta};
+use crate::size::ByteSize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// SplitMix64, a small PRNG whose output for a seed won't change with some dependency's
+/// version, so that samples can be reproduced from their seeds.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`. The modulo bias is negligible for lists of repos.
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+/// A seed for when none was given.
+pub fn random_seed() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_secs() ^ u64::from(now.subsec_nanos()) ^ u64::from(std::process::id())
+}
+
+/// Pick `n` items at random, in random order, or all of them if there are no more than
+/// `n`. The same items and seed always give the same sample.
+pub fn sample<T>(mut items: Vec<T>, n: usize, seed: u64) -> Vec<T> {
+    let mut rng = SplitMix64(seed);
+    let n = n.min(items.len());
+
+    // a partial Fisher-Yates shuffle, moving the sample to the front
+    for i in 0..n {
+        let j = i + rng.below(items.len() - i);
+        items.swap(i, j);
+    }
+    items.truncate(n);
+    items
+}
+
+/// A range of numbers, from `min` up to, but not including, `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bucket {
+    min: u64,
+    max: Option<u64>,
+}
+
+impl Bucket {
+    fn contains(&self, n: u64) -> bool {
+        n >= self.min && self.max.is_none_or(|max| n < max)
+    }
+
+    /// Parse buckets like "10-100,100-1k,1k-", with bounds parsed by `bound`.
+    fn parse_list(s: &str, bound: fn(&str) -> Result<u64, String>) -> Result<Vec<Self>, String> {
+        s.split(',')
+            .map(|bucket| {
+                let (min, max) = bucket
+                    .split_once('-')
+                    .ok_or_else(|| format!("expected a range like 10-100, got {bucket}"))?;
+                let min = match min.trim() {
+                    "" => 0,
+                    min => bound(min)?,
+                };
+                let max = match max.trim() {
+                    "" => None,
+                    max => Some(bound(max)?),
+                };
+                if max.is_some_and(|max| max <= min) {
+                    return Err(format!("empty range: {bucket}"));
+                }
+                Ok(Self { min, max })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max {
+            Some(max) => write!(f, "{}-{}", self.min, max),
+            None => write!(f, "{}-", self.min),
+        }
+    }
+}
+
+/// A count like "100", "1k", or "2m".
+fn parse_count(s: &str) -> Result<u64, String> {
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((idx, 'k' | 'K')) => (&s[..idx], 1_000),
+        Some((idx, 'm' | 'M')) => (&s[..idx], 1_000_000),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid count: {s}"))
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    s.parse::<ByteSize>().map(ByteSize::bytes)
+}
+
+/// How to divide the repos found into strata, to sample from each separately.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Strata {
+    /// One stratum per main language.
+    Language,
+    /// By the number of stars.
+    Stars(Vec<Bucket>),
+    /// By size, in bytes.
+    Size(Vec<Bucket>),
+}
+
+impl FromStr for Strata {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "language" {
+            return Ok(Strata::Language);
+        }
+        match s.split_once(':') {
+            Some(("stars", buckets)) => {
+                Ok(Strata::Stars(Bucket::parse_list(buckets, parse_count)?))
+            }
+            Some(("size", buckets)) => Ok(Strata::Size(Bucket::parse_list(buckets, parse_size)?)),
+            _ => Err(format!(
+                "expected language, stars:<ranges>, or size:<ranges>, got {s}"
+            )),
+        }
+    }
+}
+
+impl Strata {
+    /// The index and name of the stratum a repo belongs to, if any.
+    fn stratum(&self, meta: &RepoMeta) -> Option<(usize, String)> {
+        let bucket = |buckets: &[Bucket], n: u64| {
+            buckets
+                .iter()
+                .enumerate()
+                .find(|(_, bucket)| bucket.contains(n))
+                .map(|(idx, bucket)| (idx, bucket.to_string()))
+        };
+
+        match self {
+            Strata::Language => meta.language.clone().map(|language| (0, language)),
+            Strata::Stars(buckets) => {
+                let (idx, name) = bucket(buckets, meta.stars?)?;
+                Some((idx, format!("{name} stars")))
+            }
+            Strata::Size(buckets) => {
+                let (idx, name) = bucket(buckets, meta.size? * 1024)?;
+                Some((idx, format!("{name} bytes")))
+            }
+        }
+    }
+}
+
+/// Pick `n` repos at random from every stratum, skipping repos that belong to none.
+pub fn stratified_sample(repos: Vec<Repo>, n: usize, seed: u64, strata: &Strata) -> Vec<Repo> {
+    let mut groups: BTreeMap<(usize, String), Vec<Repo>> = BTreeMap::new();
+    let mut outside = 0;
+    for repo in repos {
+        match strata.stratum(&repo.meta) {
+            Some(stratum) => groups.entry(stratum).or_default().push(repo),
+            None => outside += 1,
+        }
+    }
+    if outside > 0 {
+        log::warn!("Skipping {outside} repos outside of all strata, or without the metadata");
+    }
+
+    let mut sample = Vec::new();
+    for (idx, ((_, name), group)) in groups.into_iter().enumerate() {
+        log::info!(
+            "Sampling {} of {} repos with {name}",
+            n.min(group.len()),
+            group.len()
+        );
+        // every stratum gets its own numbers, derived from the seed
+        let seed = seed.wrapping_add(idx as u64);
+        sample.extend(self::sample(group, n, seed));
+    }
+    sample
+}
@@ -0,0 +1,209 @@
+use crate::code_queries::QueryResults;
+use crate::examples::Example;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io;
+use std::path::Path;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const VERSION: &str = "2.1.0";
+
+/// A SARIF log, with a run per repo, so that each repo's findings can be uploaded on their own.
+#[derive(Debug, Serialize)]
+struct Log<'a> {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Run<'a> {
+    tool: Tool<'a>,
+    automation_details: AutomationDetails,
+    results: Vec<SarifResult<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Tool<'a> {
+    driver: Driver<'a>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver<'a> {
+    name: &'static str,
+    version: &'static str,
+    information_uri: &'static str,
+    rules: Vec<Rule<'a>>,
+}
+
+/// A query, or rather one of its columns.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule<'a> {
+    id: &'a str,
+    short_description: Message,
+}
+
+/// Tells the runs of different repos apart, e.g. as GitHub's code scanning categories.
+#[derive(Debug, Serialize)]
+struct AutomationDetails {
+    id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult<'a> {
+    rule_id: &'a str,
+    rule_index: usize,
+    level: &'static str,
+    message: Message,
+    locations: Vec<Location<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location<'a> {
+    physical_location: PhysicalLocation<'a>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation<'a> {
+    artifact_location: ArtifactLocation,
+    region: Region<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context_region: Option<Region<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ArtifactLocation {
+    /// Relative to the repo.
+    uri: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region<'a> {
+    start_line: u64,
+    end_line: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippet: Option<Snippet<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Snippet<'a> {
+    text: Cow<'a, str>,
+}
+
+impl<'a> Run<'a> {
+    fn new(result: &'a QueryResults) -> Self {
+        let mut rules: Vec<Rule> = Vec::new();
+        let mut results = Vec::new();
+        for example in result.examples.iter() {
+            let rule_index = match rules.iter().position(|rule| rule.id == example.column) {
+                Some(i) => i,
+                None => {
+                    rules.push(Rule {
+                        id: &example.column,
+                        short_description: Message {
+                            text: format!("Matches of {}", example.column),
+                        },
+                    });
+                    rules.len() - 1
+                }
+            };
+            results.push(SarifResult {
+                rule_id: &example.column,
+                rule_index,
+                level: "note",
+                message: Message {
+                    text: format!("Matched {}", example.column),
+                },
+                locations: vec![Location::new(example)],
+            });
+        }
+
+        Self {
+            tool: Tool {
+                driver: Driver {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                    information_uri: env!("CARGO_PKG_REPOSITORY"),
+                    rules,
+                },
+            },
+            automation_details: AutomationDetails {
+                id: format!("{}/{}/", result.repo_owner, result.repo_name),
+            },
+            results,
+        }
+    }
+}
+
+impl<'a> Location<'a> {
+    fn new(example: &'a Example) -> Self {
+        let line = example
+            .context
+            .iter()
+            .find(|(n, _)| *n == example.line)
+            .map(|(_, line)| Snippet {
+                text: line.as_str().into(),
+            });
+        let context = match (example.context.first(), example.context.last()) {
+            (Some((first, _)), Some((last, _))) => Some(Region {
+                start_line: *first,
+                end_line: *last,
+                snippet: Some(Snippet {
+                    text: example
+                        .context
+                        .iter()
+                        .map(|(_, line)| format!("{line}\n"))
+                        .collect::<String>()
+                        .into(),
+                }),
+            }),
+            _ => None,
+        };
+
+        Self {
+            physical_location: PhysicalLocation {
+                artifact_location: ArtifactLocation {
+                    uri: uri(&example.path),
+                },
+                region: Region {
+                    start_line: example.line,
+                    end_line: example.line,
+                    snippet: line,
+                },
+                context_region: context,
+            },
+        }
+    }
+}
+
+/// A relative URI of a path, with forward slashes on all platforms.
+fn uri(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Write the examples of all repos into a SARIF file, with one run per repo.
+pub async fn write(path: &Path, results: &[QueryResults]) -> io::Result<()> {
+    let log = Log {
+        schema: SCHEMA,
+        version: VERSION,
+        runs: results.iter().map(Run::new).collect(),
+    };
+    let json = serde_json::to_vec_pretty(&log)?;
+    tokio::fs::write(path, json).await
+}
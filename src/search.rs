@@ -1,39 +1,884 @@
-use crate::code_queries::{CodeQueries, QueryResults};
-use anyhow::Result;
+use crate::analyzers::Analyzer;
+use crate::code_queries::{CodeQueries, CodeQuery, Engine, QueryKind, QueryResults};
+use crate::duration::HumanDuration;
+use crate::error::Error;
+use crate::examples::{self, Location};
+use crate::history::HistoryMode;
+use crate::languages;
+use crate::manifests::Dependency;
+use crate::plugins::Plugin;
+use crate::size::ByteSize;
+use crate::structural::{self, RegionFinder, Regions, StructuralQueries, StructuralSearcher};
+use crate::RunCmd;
+use anyhow::{anyhow, Result};
 use grep::matcher::Matcher;
+use grep::pcre2::{RegexMatcher as Pcre2Matcher, RegexMatcherBuilder as Pcre2MatcherBuilder};
 use grep::regex::{RegexMatcher, RegexMatcherBuilder};
-use grep::searcher::{BinaryDetection, Searcher, SearcherBuilder, Sink, SinkMatch};
-use std::collections::HashMap;
+use grep::searcher::{
+    BinaryDetection, Encoding, MmapChoice, Searcher, SearcherBuilder, Sink, SinkMatch,
+};
+use memmap2::Mmap;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
 use std::io;
+use std::ops::Deref;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 use walkdir::{DirEntry, WalkDir};
 
-#[derive(Debug)]
-struct CounterSink<'a> {
-    matcher: &'a RegexMatcher,
-    matches: HashMap<String, usize>,
+/// Suffix of the column holding a query's matches inside string literals.
+pub const STRINGS_SUFFIX: &str = " (strings)";
+
+/// Suffix of the column holding the number of files containing a query.
+pub const FILES_SUFFIX: &str = " (files)";
+
+/// How to break down match counts by the language of the file they were found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LanguageBreakdown {
+    /// Additional "<query> [<language>]" columns.
+    Columns,
+    /// A separate file with one row per repo, language, and query.
+    Long,
+}
+
+impl FromStr for LanguageBreakdown {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "columns" => Ok(LanguageBreakdown::Columns),
+            "long" => Ok(LanguageBreakdown::Long),
+            _ => Err(format!("unknown language breakdown: {s}")),
+        }
+    }
+}
+
+/// What to do with symbolic links inside repos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkMode {
+    /// Neither search linked files nor descend into linked directories.
+    #[default]
+    Skip,
+    /// Search linked files and descend into linked directories, skipping cycles.
+    Follow,
+}
+
+impl FromStr for SymlinkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(SymlinkMode::Skip),
+            "follow" => Ok(SymlinkMode::Follow),
+            _ => Err(format!("unknown symlink mode: {s}")),
+        }
+    }
+}
+
+/// How to decode files before searching them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EncodingMode {
+    /// Transcode files with a UTF-16 or UTF-8 byte order mark, and decode other files that
+    /// are not valid UTF-8 as windows-1252, a superset of Latin-1.
+    #[default]
+    Auto,
+    /// Search the raw bytes of every file.
+    None,
+    /// Decode every file with this encoding.
+    Fixed(Encoding),
+}
+
+impl FromStr for EncodingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(EncodingMode::Auto),
+            "none" => Ok(EncodingMode::None),
+            label => Encoding::new(label)
+                .map(EncodingMode::Fixed)
+                .map_err(|_| format!("unknown encoding: {label}")),
+        }
+    }
+}
+
+/// The encoding of files that are neither UTF-8 nor start with a byte order mark.
+const FALLBACK_ENCODING: &str = "windows-1252";
+
+/// Directories whose files are documentation.
+const DOCS_DIRS: &[&str] = &["doc", "docs", "documentation"];
+
+/// Directories whose files are tests.
+const TEST_DIRS: &[&str] = &["test", "tests", "__tests__", "spec", "testdata"];
+
+/// The part of a repo to search, by the paths of its files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scope {
+    /// Every file.
+    #[default]
+    All,
+    /// READMEs, like README.md, and the files in documentation directories.
+    Readme,
+    /// The files in test directories, and those named like tests, e.g. foo_test.go.
+    Tests,
+    /// All files that are neither READMEs, nor docs, nor tests.
+    Code,
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Scope::All),
+            "readme" => Ok(Scope::Readme),
+            "tests" => Ok(Scope::Tests),
+            "code" => Ok(Scope::Code),
+            _ => Err(format!("unknown scope: {s}")),
+        }
+    }
+}
+
+impl Scope {
+    /// Whether the file at `path`, relative to the repo, is in the scope.
+    fn contains(self, path: &Path) -> bool {
+        match self {
+            Scope::All => true,
+            Scope::Readme => is_docs(path),
+            Scope::Tests => !is_docs(path) && is_test(path),
+            Scope::Code => !is_docs(path) && !is_test(path),
+        }
+    }
+}
+
+/// The lowercase names of a path's directories, and of its file.
+fn lowercase_parts(path: &Path) -> (Vec<String>, String) {
+    let mut parts: Vec<String> = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_lowercase())
+        .collect();
+    let file_name = parts.pop().unwrap_or_default();
+    (parts, file_name)
+}
+
+fn is_docs(path: &Path) -> bool {
+    let (dirs, file_name) = lowercase_parts(path);
+    file_name.starts_with("readme") || dirs.iter().any(|dir| DOCS_DIRS.contains(&dir.as_str()))
+}
+
+fn is_test(path: &Path) -> bool {
+    let (dirs, file_name) = lowercase_parts(path);
+    let stem = file_name.split('.').next().unwrap_or_default();
+    dirs.iter().any(|dir| TEST_DIRS.contains(&dir.as_str()))
+        || stem.starts_with("test_")
+        || stem.ends_with("_test")
+        || stem.ends_with("_spec")
+        // e.g. foo.test.js or foo.spec.ts
+        || file_name.contains(".test.")
+        || file_name.contains(".spec.")
+}
+
+/// What to do with matches inside string literals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StringMode {
+    /// Count them like any other match.
+    #[default]
+    Count,
+    /// Don't count them at all.
+    Exclude,
+    /// Count them in a separate column.
+    Separate,
+}
+
+impl FromStr for StringMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(StringMode::Count),
+            "exclude" => Ok(StringMode::Exclude),
+            "separate" => Ok(StringMode::Separate),
+            _ => Err(format!("unknown string mode: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Skip matches inside comments, for files in languages with a built-in grammar.
+    pub skip_comments: bool,
+    /// Treatment of matches inside string literals, for files in languages with a built-in
+    /// grammar.
+    pub strings: StringMode,
+    /// Skip files larger than this.
+    pub max_file_size: Option<ByteSize>,
+    /// Only search files with these (lowercase) extensions.
+    pub extensions: Option<HashSet<String>>,
+    /// Only search the files of this part of each repo.
+    pub scope: Scope,
+    /// Also search directories that usually hold vendored or generated code.
+    pub include_vendored: bool,
+    /// Also search hidden files and directories, except for `.git`.
+    pub hidden: bool,
+    /// Search these revisions of each repo instead of just the latest one.
+    pub history: Option<HistoryMode>,
+    /// Also count the files containing each query.
+    pub file_counts: bool,
+    /// Record up to this many example matches per query.
+    pub examples: usize,
+    /// Count files and lines of code per language.
+    pub stats: bool,
+    /// Break down match counts by language.
+    pub by_language: Option<LanguageBreakdown>,
+    /// Whether to follow symbolic links.
+    pub symlinks: SymlinkMode,
+    /// How to decode files.
+    pub encoding: EncodingMode,
+    /// Stop searching a repo after this long.
+    pub timeout: Option<Duration>,
+    /// Only record whether each query is found at all.
+    pub presence: bool,
+    /// Search files through memory maps instead of reading them.
+    pub mmap: bool,
+    /// Commands run in each clone, whose numbers become columns.
+    pub plugins: Vec<Plugin>,
+    /// Rules semgrep is run with in each clone.
+    pub semgrep_rules: Option<String>,
+    /// Linters run in each clone of their language.
+    pub analyzers: Vec<Analyzer>,
+    /// Detect each repo's license from its license files.
+    pub detect_license: bool,
+    /// Dependencies looked for in each repo's manifests.
+    pub dependencies: Vec<Dependency>,
+    /// Look at each repo's CI configuration.
+    pub ci: bool,
+}
+
+impl SearchOptions {
+    pub fn from_argh(argh: &RunCmd) -> Self {
+        Self {
+            skip_comments: argh.skip_comments,
+            strings: argh.strings,
+            max_file_size: argh.max_file_size,
+            extensions: argh.search_ext.as_deref().map(parse_extensions),
+            scope: argh.scope,
+            include_vendored: argh.include_vendored,
+            hidden: argh.hidden,
+            history: argh.history,
+            file_counts: argh.file_counts,
+            examples: argh.examples.unwrap_or(0),
+            stats: argh.stats,
+            by_language: argh.by_language,
+            symlinks: argh.symlinks,
+            encoding: argh.encoding.clone(),
+            timeout: argh.repo_timeout.map(HumanDuration::duration),
+            presence: argh.presence,
+            mmap: argh.mmap,
+            // read from the plugin file by the runner
+            plugins: Vec::new(),
+            semgrep_rules: argh.semgrep_rules.clone(),
+            analyzers: argh.analyzers.clone().unwrap_or_default().0,
+            detect_license: argh.detect_license,
+            // read from the dependency file by the runner
+            dependencies: Vec::new(),
+            ci: argh.ci,
+        }
+    }
+
+    /// Whether the walker should search `entry`, or descend into it.
+    pub(crate) fn include_entry(&self, entry: &DirEntry) -> bool {
+        // the repo itself may well be named e.g. "vendor" or ".github"
+        if entry.depth() == 0 {
+            return true;
+        }
+
+        if is_git_dir(entry) || (!self.hidden && is_hidden(entry)) {
+            return false;
+        }
+
+        self.include_vendored || !is_vendored(entry)
+    }
+
+    /// Whether searching can stop as soon as every query was found, i.e. nothing else needs
+    /// to look at every file.
+    fn early_exit(&self) -> bool {
+        self.presence
+            && !self.file_counts
+            && self.examples == 0
+            && !self.stats
+            && self.by_language.is_none()
+    }
+
+    fn needs_regions(&self) -> bool {
+        self.skip_comments || self.strings != StringMode::Count
+    }
 }
 
-impl Sink for CounterSink<'_> {
+/// The longest run of characters that keys and tokens consist of in `bytes`, like the token in
+/// `token = "..."`.
+fn longest_word(bytes: &[u8]) -> &[u8] {
+    bytes
+        .split(|b| !(b.is_ascii_alphanumeric() || b"+/=_-".contains(b)))
+        .max_by_key(|word| word.len())
+        .unwrap_or_default()
+}
+
+/// The Shannon entropy of `bytes`, in bits per byte, which is higher the more random they
+/// look.
+fn entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &b in bytes {
+        counts[b as usize] += 1;
+    }
+    let len = bytes.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Parse a comma-separated list of extensions and language names into a set of extensions.
+fn parse_extensions(s: &str) -> HashSet<String> {
+    let mut extensions = HashSet::new();
+    for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        match languages::extensions(item) {
+            Some(exts) => extensions.extend(exts.iter().map(|ext| ext.to_string())),
+            None => {
+                let ext = item.strip_prefix('.').unwrap_or(item);
+                extensions.insert(ext.to_lowercase());
+            }
+        }
+    }
+    extensions
+}
+
+/// The regex queries of one engine.
+struct MatcherGroup<M> {
+    /// Matches any of the queries, to find matching lines quickly.
+    combined: M,
+    /// One matcher per query, to attribute the matches of a line, with its minimum entropy.
+    queries: Vec<(String, M, Option<f64>)>,
+}
+
+impl MatcherGroup<RegexMatcher> {
+    fn rust(queries: &[&CodeQuery]) -> Result<Self> {
+        let builder = matcher_builder();
+        let patterns: Vec<&str> = queries.iter().map(|q| q.pattern.as_str()).collect();
+
+        let mut per_query = Vec::with_capacity(queries.len());
+        for query in queries {
+            let matcher = builder.build(&query.pattern)?;
+            per_query.push((query.name.clone(), matcher, query.min_entropy));
+        }
+
+        Ok(Self {
+            combined: builder.build_literals(&patterns)?,
+            queries: per_query,
+        })
+    }
+}
+
+impl MatcherGroup<Pcre2Matcher> {
+    fn pcre2(queries: &[&CodeQuery]) -> Result<Self> {
+        let builder = pcre2_matcher_builder();
+        let patterns: Vec<&str> = queries.iter().map(|q| q.pattern.as_str()).collect();
+
+        let mut per_query = Vec::with_capacity(queries.len());
+        for query in queries {
+            let matcher = builder.build(&query.pattern)?;
+            per_query.push((query.name.clone(), matcher, query.min_entropy));
+        }
+
+        Ok(Self {
+            combined: builder.build_many(&patterns)?,
+            queries: per_query,
+        })
+    }
+}
+
+struct CounterSink<'a, M> {
+    group: &'a MatcherGroup<M>,
+    options: &'a SearchOptions,
+    presence: Option<&'a Presence>,
+    matches: &'a mut HashMap<String, usize>,
+    /// Comments and string literals of the current file.
+    regions: &'a Regions,
+    /// The current file.
+    path: &'a Path,
+    examples: &'a mut HashMap<String, Vec<Location>>,
+}
+
+impl<M: Matcher> Sink for CounterSink<'_, M> {
     type Error = io::Error;
 
     fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch) -> Result<bool, Self::Error> {
-        let mut matches = Vec::new();
-        self.matcher.find_iter(mat.bytes(), |m| {
-            matches.push(m);
-            true
-        })?;
+        let line_offset = mat.absolute_byte_offset() as usize;
+
+        for (name, matcher, min_entropy) in self.group.queries.iter() {
+            let mut starts = Vec::new();
+            matcher
+                .find_iter(mat.bytes(), |m| {
+                    let random =
+                        min_entropy.is_none_or(|min| entropy(longest_word(&mat.bytes()[m])) >= min);
+                    if random {
+                        starts.push(m.start());
+                    }
+                    true
+                })
+                .map_err(|e| io::Error::other(e.to_string()))?;
+
+            for start in starts {
+                let offset = line_offset + start;
+                if self.options.skip_comments
+                    && structural::in_ranges(&self.regions.comments, offset)
+                {
+                    continue;
+                }
+
+                let mut key = name.clone();
+
+                if self.options.strings != StringMode::Count
+                    && structural::in_ranges(&self.regions.strings, offset)
+                {
+                    match self.options.strings {
+                        StringMode::Exclude => continue,
+                        _ => key.push_str(STRINGS_SUFFIX),
+                    }
+                }
+
+                if let Some(line) = mat.line_number() {
+                    let examples = self.examples.entry(key.clone()).or_default();
+                    // one example per line is enough
+                    let same_line = examples
+                        .last()
+                        .is_some_and(|(path, l)| *l == line && path == self.path);
+                    if examples.len() < self.options.examples && !same_line {
+                        examples.push((self.path.to_owned(), line));
+                    }
+                }
+
+                if let Some(presence) = self.presence {
+                    presence.mark(&key);
+                }
+
+                let count = self.matches.entry(key).or_insert(0);
+                *count += 1;
+            }
+        }
+
+        // no need to look any further once this group's queries were all found
+        Ok(!self
+            .presence
+            .is_some_and(|presence| presence.group_found(self.group)))
+    }
+}
+
+/// The queries found anywhere in a repo so far, shared by the threads searching it, to stop
+/// searching early in presence-only mode.
+struct Presence {
+    found: HashMap<String, AtomicBool>,
+    missing: AtomicUsize,
+    strings: StringMode,
+}
+
+impl Presence {
+    fn new(queries: &CodeQueries, options: &SearchOptions) -> Self {
+        let mut found = HashMap::new();
+        for query in queries.iter() {
+            found.insert(query.name.clone(), AtomicBool::new(false));
+            if options.strings == StringMode::Separate && matches!(query.kind, QueryKind::Regex(_))
+            {
+                found.insert(
+                    format!("{}{STRINGS_SUFFIX}", query.name),
+                    AtomicBool::new(false),
+                );
+            }
+        }
+
+        Self {
+            missing: AtomicUsize::new(found.len()),
+            found,
+            strings: options.strings,
+        }
+    }
+
+    fn mark(&self, key: &str) {
+        if let Some(found) = self.found.get(key) {
+            if !found.swap(true, Ordering::Relaxed) {
+                self.missing.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn is_found(&self, key: &str) -> bool {
+        self.found
+            .get(key)
+            .is_none_or(|found| found.load(Ordering::Relaxed))
+    }
+
+    /// Whether every query of a group was found, in and outside of strings.
+    fn group_found<M>(&self, group: &MatcherGroup<M>) -> bool {
+        group.queries.iter().all(|(name, ..)| {
+            self.is_found(name)
+                && (self.strings != StringMode::Separate
+                    || self.is_found(&format!("{name}{STRINGS_SUFFIX}")))
+        })
+    }
+
+    fn all_found(&self) -> bool {
+        self.missing.load(Ordering::Relaxed) == 0
+    }
+}
+
+/// All queries, compiled once per repo and shared by the threads searching it.
+struct Matchers {
+    rust: Option<MatcherGroup<RegexMatcher>>,
+    pcre2: Option<MatcherGroup<Pcre2Matcher>>,
+    structural: StructuralQueries,
+}
+
+impl Matchers {
+    fn compile(queries: &CodeQueries) -> Result<Self> {
+        let rust_queries = queries.regex_queries(Engine::Rust);
+        let rust = match rust_queries.is_empty() {
+            true => None,
+            false => Some(MatcherGroup::rust(&rust_queries)?),
+        };
+
+        let pcre2_queries = queries.regex_queries(Engine::Pcre2);
+        let pcre2 = match pcre2_queries.is_empty() {
+            true => None,
+            false => Some(MatcherGroup::pcre2(&pcre2_queries)?),
+        };
+
+        Ok(Self {
+            rust,
+            pcre2,
+            structural: StructuralQueries::compile(queries)?,
+        })
+    }
+}
+
+/// Number of languages listed in the languages column.
+const TOP_LANGUAGES: usize = 3;
+
+/// Files and non-blank lines of one language.
+#[derive(Debug, Default, Clone, Copy)]
+struct LanguageStats {
+    files: usize,
+    lines: usize,
+}
+
+/// Everything found in (some of) the files of a repo.
+#[derive(Default)]
+struct Findings {
+    matches: HashMap<String, usize>,
+    examples: HashMap<String, Vec<Location>>,
+    /// Keyed by language, or "other".
+    stats: HashMap<&'static str, LanguageStats>,
+    /// Matches keyed by language, or "other".
+    by_language: HashMap<&'static str, HashMap<String, usize>>,
+}
 
-        for m in matches {
-            let s = std::str::from_utf8(&mat.bytes()[m.start()..m.end()]).unwrap();
-            let count = self.matches.entry(s.to_owned()).or_insert(0);
-            *count += 1;
+impl Findings {
+    fn merge(mut self, other: Findings) -> Self {
+        for (key, count) in other.matches {
+            *self.matches.entry(key).or_insert(0) += count;
         }
 
-        Ok(true)
+        for (key, locations) in other.examples {
+            self.examples.entry(key).or_default().extend(locations);
+        }
+
+        for (language, matches) in other.by_language {
+            let entry = self.by_language.entry(language).or_default();
+            for (key, count) in matches {
+                *entry.entry(key).or_insert(0) += count;
+            }
+        }
+
+        for (language, stats) in other.stats {
+            let entry = self.stats.entry(language).or_default();
+            entry.files += stats.files;
+            entry.lines += stats.lines;
+        }
+
+        self
+    }
+
+    /// The files, lines, and languages columns.
+    fn stats_columns(&self) -> Vec<(String, String)> {
+        let files: usize = self.stats.values().map(|s| s.files).sum();
+        let lines: usize = self.stats.values().map(|s| s.lines).sum();
+
+        let mut by_lines: Vec<(&str, usize)> = self
+            .stats
+            .iter()
+            .filter(|(language, _)| **language != "other")
+            .map(|(language, stats)| (*language, stats.lines))
+            .collect();
+        by_lines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        let languages: Vec<String> = by_lines
+            .iter()
+            .take(TOP_LANGUAGES)
+            .map(|(language, lines)| format!("{language}:{lines}"))
+            .collect();
+
+        vec![
+            ("files".to_owned(), files.to_string()),
+            ("lines".to_owned(), lines.to_string()),
+            ("languages".to_owned(), languages.join(" ")),
+        ]
+    }
+}
+
+/// The state of a single thread searching files of a repo.
+struct Worker<'a> {
+    matchers: &'a Matchers,
+    options: &'a SearchOptions,
+    presence: Option<&'a Presence>,
+    searcher: Searcher,
+    /// Decodes files that are not UTF-8, if the encoding is detected automatically.
+    fallback: Searcher,
+    structural: StructuralSearcher,
+    region_finder: RegionFinder,
+    regions: Regions,
+    findings: Findings,
+    /// The matches of the file currently being searched.
+    file_matches: HashMap<String, usize>,
+}
+
+impl<'a> Worker<'a> {
+    fn new(
+        matchers: &'a Matchers,
+        options: &'a SearchOptions,
+        presence: Option<&'a Presence>,
+    ) -> Self {
+        Self {
+            matchers,
+            options,
+            presence,
+            searcher: searcher_builder()
+                .line_number(options.examples > 0)
+                .memory_map(mmap_choice(options))
+                .bom_sniffing(options.encoding != EncodingMode::None)
+                .encoding(match &options.encoding {
+                    EncodingMode::Fixed(encoding) => Some(encoding.clone()),
+                    _ => None,
+                })
+                .build(),
+            fallback: searcher_builder()
+                .line_number(options.examples > 0)
+                .memory_map(mmap_choice(options))
+                .encoding(Encoding::new(FALLBACK_ENCODING).ok())
+                .build(),
+            structural: StructuralSearcher::new(),
+            region_finder: RegionFinder::new(),
+            regions: Regions::default(),
+            findings: Findings::default(),
+            file_matches: HashMap::new(),
+        }
+    }
+
+    /// Count the file and its non-blank lines towards its language.
+    fn count_lines(&mut self, path: &Path) -> Result<()> {
+        let bytes = std::fs::read(path)?;
+
+        // like the searcher, skip binary files
+        if bytes.contains(&0) {
+            return Ok(());
+        }
+
+        let lines = bytes
+            .split(|b| *b == b'\n')
+            .filter(|line| line.iter().any(|b| !b.is_ascii_whitespace()))
+            .count();
+
+        let language = languages::language(path).unwrap_or("other");
+        let stats = self.findings.stats.entry(language).or_default();
+        stats.files += 1;
+        stats.lines += lines;
+
+        Ok(())
+    }
+
+    fn search(&mut self, path: &Path) -> Result<()> {
+        self.file_matches.clear();
+
+        if self.options.stats {
+            self.count_lines(path)?;
+        }
+
+        if self.matchers.rust.is_some() || self.matchers.pcre2.is_some() {
+            // to detect the encoding, the whole file is needed anyway
+            let contents = match self.options.encoding {
+                EncodingMode::Auto => Some(Contents::open(path, self.options.mmap)?),
+                _ => None,
+            };
+            let fallback = contents.as_deref().is_some_and(needs_fallback);
+            if fallback {
+                log::trace!("Decoding {:?} as {FALLBACK_ENCODING}", path);
+            }
+
+            // byte offsets of comments and strings don't survive transcoding
+            let transcoded = match &self.options.encoding {
+                EncodingMode::Auto => contents.as_deref().is_some_and(has_bom) || fallback,
+                EncodingMode::None => false,
+                EncodingMode::Fixed(encoding) => {
+                    Encoding::new("utf-8").ok().as_ref() != Some(encoding)
+                }
+            };
+
+            self.regions.clear();
+            if self.options.needs_regions() && !transcoded {
+                match self.region_finder.regions(path) {
+                    Ok(Some(found)) => self.regions = found,
+                    Ok(None) => {}
+                    Err(e) => log::debug!("Failed to find comments and strings in {:?}: {e}", path),
+                }
+            }
+
+            if let Some(group) = &self.matchers.rust {
+                self.search_with(group, path, contents.as_deref(), fallback)?;
+            }
+
+            if let Some(group) = &self.matchers.pcre2 {
+                self.search_with(group, path, contents.as_deref(), fallback)?;
+            }
+        }
+
+        let all_found = self.presence.is_some_and(Presence::all_found);
+        if !self.matchers.structural.is_empty() && !all_found {
+            if let Err(e) =
+                self.structural
+                    .search_file(&self.matchers.structural, path, &mut self.file_matches)
+            {
+                log::debug!("Skipping {:?} for structural queries: {e}", path);
+            }
+        }
+
+        let mut maps = vec![&mut self.findings.matches];
+        if self.options.by_language.is_some() && !self.file_matches.is_empty() {
+            let language = languages::language(path).unwrap_or("other");
+            maps.push(self.findings.by_language.entry(language).or_default());
+        }
+
+        for (key, count) in self.file_matches.drain() {
+            for matches in maps.iter_mut() {
+                if self.options.file_counts {
+                    *matches.entry(format!("{key}{FILES_SUFFIX}")).or_insert(0) += 1;
+                }
+                *matches.entry(key.clone()).or_insert(0) += count;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Search a file with one group of matchers, reading it from disk unless its `contents`
+    /// were read already.
+    fn search_with<M: Matcher>(
+        &mut self,
+        group: &MatcherGroup<M>,
+        path: &Path,
+        contents: Option<&[u8]>,
+        fallback: bool,
+    ) -> io::Result<()> {
+        if self
+            .presence
+            .is_some_and(|presence| presence.group_found(group))
+        {
+            return Ok(());
+        }
+
+        let mut sink = CounterSink {
+            group,
+            options: self.options,
+            presence: self.presence,
+            matches: &mut self.file_matches,
+            regions: &self.regions,
+            path,
+            examples: &mut self.findings.examples,
+        };
+
+        let searcher = if fallback {
+            &mut self.fallback
+        } else {
+            &mut self.searcher
+        };
+
+        match contents {
+            Some(contents) => searcher.search_slice(&group.combined, contents, &mut sink),
+            None => searcher.search_path(&group.combined, path, &mut sink),
+        }
+    }
+}
+
+/// Memory maps are only safe as long as nothing modifies the mapped files, which is the case
+/// for repos that are cloned and then searched.
+fn mmap_choice(options: &SearchOptions) -> MmapChoice {
+    match options.mmap {
+        // SAFETY: see above
+        true => unsafe { MmapChoice::auto() },
+        false => MmapChoice::never(),
+    }
+}
+
+/// The contents of a file, either read into memory or memory-mapped.
+enum Contents {
+    Read(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Contents {
+    /// Map the file if `mmap` is set, falling back to reading it if that fails, e.g. for
+    /// empty files on some platforms.
+    fn open(path: &Path, mmap: bool) -> io::Result<Self> {
+        if mmap {
+            let file = File::open(path)?;
+            // SAFETY: see `mmap_choice`
+            if let Ok(map) = unsafe { Mmap::map(&file) } {
+                return Ok(Contents::Mapped(map));
+            }
+        }
+
+        std::fs::read(path).map(Contents::Read)
+    }
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Contents::Read(bytes) => bytes,
+            Contents::Mapped(map) => map,
+        }
     }
 }
 
+/// Whether a file starts with a UTF-8 or UTF-16 byte order mark.
+fn has_bom(contents: &[u8]) -> bool {
+    contents.starts_with(b"\xEF\xBB\xBF")
+        || contents.starts_with(b"\xFF\xFE")
+        || contents.starts_with(b"\xFE\xFF")
+}
+
+/// Whether a file needs to be decoded with the fallback encoding, i.e. is neither UTF-8,
+/// nor starts with a byte order mark, nor is binary.
+fn needs_fallback(contents: &[u8]) -> bool {
+    !has_bom(contents) && !contents.contains(&0) && std::str::from_utf8(contents).is_err()
+}
+
 fn is_hidden(entry: &DirEntry) -> bool {
     entry
         .file_name()
@@ -42,45 +887,257 @@ fn is_hidden(entry: &DirEntry) -> bool {
         .unwrap_or(false)
 }
 
-fn not_hidden(entry: &DirEntry) -> bool {
-    !is_hidden(entry)
+/// Whether a walker error stems from a link to a file that doesn't exist.
+fn is_broken_link(e: &walkdir::Error) -> bool {
+    e.io_error()
+        .is_some_and(|e| e.kind() == io::ErrorKind::NotFound)
+        && e.path().is_some_and(|path| path.is_symlink())
+}
+
+fn is_git_dir(entry: &DirEntry) -> bool {
+    entry.file_name() == ".git"
+}
+
+/// Directories that usually hold code the repo's authors didn't write.
+const VENDORED_DIRS: &[&str] = &["node_modules", "vendor", "third_party", "target", "dist"];
+
+fn is_vendored(entry: &DirEntry) -> bool {
+    entry.file_type().is_dir()
+        && entry
+            .file_name()
+            .to_str()
+            .map(|s| VENDORED_DIRS.contains(&s))
+            .unwrap_or(false)
+}
+
+/// The matcher configuration used for every search.
+pub fn matcher_builder() -> RegexMatcherBuilder {
+    let mut builder = RegexMatcherBuilder::new();
+    builder.word(true);
+    builder
+}
+
+/// The matcher configuration used for queries using the PCRE2 engine.
+pub fn pcre2_matcher_builder() -> Pcre2MatcherBuilder {
+    let mut builder = Pcre2MatcherBuilder::new();
+    builder.word(true).utf(true).jit_if_available(true);
+    builder
+}
+
+/// The searcher configuration used for every search.
+pub fn searcher_builder() -> SearcherBuilder {
+    let mut builder = SearcherBuilder::new();
+    builder
+        .line_number(false)
+        .multi_line(false)
+        .binary_detection(BinaryDetection::quit(b'\x00'));
+    builder
 }
 
+/// Entries that were not searched, by reason.
+#[derive(Debug, Default)]
+struct Skipped {
+    /// Files larger than the maximum file size.
+    large: usize,
+    /// Symbolic links, if they are not followed.
+    symlinks: usize,
+    /// Symbolic links that form a cycle or point nowhere, if they are followed.
+    broken_links: usize,
+}
+
+/// Search all files of a repo, in parallel on rayon's thread pool.
+///
+/// Returns the findings, and the number of entries that were skipped.
+fn search_files(
+    path: &Path,
+    queries: &CodeQueries,
+    options: &SearchOptions,
+    cancel: &CancellationToken,
+) -> Result<(Findings, Skipped)> {
+    let matchers = Matchers::compile(queries)?;
+
+    // the search runs on blocking threads, which don't notice when the repo times out or
+    // the run is cancelled
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let check_deadline = || match deadline {
+        _ if cancel.is_cancelled() => Err(Error::Interrupted.into()),
+        Some(deadline) if Instant::now() > deadline => Err(anyhow!("Search timed out")),
+        _ => Ok(()),
+    };
+
+    let presence = options
+        .early_exit()
+        .then(|| Presence::new(queries, options));
+
+    let mut files = Vec::new();
+    let mut skipped = Skipped::default();
+    let walker = WalkDir::new(path)
+        .follow_links(options.symlinks == SymlinkMode::Follow)
+        .into_iter();
+    for result in walker.filter_entry(|entry| options.include_entry(entry)) {
+        check_deadline()?;
+
+        let dir_entry = match result {
+            Ok(dir_entry) => dir_entry,
+            // only links can fail like this, since the walker only reports the errors of
+            // links it follows
+            Err(e) if e.loop_ancestor().is_some() || is_broken_link(&e) => {
+                log::trace!("Skipping link {:?}: {}", e.path(), e);
+                skipped.broken_links += 1;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if dir_entry.path_is_symlink() && options.symlinks == SymlinkMode::Skip {
+            log::trace!("Skipping link {:?}", dir_entry.path());
+            skipped.symlinks += 1;
+            continue;
+        }
+
+        if !dir_entry.file_type().is_file() {
+            continue;
+        }
+
+        if let Some(extensions) = &options.extensions {
+            match languages::extension(dir_entry.path()) {
+                Some(ext) if extensions.contains(&ext) => {}
+                _ => continue,
+            }
+        }
+
+        let relative = dir_entry
+            .path()
+            .strip_prefix(path)
+            .unwrap_or(dir_entry.path());
+        if !options.scope.contains(relative) {
+            continue;
+        }
+
+        if let Some(max) = options.max_file_size {
+            if dir_entry.metadata()?.len() > max.bytes() {
+                log::trace!("Skipping large file {:?}", dir_entry.path());
+                skipped.large += 1;
+                continue;
+            }
+        }
+
+        files.push(dir_entry.into_path());
+    }
+
+    let findings = files
+        .par_iter()
+        .try_fold(
+            || Worker::new(&matchers, options, presence.as_ref()),
+            |mut worker, file| {
+                check_deadline()?;
+                if presence.as_ref().is_some_and(Presence::all_found) {
+                    return Ok(worker);
+                }
+                worker.search(file)?;
+                Ok::<_, anyhow::Error>(worker)
+            },
+        )
+        .map(|worker| worker.map(|w| w.findings))
+        .try_reduce(Findings::default, |merged, findings| {
+            Ok(merged.merge(findings))
+        })?;
+
+    Ok((findings, skipped))
+}
+
+/// Search the files of the repo in `path` with all `queries`, and count their matches. Once
+/// `cancel` is cancelled, the search stops soon, and returns [`Error::Interrupted`].
 pub async fn search_repo(
     path: &Path,
     repo_owner: String,
     repo_name: String,
     queries: &CodeQueries,
+    options: &SearchOptions,
+    cancel: &CancellationToken,
+) -> Result<QueryResults, Error> {
+    let repo = format!("{repo_owner}/{repo_name}");
+    count_matches(path, repo_owner, repo_name, queries, options, cancel)
+        .await
+        .map_err(|error| match cancel.is_cancelled() {
+            true => Error::Interrupted,
+            false => Error::Search { repo, error },
+        })
+}
+
+async fn count_matches(
+    path: &Path,
+    repo_owner: String,
+    repo_name: String,
+    queries: &CodeQueries,
+    options: &SearchOptions,
+    cancel: &CancellationToken,
 ) -> Result<QueryResults> {
-    let matcher = RegexMatcherBuilder::new()
-        .word(true)
-        .build_literals(queries.as_slice())?;
-    let mut searcher = SearcherBuilder::new()
-        .line_number(false)
-        .multi_line(false)
-        .binary_detection(BinaryDetection::quit(b'\x00'))
-        .build();
+    let (mut findings, examples, skipped) = tokio::task::spawn_blocking({
+        let path = path.to_owned();
+        let queries = queries.clone();
+        let options = options.clone();
+        let cancel = cancel.clone();
+        move || {
+            let (mut findings, skipped) = search_files(&path, &queries, &options, &cancel)?;
+            let examples = match options.examples {
+                0 => Vec::new(),
+                limit => examples::collect(&path, std::mem::take(&mut findings.examples), limit),
+            };
+            Ok::<_, anyhow::Error>((findings, examples, skipped))
+        }
+    })
+    .await??;
 
-    let mut sink = CounterSink {
-        matcher: &matcher,
-        matches: HashMap::new(),
-    };
+    if options.presence {
+        let by_language = findings
+            .by_language
+            .values_mut()
+            .flat_map(HashMap::values_mut);
+        for count in findings.matches.values_mut().chain(by_language) {
+            *count = 1;
+        }
+    }
 
-    let walker = WalkDir::new(path).into_iter();
-    for result in walker.filter_entry(not_hidden) {
-        let dir_entry = result?;
-        if !dir_entry.file_type().is_file() {
-            continue;
+    let mut extra = Vec::new();
+    if options.stats {
+        extra.extend(findings.stats_columns());
+    }
+
+    if options.max_file_size.is_some() {
+        if skipped.large > 0 {
+            log::info!(
+                "Skipped {} large files in {repo_owner}/{repo_name}",
+                skipped.large
+            );
         }
+        extra.push(("skipped_files".to_owned(), skipped.large.to_string()));
+    }
 
-        tokio::task::yield_now().await;
-        searcher.search_path(&matcher, dir_entry.path(), &mut sink)?;
+    if skipped.symlinks > 0 {
+        log::info!(
+            "Skipped {} symlinks in {repo_owner}/{repo_name}",
+            skipped.symlinks
+        );
+    }
+    if skipped.broken_links > 0 {
+        log::info!(
+            "Skipped {} cyclic or broken symlinks in {repo_owner}/{repo_name}",
+            skipped.broken_links
+        );
     }
 
     let results = QueryResults {
         repo_name,
         repo_owner,
-        inner: sink.matches,
+        inner: findings.matches,
+        extra,
+        examples,
+        by_language: findings
+            .by_language
+            .into_iter()
+            .map(|(language, matches)| (language.to_owned(), matches))
+            .collect(),
     };
 
     Ok(results)
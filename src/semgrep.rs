@@ -0,0 +1,56 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::process::Command;
+
+/// Prefix of the columns with the findings of each rule.
+const COLUMN_PREFIX: &str = "semgrep: ";
+
+/// The parts of semgrep's JSON output that are counted.
+#[derive(Debug, Deserialize)]
+struct Output {
+    results: Vec<Finding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Finding {
+    check_id: String,
+}
+
+/// Run semgrep with `rules` in the clone in `path`, and return a column with the number of
+/// findings, and one with those of each rule that found anything.
+pub async fn run(rules: &str, path: &Path) -> Result<Vec<(String, String)>> {
+    let output = Command::new("semgrep")
+        .args(["scan", "--json", "--quiet", "--metrics", "off"])
+        .args(["--disable-version-check", "--config", rules, "."])
+        .current_dir(path)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("Failed to run semgrep")?;
+    // semgrep exits with 1 on findings only with --error, and with more on errors
+    if !output.status.success() {
+        return Err(anyhow!(
+            "semgrep failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let output: Output =
+        serde_json::from_slice(&output.stdout).context("Failed to parse the output of semgrep")?;
+    let mut counts = BTreeMap::new();
+    for finding in output.results.iter() {
+        *counts.entry(finding.check_id.as_str()).or_insert(0) += 1;
+    }
+
+    let mut columns = vec![(
+        "semgrep_findings".to_owned(),
+        output.results.len().to_string(),
+    )];
+    for (rule, count) in counts {
+        columns.push((format!("{COLUMN_PREFIX}{rule}"), count.to_string()));
+    }
+    Ok(columns)
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A number of bytes, parsed from strings like `512`, `100K`, `10M`, or `2G`.
+///
+/// Suffixes are binary, i.e. `1K` is 1024 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    pub fn bytes(self) -> u64 {
+        self.0
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (digits, multiplier) = match s.char_indices().last() {
+            Some((idx, 'k' | 'K')) => (&s[..idx], 1 << 10),
+            Some((idx, 'm' | 'M')) => (&s[..idx], 1 << 20),
+            Some((idx, 'g' | 'G')) => (&s[..idx], 1 << 30),
+            Some((idx, 't' | 'T')) => (&s[..idx], 1 << 40),
+            _ => (s, 1),
+        };
+
+        let n: u64 = digits
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid size: {s}"))?;
+
+        n.checked_mul(multiplier)
+            .map(ByteSize)
+            .ok_or_else(|| format!("size too large: {s}"))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+
+        if self.0 < 1024 {
+            return write!(f, "{}B", self.0);
+        }
+
+        let mut value = self.0 as f64;
+        let mut unit = "B";
+        for next in UNITS {
+            if value < 1024.0 {
+                break;
+            }
+            value /= 1024.0;
+            unit = next;
+        }
+
+        write!(f, "{value:.1}{unit}")
+    }
+}
@@ -0,0 +1,246 @@
+use crate::code_queries::{CodeQueries, QueryKind};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Language, Parser, Query, QueryCursor};
+
+/// Languages for which a tree-sitter grammar is built in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lang {
+    C,
+    Cpp,
+    Go,
+    JavaScript,
+    Python,
+    Rust,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 6] = [
+        Lang::C,
+        Lang::Cpp,
+        Lang::Go,
+        Lang::JavaScript,
+        Lang::Python,
+        Lang::Rust,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lang::C => "c",
+            Lang::Cpp => "cpp",
+            Lang::Go => "go",
+            Lang::JavaScript => "javascript",
+            Lang::Python => "python",
+            Lang::Rust => "rust",
+        }
+    }
+
+    fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "c" | "h" => Some(Lang::C),
+            "cc" | "cpp" | "cxx" | "c++" | "hh" | "hpp" | "hxx" => Some(Lang::Cpp),
+            "go" => Some(Lang::Go),
+            "js" | "mjs" | "cjs" | "jsx" => Some(Lang::JavaScript),
+            "py" | "pyi" => Some(Lang::Python),
+            "rs" => Some(Lang::Rust),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_extension)
+    }
+
+    pub fn language(self) -> Language {
+        match self {
+            Lang::C => tree_sitter_c::LANGUAGE.into(),
+            Lang::Cpp => tree_sitter_cpp::LANGUAGE.into(),
+            Lang::Go => tree_sitter_go::LANGUAGE.into(),
+            Lang::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Lang::Python => tree_sitter_python::LANGUAGE.into(),
+            Lang::Rust => tree_sitter_rust::LANGUAGE.into(),
+        }
+    }
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Lang::ALL
+            .into_iter()
+            .find(|lang| lang.name() == s)
+            .ok_or_else(|| format!("unknown tree-sitter language: {s}"))
+    }
+}
+
+impl fmt::Display for Lang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Compile a single tree-sitter query for the given language.
+pub fn compile(lang: Lang, pattern: &str) -> Result<Query> {
+    Query::new(&lang.language(), pattern).map_err(|e| anyhow!("{e}"))
+}
+
+/// All structural queries of a query file, compiled and grouped by language.
+pub struct StructuralQueries {
+    by_lang: HashMap<Lang, Vec<(String, Query)>>,
+}
+
+impl StructuralQueries {
+    pub fn compile(queries: &CodeQueries) -> Result<Self> {
+        let mut by_lang: HashMap<Lang, Vec<(String, Query)>> = HashMap::new();
+
+        for query in queries.iter() {
+            if let QueryKind::Structural(lang) = query.kind {
+                let compiled = compile(lang, &query.pattern)
+                    .map_err(|e| anyhow!("Invalid query {}: {e}", query.name))?;
+                by_lang
+                    .entry(lang)
+                    .or_default()
+                    .push((query.name.clone(), compiled));
+            }
+        }
+
+        Ok(Self { by_lang })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_lang.is_empty()
+    }
+}
+
+/// Runs structural queries against files. Each thread needs its own.
+pub struct StructuralSearcher {
+    parser: Parser,
+    cursor: QueryCursor,
+}
+
+impl StructuralSearcher {
+    pub fn new() -> Self {
+        Self {
+            parser: Parser::new(),
+            cursor: QueryCursor::new(),
+        }
+    }
+
+    /// Parse a file and count the matches of every query for the file's language.
+    ///
+    /// Files in languages without any queries are not even read.
+    pub fn search_file(
+        &mut self,
+        queries: &StructuralQueries,
+        path: &Path,
+        counts: &mut HashMap<String, usize>,
+    ) -> Result<()> {
+        let Some(lang) = Lang::from_path(path) else {
+            return Ok(());
+        };
+        let Some(queries) = queries.by_lang.get(&lang) else {
+            return Ok(());
+        };
+
+        let source = std::fs::read(path)?;
+        self.parser.set_language(&lang.language())?;
+        let tree = self
+            .parser
+            .parse(&source, None)
+            .ok_or_else(|| anyhow!("Failed to parse {:?}", path))?;
+
+        for (name, query) in queries {
+            let found = self
+                .cursor
+                .matches(query, tree.root_node(), source.as_slice())
+                .count();
+            if found > 0 {
+                *counts.entry(name.clone()).or_insert(0) += found;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Byte ranges of a file that hold comments and string literals.
+///
+/// Both lists are sorted and non-overlapping.
+#[derive(Debug, Default)]
+pub struct Regions {
+    pub comments: Vec<Range<usize>>,
+    pub strings: Vec<Range<usize>>,
+}
+
+impl Regions {
+    pub fn clear(&mut self) {
+        self.comments.clear();
+        self.strings.clear();
+    }
+}
+
+/// Finds comments and string literals in source files of the built-in languages.
+pub struct RegionFinder {
+    parser: Parser,
+}
+
+impl RegionFinder {
+    pub fn new() -> Self {
+        Self {
+            parser: Parser::new(),
+        }
+    }
+
+    /// Find the comments and string literals of a file, or `None` if the file's language has
+    /// no built-in grammar.
+    pub fn regions(&mut self, path: &Path) -> Result<Option<Regions>> {
+        let Some(lang) = Lang::from_path(path) else {
+            return Ok(None);
+        };
+
+        let source = std::fs::read(path)?;
+        self.parser.set_language(&lang.language())?;
+        let tree = self
+            .parser
+            .parse(&source, None)
+            .ok_or_else(|| anyhow!("Failed to parse {:?}", path))?;
+
+        let mut regions = Regions::default();
+        let mut cursor = tree.walk();
+
+        // pre-order traversal, not descending into comments or strings
+        'outer: loop {
+            let node = cursor.node();
+            let kind = node.kind();
+            if kind.contains("comment") {
+                regions.comments.push(node.byte_range());
+            } else if kind.contains("string") {
+                regions.strings.push(node.byte_range());
+            } else if cursor.goto_first_child() {
+                continue;
+            }
+
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break 'outer;
+                }
+            }
+        }
+
+        Ok(Some(regions))
+    }
+}
+
+/// Whether `offset` lies within one of the sorted, non-overlapping `ranges`.
+pub fn in_ranges(ranges: &[Range<usize>], offset: usize) -> bool {
+    let idx = ranges.partition_point(|r| r.end <= offset);
+    ranges.get(idx).is_some_and(|r| r.contains(&offset))
+}
@@ -0,0 +1,76 @@
+use crate::duration::HumanDuration;
+use crate::git;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Whether to check out the submodules of repos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SubmoduleMode {
+    #[default]
+    None,
+    /// Only their latest commits.
+    Shallow,
+    /// With their full histories.
+    Full,
+}
+
+impl FromStr for SubmoduleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(SubmoduleMode::None),
+            "shallow" => Ok(SubmoduleMode::Shallow),
+            "full" => Ok(SubmoduleMode::Full),
+            _ => Err(format!("unknown submodule mode: {s}")),
+        }
+    }
+}
+
+/// Check out the submodules of the clone in `path`, recursively, at the commits its
+/// checkout refers to.
+pub async fn update(
+    path: &Path,
+    mode: SubmoduleMode,
+    timeout: Option<HumanDuration>,
+) -> Result<()> {
+    if mode == SubmoduleMode::None {
+        return Ok(());
+    }
+
+    let mut command = git::command();
+    command.arg("-C").arg(path.as_os_str()).args([
+        "submodule",
+        "update",
+        "--init",
+        "--recursive",
+        "--force",
+    ]);
+    if mode == SubmoduleMode::Shallow {
+        command.arg("--depth").arg("1");
+    }
+
+    let output = command.kill_on_drop(true).output();
+    let output = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout.duration(), output)
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "Updating the submodules of {:?} took longer than {timeout}",
+                    path
+                )
+            })??,
+        None => output.await?,
+    };
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Failed to update the submodules of {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
@@ -0,0 +1,350 @@
+use crate::bandwidth;
+use crate::forge::{Repo, RepoMeta};
+use crate::registry::USER_AGENT;
+use crate::RunCmd;
+use anyhow::{anyhow, Context, Result};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use url::Url;
+
+const API_URL: &str = "https://archive.softwareheritage.org/api/1/";
+
+/// Origins resolved per page, since every one takes several requests.
+const PAGE_SIZE: usize = 10;
+
+/// How long to wait between asking whether an archive is ready.
+const VAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize)]
+struct Visit {
+    snapshot: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Snapshot {
+    branches: HashMap<String, Option<Branch>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Branch {
+    target: String,
+    /// "alias", "revision", "release", ...
+    target_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    target: String,
+    target_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Revision {
+    directory: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Cooking {
+    /// "new", "pending", "done", or "failed"
+    status: String,
+    fetch_url: Option<Url>,
+    progress_message: Option<String>,
+}
+
+/// A client for the Software Heritage API, authenticated with `SWH_TOKEN` if it is set.
+#[derive(Debug, Clone)]
+pub struct SwhClient {
+    client: reqwest::Client,
+    token: Option<String>,
+}
+
+impl SwhClient {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().user_agent(USER_AGENT).build()?,
+            token: std::env::var("SWH_TOKEN").ok(),
+        })
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Send a request, waiting out the rate limit if it's exhausted.
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        loop {
+            let attempt = request
+                .try_clone()
+                .ok_or_else(|| anyhow!("Can't retry request"))?;
+            let response = self.authorize(attempt).send().await?;
+
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                let reset = response
+                    .headers()
+                    .get("x-ratelimit-reset")
+                    .and_then(|reset| reset.to_str().ok()?.parse::<i64>().ok())
+                    .ok_or_else(|| anyhow!("Software Heritage rate limit exhausted"))?;
+                let delta = (reset - chrono::Utc::now().timestamp()).max(1) as u64;
+                log::warn!("Software Heritage rate limit exhausted, sleeping for {delta}s");
+                tokio::time::sleep(Duration::from_secs(delta)).await;
+                continue;
+            }
+
+            return Ok(response.error_for_status()?.json().await?);
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.send(self.client.get(format!("{API_URL}{path}"))).await
+    }
+
+    /// The root directory of the latest archived snapshot of an origin.
+    async fn origin_directory(&self, origin: &str) -> Result<String> {
+        let visit: Visit = self
+            .get(&format!(
+                "origin/{origin}/visit/latest/?require_snapshot=true"
+            ))
+            .await?;
+        let snapshot = visit
+            .snapshot
+            .ok_or_else(|| anyhow!("No archived snapshot of {origin}"))?;
+        self.snapshot_directory(&snapshot).await
+    }
+
+    /// The root directory of the HEAD branch of a snapshot.
+    async fn snapshot_directory(&self, snapshot: &str) -> Result<String> {
+        let mut branch = "HEAD".to_owned();
+        // HEAD is usually an alias of another branch, which may be an alias too
+        for _ in 0..5 {
+            let page: Snapshot = self
+                .get(&format!(
+                    "snapshot/{snapshot}/?branches_from={branch}&branches_count=1"
+                ))
+                .await?;
+            let target = page
+                .branches
+                .get(&branch)
+                .and_then(Option::as_ref)
+                .ok_or_else(|| anyhow!("Snapshot {snapshot} has no branch {branch}"))?;
+
+            match target.target_type.as_str() {
+                "alias" => branch = target.target.clone(),
+                "revision" => return self.revision_directory(&target.target).await,
+                "release" => return self.release_directory(&target.target).await,
+                other => return Err(anyhow!("Branch {branch} points to a {other}")),
+            }
+        }
+        Err(anyhow!("Too many aliases in snapshot {snapshot}"))
+    }
+
+    async fn release_directory(&self, release: &str) -> Result<String> {
+        let release: Release = self.get(&format!("release/{release}/")).await?;
+        match release.target_type.as_str() {
+            "revision" => self.revision_directory(&release.target).await,
+            "directory" => Ok(release.target),
+            other => Err(anyhow!("Release points to a {other}")),
+        }
+    }
+
+    async fn revision_directory(&self, revision: &str) -> Result<String> {
+        let revision: Revision = self.get(&format!("revision/{revision}/")).await?;
+        Ok(revision.directory)
+    }
+
+    /// The root directory of an origin URL or a SWHID.
+    async fn directory(&self, origin: &str) -> Result<String> {
+        let Some(swhid) = origin.strip_prefix("swh:1:") else {
+            return self.origin_directory(origin).await;
+        };
+        // qualifiers, like ";origin=...", don't change what is identified
+        let swhid = swhid.split(';').next().unwrap_or(swhid);
+        let (kind, id) = swhid
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid SWHID: {origin}"))?;
+
+        match kind {
+            "dir" => Ok(id.to_owned()),
+            "rev" => self.revision_directory(id).await,
+            "rel" => self.release_directory(id).await,
+            "snp" => self.snapshot_directory(id).await,
+            _ => Err(anyhow!("Unsupported SWHID: {origin}")),
+        }
+    }
+
+    /// Download the contents of a directory into `path`. Software Heritage has to prepare
+    /// an archive of it first, which can take minutes for large directories.
+    pub async fn download(&self, directory: &str, path: &Path) -> Result<()> {
+        let cook_url = format!("{API_URL}vault/flat/swh:1:dir:{directory}/");
+        let mut cooking: Cooking = self.send(self.client.post(&cook_url)).await?;
+
+        let fetch_url = loop {
+            match cooking.status.as_str() {
+                "done" => {
+                    break cooking
+                        .fetch_url
+                        .ok_or_else(|| anyhow!("No archive of directory {directory}"))?;
+                }
+                "failed" => {
+                    return Err(anyhow!(
+                        "Software Heritage failed to archive directory {directory}: {}",
+                        cooking.progress_message.unwrap_or_default()
+                    ));
+                }
+                _ => {
+                    log::debug!("Waiting for an archive of directory {directory}");
+                    tokio::time::sleep(VAULT_POLL_INTERVAL).await;
+                    cooking = self.send(self.client.get(&cook_url)).await?;
+                }
+            }
+        };
+
+        let mut response = self
+            .authorize(self.client.get(fetch_url))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // appended rather than replacing an extension, since names like socket.io have dots
+        let mut archive_path = path.as_os_str().to_owned();
+        archive_path.push(".tar.gz");
+        let archive_path = PathBuf::from(archive_path);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let downloaded = async {
+            let mut archive = tokio::fs::File::create(&archive_path).await?;
+            // read no faster than --max-bandwidth allows, which slows down the sender, too
+            while let Some(chunk) = response.chunk().await? {
+                if let Some(bandwidth) = bandwidth::limit() {
+                    bandwidth.take(chunk.len()).await;
+                }
+                archive.write_all(&chunk).await?;
+            }
+            archive.flush().await?;
+            anyhow::Ok(())
+        };
+        if let Err(e) = downloaded.await {
+            let _ = tokio::fs::remove_file(&archive_path).await;
+            return Err(e);
+        }
+        // unpacked next to `path` and moved there once complete, so that a run that's
+        // interrupted doesn't leave a partial directory that the next run takes for downloaded
+        let mut unpack_path = path.as_os_str().to_owned();
+        unpack_path.push(".unpacking");
+        let unpack_path = PathBuf::from(unpack_path);
+        let _ = tokio::fs::remove_dir_all(&unpack_path).await;
+        tokio::fs::create_dir_all(&unpack_path).await?;
+
+        // the archive contains a single directory named after the SWHID
+        let output = Command::new("tar")
+            .arg("-xzf")
+            .arg(&archive_path)
+            .arg("-C")
+            .arg(&unpack_path)
+            .arg("--strip-components=1")
+            .kill_on_drop(true)
+            .output()
+            .await;
+        tokio::fs::remove_file(&archive_path).await?;
+
+        let unpacked = match output {
+            Ok(output) if output.status.success() => tokio::fs::rename(&unpack_path, path)
+                .await
+                .map_err(Into::into),
+            Ok(output) => Err(anyhow!(
+                "Failed to unpack directory {directory}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) => Err(e.into()),
+        };
+        if unpacked.is_err() {
+            let _ = tokio::fs::remove_dir_all(&unpack_path).await;
+        }
+        unpacked
+    }
+}
+
+/// Repos archived by Software Heritage, given by origin URLs or SWHIDs.
+pub struct SwhOrigins {
+    client: SwhClient,
+    /// Origins left to resolve, taken from the back.
+    origins: Vec<String>,
+}
+
+impl SwhOrigins {
+    pub fn from_argh(argh: &RunCmd) -> Result<Self> {
+        let Some(path) = &argh.from_swh else {
+            return Err(anyhow!("No Software Heritage origins given"));
+        };
+        if argh.history.is_some() {
+            return Err(anyhow!("--history can't be combined with --from-swh"));
+        }
+
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+        let mut origins: Vec<String> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(ToOwned::to_owned)
+            .collect();
+        log::info!("Found {} origins in {:?}", origins.len(), path);
+        origins.reverse();
+
+        Ok(Self {
+            client: SwhClient::new()?,
+            origins,
+        })
+    }
+
+    /// The repo for an origin, named after its URL, or "swh/<id>" for SWHIDs.
+    async fn resolve(&self, origin: &str) -> Result<Repo> {
+        let directory = self.client.directory(origin).await?;
+
+        let mut repo = match origin.starts_with("swh:") {
+            true => {
+                let id = origin.split(';').next().unwrap_or(origin);
+                let id = id.rsplit(':').next().unwrap_or(id);
+                Repo {
+                    owner: "swh".to_owned(),
+                    name: id.to_owned(),
+                    clone_url: Url::parse(&format!(
+                        "https://archive.softwareheritage.org/{origin}"
+                    ))?,
+                    swh_directory: None,
+                    meta: RepoMeta::default(),
+                }
+            }
+            false => Repo::from_url(origin)?,
+        };
+        repo.swh_directory = Some(directory);
+        Ok(repo)
+    }
+
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Repo>>> {
+        if self.origins.is_empty() {
+            return Ok(None);
+        }
+        let page = self
+            .origins
+            .split_off(self.origins.len().saturating_sub(PAGE_SIZE));
+
+        let mut repos = Vec::new();
+        for origin in page.into_iter().rev() {
+            match self.resolve(&origin).await {
+                Ok(repo) => repos.push(repo),
+                Err(e) => log::warn!("Skipping {origin}: {e}"),
+            }
+        }
+        Ok(Some(repos))
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The variable that the GitHub token is taken from, by the GitHub client and by git's
+/// credential helper alike.
+const VAR: &str = "GITHUB_TOKEN";
+
+/// The service and account that the token is kept under in the OS keyring.
+const KEYRING_SERVICE: &str = "octosurfer";
+const KEYRING_ACCOUNT: &str = "github.com";
+
+/// The output of a command that prints a token, if it ran and printed one.
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let token = String::from_utf8(output.stdout).ok()?.trim().to_owned();
+    (output.status.success() && !token.is_empty()).then_some(token)
+}
+
+/// The token of the gh CLI, if it's installed and logged in to github.com.
+fn gh() -> Option<String> {
+    output("gh", &["auth", "token", "--hostname", "github.com"])
+}
+
+/// The token kept in the OS keyring, if any: with `secret-tool` on Linux, or `security` on
+/// macOS.
+fn keyring() -> Option<String> {
+    match std::env::consts::OS {
+        "macos" => output(
+            "security",
+            &[
+                "find-generic-password",
+                "-s",
+                KEYRING_SERVICE,
+                "-a",
+                KEYRING_ACCOUNT,
+                "-w",
+            ],
+        ),
+        _ => output(
+            "secret-tool",
+            &[
+                "lookup",
+                "service",
+                KEYRING_SERVICE,
+                "account",
+                KEYRING_ACCOUNT,
+            ],
+        ),
+    }
+}
+
+/// Store `token` in the OS keyring, or if there is none, in a file in the config directory
+/// that only the user can read, and tell where.
+pub fn store(token: &str) -> Result<String> {
+    let stored = match std::env::consts::OS {
+        // `security` only takes the password as an argument, which other users see in the
+        // process list, so the whole command is given to its interactive mode on stdin
+        "macos" => {
+            let command = format!(
+                "add-generic-password -U -s {KEYRING_SERVICE} -a {KEYRING_ACCOUNT} -w \"{token}\"\n"
+            );
+            run_with_input(Command::new("security").arg("-i"), &command)
+        }
+        _ => run_with_input(
+            Command::new("secret-tool")
+                .args(["store", "--label", "octosurfer GitHub token"])
+                .args(["service", KEYRING_SERVICE, "account", KEYRING_ACCOUNT]),
+            token,
+        ),
+    };
+    if stored {
+        return Ok("the keyring".to_owned());
+    }
+
+    let path = stored_file().ok_or_else(|| anyhow!("No keyring, and no home directory"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("Failed to store the token in {path:?}"))?;
+    // the mode only applies to new files, not to one that's there already
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(token.as_bytes())?;
+    Ok(format!("{path:?}"))
+}
+
+/// Run `command` with `input` on its stdin, and tell whether it succeeded.
+fn run_with_input(command: &mut Command, input: &str) -> bool {
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(input.as_bytes())?;
+            }
+            child.wait()
+        })
+        .is_ok_and(|status| status.success())
+}
+
+/// The file the token is stored in if there is no keyring.
+fn stored_file() -> Option<PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config) => PathBuf::from(config),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("octosurfer").join("github-token"))
+}
+
+/// The token stored in a file by [`store`], if any.
+fn stored() -> Option<String> {
+    let token = std::fs::read_to_string(stored_file()?).ok()?;
+    Some(token.trim().to_owned()).filter(|token| !token.is_empty())
+}
+
+/// Find the GitHub token, and set GITHUB_TOKEN to it for everything else: the one in
+/// `token_file`, or else the one GITHUB_TOKEN is set to already, or else that of the gh CLI,
+/// or else the one in the OS keyring or stored by `login`. Needs to be called first thing, like
+/// [`proxy::configure`](crate::proxy::configure).
+pub fn configure(token_file: Option<&Path>) -> Result<()> {
+    let token = if let Some(path) = token_file {
+        let token = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the token file {path:?}"))?;
+        let token = token.trim().to_owned();
+        if token.is_empty() {
+            return Err(anyhow!("The token file {path:?} is empty"));
+        }
+        token
+    } else if std::env::var(VAR).is_ok_and(|token| !token.is_empty()) {
+        return Ok(());
+    } else if let Some(token) = gh().or_else(keyring).or_else(stored) {
+        token
+    } else {
+        return Ok(());
+    };
+    std::env::set_var(VAR, token);
+    Ok(())
+}
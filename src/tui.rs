@@ -0,0 +1,317 @@
+use crate::code_queries::QueryResults;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::cursor::{Hide, Show};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, List, ListItem, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Stderr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Log lines kept for the log pane.
+const LOG_LINES: usize = 200;
+
+/// How often the dashboard is redrawn.
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// The rate limits GitHub reported last, by resource.
+static RATE_LIMITS: Mutex<BTreeMap<String, RateLimit>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone)]
+struct RateLimit {
+    remaining: usize,
+    limit: usize,
+    reset: Option<DateTime<Utc>>,
+}
+
+/// Remember the rate limit of a resource, like "search", to show it on the dashboard.
+pub fn record_rate_limit(resource: &str, remaining: usize, limit: usize, reset: u64) {
+    let reset = DateTime::from_timestamp(reset as i64, 0);
+    let rate = RateLimit {
+        remaining,
+        limit,
+        reset,
+    };
+    if let Ok(mut limits) = RATE_LIMITS.lock() {
+        limits.insert(resource.to_owned(), rate);
+    }
+}
+
+/// What a repo is doing.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum RepoState {
+    /// In a phase like "cloning", and how far along it is, if known.
+    Active(String, Option<u64>),
+    Failed,
+    Queued,
+    Done,
+}
+
+#[derive(Debug, Default)]
+struct BoardState {
+    /// Whether the dashboard is drawn, so logs go to its log pane rather than stderr.
+    drawn: bool,
+    repos: Vec<(String, RepoState)>,
+    /// The number of repos with matches, and the number of matches, by query.
+    matches: BTreeMap<String, (u64, u64)>,
+    log: VecDeque<String>,
+}
+
+/// The live state of a run, as shown on the dashboard.
+#[derive(Debug, Clone, Default)]
+pub struct Board(Arc<Mutex<BoardState>>);
+
+impl Board {
+    fn update(&self, f: impl FnOnce(&mut BoardState)) {
+        // a panic while drawing shouldn't stop the run
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut state)
+    }
+
+    /// Forget the repos and matches of the previous run.
+    pub fn restart(&self) {
+        self.update(|state| {
+            state.repos.clear();
+            state.matches.clear();
+        });
+    }
+
+    /// Add a queued repo, and return its index.
+    pub fn add_repo(&self, full_name: String) -> usize {
+        let mut idx = 0;
+        self.update(|state| {
+            idx = state.repos.len();
+            state.repos.push((full_name, RepoState::Queued));
+        });
+        idx
+    }
+
+    pub fn set_phase(&self, idx: usize, phase: &str, percent: Option<u64>) {
+        self.update(|state| state.repos[idx].1 = RepoState::Active(phase.to_owned(), percent));
+    }
+
+    /// Mark a repo as done, counting its matches, or as failed.
+    pub fn finish(&self, idx: usize, results: &Result<Vec<QueryResults>>) {
+        self.update(|state| {
+            let Ok(results) = results else {
+                state.repos[idx].1 = RepoState::Failed;
+                return;
+            };
+            state.repos[idx].1 = RepoState::Done;
+            for results in results {
+                for (query, count) in results.inner.iter() {
+                    let (repos, total) = state.matches.entry(query.clone()).or_default();
+                    *repos += u64::from(*count > 0);
+                    *total += *count as u64;
+                }
+            }
+        });
+    }
+
+    fn log(&self, line: String) {
+        self.update(|state| {
+            if !state.drawn {
+                eprintln!("{line}");
+                return;
+            }
+            if state.log.len() == LOG_LINES {
+                state.log.pop_front();
+            }
+            state.log.push_back(line);
+        });
+    }
+}
+
+/// Logs into the dashboard's log pane while it's drawn, instead of drawing over it.
+pub struct BoardLogger {
+    board: Board,
+}
+
+impl BoardLogger {
+    pub fn new(board: Board) -> Self {
+        Self { board }
+    }
+}
+
+impl log::Log for BoardLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            let line = format!(
+                "{:<5} [{}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            self.board.log(line);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn draw(frame: &mut Frame, state: &BoardState) {
+    let [top, middle, bottom] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Fill(1),
+        Constraint::Length(10),
+    ])
+    .areas(frame.area());
+    let [repos_area, side] =
+        Layout::horizontal([Constraint::Fill(1), Constraint::Length(50)]).areas(middle);
+    let [matches_area, limits_area] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(6)]).areas(side);
+
+    let total = state.repos.len();
+    let count = |wanted: &RepoState| state.repos.iter().filter(|(_, s)| s == wanted).count();
+    let (done, failed) = (count(&RepoState::Done), count(&RepoState::Failed));
+    let ratio = match total {
+        0 => 0.0,
+        total => (done + failed) as f64 / total as f64,
+    };
+    let gauge = Gauge::default()
+        .block(Block::bordered().title(" octosurfer "))
+        .gauge_style(Style::default().fg(Color::Cyan))
+        .ratio(ratio)
+        .label(format!(
+            "{done} done, {failed} failed, {} to go, of {total} repos",
+            total - done - failed
+        ));
+    frame.render_widget(gauge, top);
+
+    // the repos in progress first, then those that failed
+    let mut repos: Vec<&(String, RepoState)> = state.repos.iter().collect();
+    repos.sort_by(|a, b| a.1.cmp(&b.1));
+    let items: Vec<ListItem> = repos
+        .into_iter()
+        .take(repos_area.height as usize)
+        .map(|(name, repo_state)| {
+            let (status, color) = match repo_state {
+                RepoState::Active(phase, Some(percent)) => {
+                    (format!("{phase} {percent}%"), Color::Yellow)
+                }
+                RepoState::Active(phase, None) => (phase.clone(), Color::Yellow),
+                RepoState::Failed => ("failed".to_owned(), Color::Red),
+                RepoState::Queued => ("queued".to_owned(), Color::Gray),
+                RepoState::Done => ("done".to_owned(), Color::Green),
+            };
+            ListItem::new(format!("{status:<28} {name}")).style(Style::default().fg(color))
+        })
+        .collect();
+    frame.render_widget(
+        List::new(items).block(Block::bordered().title(" Repos ")),
+        repos_area,
+    );
+
+    let rows: Vec<Row> = state
+        .matches
+        .iter()
+        .map(|(query, (repos, total))| {
+            Row::new([query.clone(), repos.to_string(), total.to_string()])
+        })
+        .collect();
+    let widths = [
+        Constraint::Fill(1),
+        Constraint::Length(8),
+        Constraint::Length(10),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(["query", "repos", "matches"]).style(Style::default().fg(Color::Cyan)))
+        .block(Block::bordered().title(" Matches "));
+    frame.render_widget(table, matches_area);
+
+    let limits = RATE_LIMITS
+        .lock()
+        .map(|limits| limits.clone())
+        .unwrap_or_default();
+    let lines: Vec<Line> = limits
+        .iter()
+        .map(|(resource, rate)| {
+            let reset = rate
+                .reset
+                .map(|reset| format!(", resets {}", reset.format("%H:%M:%S")))
+                .unwrap_or_default();
+            Line::from(format!(
+                "{resource}: {}/{}{reset}",
+                rate.remaining, rate.limit
+            ))
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" GitHub rate limits ")),
+        limits_area,
+    );
+
+    let height = bottom.height.saturating_sub(2) as usize;
+    let log: Vec<Line> = state
+        .log
+        .iter()
+        .skip(state.log.len().saturating_sub(height))
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(log).block(Block::bordered().title(" Log ")),
+        bottom,
+    );
+}
+
+/// The dashboard, drawn on stderr's alternate screen until it's stopped.
+pub struct Dashboard {
+    board: Board,
+    stop: watch::Sender<bool>,
+    task: JoinHandle<Result<()>>,
+}
+
+impl Dashboard {
+    pub fn start(board: Board) -> Result<Self> {
+        let mut stderr = std::io::stderr();
+        // without raw mode, so that Ctrl-C still interrupts the run
+        execute!(stderr, EnterAlternateScreen, Hide)?;
+        let terminal = Terminal::new(CrosstermBackend::new(stderr))?;
+
+        board.update(|state| state.drawn = true);
+        let (stop, stopped) = watch::channel(false);
+        let task = tokio::spawn(redraw(terminal, board.clone(), stopped));
+        Ok(Self { board, stop, task })
+    }
+
+    /// Stop drawing, restore the terminal, and print the log kept meanwhile.
+    pub async fn stop(self) -> Result<()> {
+        self.stop.send_replace(true);
+        let drawn = self.task.await?;
+        execute!(std::io::stderr(), LeaveAlternateScreen, Show)?;
+        self.board.update(|state| {
+            state.drawn = false;
+            for line in state.log.drain(..) {
+                eprintln!("{line}");
+            }
+        });
+        drawn
+    }
+}
+
+async fn redraw(
+    mut terminal: Terminal<CrosstermBackend<Stderr>>,
+    board: Board,
+    mut stopped: watch::Receiver<bool>,
+) -> Result<()> {
+    loop {
+        terminal.draw(|frame| board.update(|state| draw(frame, state)))?;
+        tokio::select! {
+            _ = tokio::time::sleep(REFRESH) => {}
+            _ = stopped.changed() => return Ok(()),
+        }
+    }
+}